@@ -4,45 +4,87 @@
 
 //! Various functions to work with buttplug devices
 
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use buttplug::client::ButtplugClientDevice;
-use buttplug::core::message::{ButtplugDeviceMessageType, ClientGenericDeviceMessageAttributesV3};
+use buttplug::client::{ButtplugClientDevice, RotateCommand, ScalarCommand};
+use buttplug::core::message::{ActuatorType as ButtplugActuatorType, ButtplugDeviceMessageType, ClientGenericDeviceMessageAttributesV3};
 use buttplug::server::device::ServerDeviceManager;
+use chrono::Utc;
+use tokio::time;
+use tracing::{debug, info, warn};
 
 use crate::app::buttplug::structs::DeviceList;
-use crate::app::structs::{ApplicationState, ApplicationStateDb, ApplicationStatus, DeviceStatus};
-use crate::config::v3::{ActuatorType, MotorConfigurationV3, MotorTypeV3};
+use crate::app::structs::{ApplicationState, ApplicationStateDb, ApplicationStatus, BatteryHistoryDb, DeviceScaleDb, DeviceStatus, GroupScaleDb, LatencyReport, SentCommandDbs, SharedFeatures};
+use crate::config::v3::{ActuatorType, BatteryScale, DeviceOrder, MotorConfigurationV3, MotorTypeV3};
 use crate::gui::TaggedMotor;
 
-pub async fn get_tagged_devices(application_state_db: &ApplicationStateDb) -> Option<ApplicationStatus> {
+/// how long to wait for devices to stop when shutting down
+const STOP_ALL_DEVICES_TIMEOUT: Duration = Duration::from_secs(2);
+/// how many commands a latency test sends. Each on/off pair briefly runs the motor at its lowest level.
+const LATENCY_TEST_COMMANDS: usize = 10;
+/// pause between latency test commands, so they don't pile up in the device's queue
+const LATENCY_TEST_INTERVAL: Duration = Duration::from_millis(100);
+/// how often the same untagged devices are warned about again
+const UNTAGGED_DEVICE_WARNING_INTERVAL: Duration = Duration::from_secs(600);
+
+/// the untagged devices last warned about, and when
+static LAST_UNTAGGED_DEVICE_WARNING: Mutex<Option<(BTreeSet<String>, Instant)>> = Mutex::new(None);
+
+/// Battery levels read along the way are recorded in `battery_history_db`, if battery history is enabled
+pub async fn get_tagged_devices(application_state_db: &ApplicationStateDb, device_scale_db: &DeviceScaleDb, group_scale_db: &GroupScaleDb, battery_history_db: &BatteryHistoryDb) -> Option<ApplicationStatus> {
     let application_state_mutex = application_state_db.read().await;
     match application_state_mutex.as_ref() {
         Some(application_state) => {
-            let DeviceList { motors, mut devices } = get_devices(application_state).await;
+            let device_scales = device_scale_db.read().await.clone();
+            let DeviceList { motors, mut devices } = get_devices(application_state, &device_scales, battery_history_db).await;
             let configuration = &application_state.configuration;
-            let tags = &configuration.tags;
+            let tags = configuration.tags();
 
             // convert tags to TaggedMotor
             let mut tagged_motors = motors_to_tagged(tags);
 
             // for each device not yet in TaggedMotor, generate a new dummy TaggedMotor
-            let mut missing_motors: Vec<TaggedMotor> = motors.into_iter()
-                .filter(|motor| !tagged_motors.iter().any(|possible_match| &possible_match.motor == motor))
+            let (missing_motors, connected_tagged_motors): (Vec<MotorConfigurationV3>, Vec<MotorConfigurationV3>) = motors.into_iter()
+                .partition(|motor| !tagged_motors.iter().any(|possible_match| possible_match.motor.is_same_motor(motor)));
+            // motors that can't be controlled aren't offered for tagging. Existing tags for them are kept, but ignored.
+            let missing_motors: Vec<MotorConfigurationV3> = missing_motors.into_iter()
+                .filter(|motor| configuration.is_motor_type_allowed(&motor.feature_type))
+                .collect();
+            if configuration.warn_untagged_devices {
+                warn_untagged_devices(&missing_motors, &connected_tagged_motors);
+            }
+            let mut missing_motors: Vec<TaggedMotor> = missing_motors.into_iter()
                 .map(|missing_motor| TaggedMotor::new(missing_motor, None))
                 .collect();
 
             // merge results
             tagged_motors.append(&mut missing_motors);
 
+            for tagged_motor in tagged_motors.iter_mut() {
+                let motor = &tagged_motor.motor;
+                tagged_motor.step_count = device_for_motor(application_state, motor)
+                    .and_then(|device| feature_attributes(&device, &motor.feature_type, motor.feature_index).map(|attributes| *attributes.step_count()));
+            }
+
             // sort the things
             tagged_motors.sort_unstable();
-            devices.sort_unstable();
+            match configuration.device_order {
+                DeviceOrder::Name => devices.sort_unstable(),
+                DeviceOrder::ConnectionTime => (), // get_devices already lists devices in the order they connected
+                DeviceOrder::Pinned => {
+                    let pin_position = |device: &DeviceStatus| configuration.pinned_devices.iter().position(|name| *name == device.name).unwrap_or(usize::MAX);
+                    devices.sort_by(|a, b| pin_position(a).cmp(&pin_position(b)).then_with(|| a.cmp(b)));
+                }
+            }
 
             Some(ApplicationStatus {
                 motors: tagged_motors,
                 devices,
+                devices_over_limit: application_state.devices_over_limit(),
+                group_scales: group_scale_db.read().await.clone(),
+                scanning: application_state.scanning,
                 configuration: configuration.clone(),
             })
         }
@@ -50,18 +92,75 @@ pub async fn get_tagged_devices(application_state_db: &ApplicationStateDb) -> Op
     }
 }
 
+/// Warn about connected devices that don't have a single tagged motor, as commands can't reach them.
+/// The same devices are only warned about again once [`UNTAGGED_DEVICE_WARNING_INTERVAL`] has passed.
+fn warn_untagged_devices(missing_motors: &[MotorConfigurationV3], connected_tagged_motors: &[MotorConfigurationV3]) {
+    let untagged_devices: BTreeSet<String> = missing_motors.iter()
+        .filter(|missing_motor| !connected_tagged_motors.iter().any(|motor| motor.device_name == missing_motor.device_name))
+        .map(|missing_motor| missing_motor.device_name.clone())
+        .collect();
+    let mut last_warning = LAST_UNTAGGED_DEVICE_WARNING.lock().unwrap_or_else(|e| e.into_inner());
+    let recently_warned = last_warning.as_ref()
+        .is_some_and(|(devices, time)| *devices == untagged_devices && time.elapsed() < UNTAGGED_DEVICE_WARNING_INTERVAL);
+    if untagged_devices.is_empty() || recently_warned {
+        return;
+    }
+    let device_names: Vec<&str> = untagged_devices.iter().map(String::as_str).collect();
+    warn!("connected devices without any tagged motors, which commands can't reach: {}", device_names.join(", "));
+    *last_warning = Some((untagged_devices, Instant::now()));
+}
+
 fn motors_to_tagged(tags: &HashMap<String, MotorConfigurationV3>) -> Vec<TaggedMotor> {
     tags.iter()
         .map(|(tag, motor)| TaggedMotor::new(motor.clone(), Some(tag.clone())))
         .collect()
 }
 
-/// Get display name for device.
-#[inline(always)]
-fn display_name_from_device(device: &ButtplugClientDevice) -> String {
-    device.name().clone()
-    // once we want to handle duplicate devices:
-    //format!("{}#{}", device.name(), device.index())
+/// Get display name for device, after applying the configured device renames. This is the name tags are configured with.
+/// If [`number_duplicate_devices`](crate::config::v3::ConfigurationV3::number_duplicate_devices) is set, the number the device
+/// was given by [`number_devices`] is appended as a `#1`, `#2`, etc. suffix.
+pub fn display_name_from_device(device: &ButtplugClientDevice, application_state: &ApplicationState) -> String {
+    let name = application_state.configuration.device_display_name(device.name());
+    if !application_state.configuration.number_duplicate_devices {
+        return name;
+    }
+    let number = id_from_device(device, &application_state.device_manager)
+        .and_then(|identifier| application_state.device_numbering.lock().unwrap_or_else(|e| e.into_inner()).device_numbers.get(&name, &identifier));
+    match number {
+        Some(number) => format!("{name}#{number}"),
+        None => name,
+    }
+}
+
+/// Number every used device that doesn't have a number for its display name yet, if
+/// [`number_duplicate_devices`](crate::config::v3::ConfigurationV3::number_duplicate_devices) is set. A device gets the number
+/// its configured tags use if it's free, so devices keep their numbers between launches. Devices without an identifier
+/// are left out, as there's no telling them apart.
+pub fn number_devices(application_state: &mut ApplicationState) {
+    if !application_state.configuration.number_duplicate_devices {
+        return;
+    }
+    let mut device_numbering = application_state.device_numbering.lock().unwrap_or_else(|e| e.into_inner());
+    for device in application_state.devices() {
+        let Some(identifier) = id_from_device(&device, &application_state.device_manager) else { continue };
+        let name = application_state.configuration.device_display_name(device.name());
+        let configured_number = application_state.configuration.tags().values()
+            .filter(|motor| motor.device_identifier.as_deref() == Some(identifier.as_str()))
+            .find_map(|motor| motor.device_name.strip_prefix(name.as_str())?.strip_prefix('#')?.parse().ok());
+        device_numbering.device_numbers.assign(&name, &identifier, configured_number);
+    }
+}
+
+/// Identifiers of the used devices whose display name before numbering is `name`, sorted. Devices without an
+/// identifier are left out, as there's no telling them apart. Duplicate devices are numbered in this order.
+pub fn identifiers_with_display_name(application_state: &ApplicationState, name: &str) -> Vec<String> {
+    let mut identifiers: Vec<String> = application_state.devices().iter()
+        .filter(|device| application_state.configuration.device_display_name(device.name()) == name)
+        .filter_map(|device| id_from_device(device, &application_state.device_manager))
+        .collect();
+    identifiers.sort_unstable();
+    identifiers.dedup();
+    identifiers
 }
 
 /// Get unique identifier for a device. This should ALWAYS be the same for a given device.
@@ -77,9 +176,37 @@ pub fn id_from_device(device: &ButtplugClientDevice, device_manager: &ServerDevi
     )
 }
 
+/// How many scalar, rotation, and linear motors a device has. These are the only motors buttplug-lite can control.
+pub fn motor_count(device: &ButtplugClientDevice) -> usize {
+    let attributes = device.message_attributes();
+    attributes.scalar_cmd().as_ref().map_or(0, |v| v.len())
+        + attributes.rotate_cmd().as_ref().map_or(0, |v| v.len())
+        + attributes.linear_cmd().as_ref().map_or(0, |v| v.len())
+}
+
+/// The attributes a device reports for one of its motors, if it has that motor
+pub fn feature_attributes<'a>(device: &'a ButtplugClientDevice, feature_type: &MotorTypeV3, feature_index: u32) -> Option<&'a ClientGenericDeviceMessageAttributesV3> {
+    let attributes = device.message_attributes();
+    let features = match feature_type {
+        MotorTypeV3::Scalar { .. } => attributes.scalar_cmd(),
+        MotorTypeV3::Rotation => attributes.rotate_cmd(),
+        MotorTypeV3::Linear => attributes.linear_cmd(),
+    };
+    features.as_ref()?.get(feature_index as usize)
+}
+
+/// The connected device a configured motor belongs to, if any
+pub fn device_for_motor(application_state: &ApplicationState, motor: &MotorConfigurationV3) -> Option<Arc<ButtplugClientDevice>> {
+    application_state.devices().into_iter()
+        .find(|device| {
+            display_name_from_device(device, application_state) == motor.device_name
+                && (motor.device_identifier.is_none() || id_from_device(device, &application_state.device_manager) == motor.device_identifier) // motors saved by older versions may not have an identifier
+        })
+}
+
 /// Get a full debug name for a device. This is intended for logging.
 pub fn debug_name_from_device(device: &ButtplugClientDevice, device_manager: &ServerDeviceManager) -> String {
-    let name = display_name_from_device(device);
+    let name = device.name().clone();
     match id_from_device(device, device_manager) {
         Some(id) => format!("{name}@{id}"),
         None => name,
@@ -87,7 +214,8 @@ pub fn debug_name_from_device(device: &ButtplugClientDevice, device_manager: &Se
 }
 
 /// get all distinct motors
-fn motor_configuration_from_devices(devices: Vec<Arc<ButtplugClientDevice>>, device_manager: &ServerDeviceManager) -> Vec<MotorConfigurationV3> {
+fn motor_configuration_from_devices(devices: Vec<Arc<ButtplugClientDevice>>, application_state: &ApplicationState) -> Vec<MotorConfigurationV3> {
+    let configuration = &application_state.configuration;
     let mut motor_configuration_count: usize = 0;
     for device in devices.iter() {
         motor_configuration_count += device.message_attributes().scalar_cmd().as_ref().map_or(0, |v| v.len());
@@ -103,12 +231,19 @@ fn motor_configuration_from_devices(devices: Vec<Arc<ButtplugClientDevice>>, dev
         let scalar_cmds: &Vec<ClientGenericDeviceMessageAttributesV3> = device.message_attributes().scalar_cmd().as_ref().unwrap_or(&empty_vec);
         for index in 0..scalar_cmds.len() {
             let message_attributes: &ClientGenericDeviceMessageAttributesV3 = scalar_cmds.get(index).expect("I didn't know a vec could change mid-iteration");
-            let actuator_type: ActuatorType = message_attributes.actuator_type().into();
+            let actuator_type = match (message_attributes.actuator_type().into(), &configuration.unknown_actuator_type) {
+                (ActuatorType::Unknown, Some(fallback)) => fallback.clone(),
+                (actuator_type, _) => actuator_type,
+            };
             let motor_config = MotorConfigurationV3 {
-                device_name: display_name_from_device(&device),
-                device_identifier: id_from_device(&device, device_manager),
+                device_name: display_name_from_device(&device, application_state),
+                device_identifier: id_from_device(&device, &application_state.device_manager),
                 feature_type: MotorTypeV3::Scalar { actuator_type },
                 feature_index: index as u32,
+                enabled: true,
+                min_on_time_ms: None,
+                watchdog_exempt: false,
+                response_curve: None,
             };
             motor_configurations.push(motor_config);
         }
@@ -116,10 +251,14 @@ fn motor_configuration_from_devices(devices: Vec<Arc<ButtplugClientDevice>>, dev
         let rotate_cmds: &Vec<ClientGenericDeviceMessageAttributesV3> = device.message_attributes().rotate_cmd().as_ref().unwrap_or(&empty_vec);
         for index in 0..rotate_cmds.len() {
             let motor_config = MotorConfigurationV3 {
-                device_name: display_name_from_device(&device),
-                device_identifier: id_from_device(&device, device_manager),
+                device_name: display_name_from_device(&device, application_state),
+                device_identifier: id_from_device(&device, &application_state.device_manager),
                 feature_type: MotorTypeV3::Rotation,
                 feature_index: index as u32,
+                enabled: true,
+                min_on_time_ms: None,
+                watchdog_exempt: false,
+                response_curve: None,
             };
             motor_configurations.push(motor_config);
         }
@@ -127,10 +266,14 @@ fn motor_configuration_from_devices(devices: Vec<Arc<ButtplugClientDevice>>, dev
         let linear_cmds: &Vec<ClientGenericDeviceMessageAttributesV3> = device.message_attributes().linear_cmd().as_ref().unwrap_or(&empty_vec);
         for index in 0..linear_cmds.len() {
             let motor_config = MotorConfigurationV3 {
-                device_name: display_name_from_device(&device),
-                device_identifier: id_from_device(&device, device_manager),
+                device_name: display_name_from_device(&device, application_state),
+                device_identifier: id_from_device(&device, &application_state.device_manager),
                 feature_type: MotorTypeV3::Linear,
                 feature_index: index as u32,
+                enabled: true,
+                min_on_time_ms: None,
+                watchdog_exempt: false,
+                response_curve: None,
             };
             motor_configurations.push(motor_config);
         }
@@ -139,29 +282,252 @@ fn motor_configuration_from_devices(devices: Vec<Arc<ButtplugClientDevice>>, dev
     motor_configurations
 }
 
-async fn get_devices(application_state: &ApplicationState) -> DeviceList {
-    let devices = application_state.client.devices();
+/// The actuator type a device reports for one of its scalar features, falling back to `configured` if it has no such
+/// feature. The device server rejects scalar commands whose actuator type doesn't match, which motors configured with
+/// [`ConfigurationV3::unknown_actuator_type`] would otherwise run into.
+pub fn reported_actuator_type(device: &ButtplugClientDevice, feature_index: u32, configured: ButtplugActuatorType) -> ButtplugActuatorType {
+    device.message_attributes().scalar_cmd().as_ref()
+        .and_then(|attributes| attributes.get(feature_index as usize))
+        .map_or(configured, |attributes| *attributes.actuator_type())
+}
+
+/// which of a device's features take both scalar commands and rotate or linear commands
+pub fn shared_features(device: &ButtplugClientDevice) -> SharedFeatures {
+    let attributes = device.message_attributes();
+    let scalar_actuator_types: Vec<ButtplugActuatorType> = attributes.scalar_cmd().as_ref()
+        .map(|attributes| attributes.iter().map(|attribute| *attribute.actuator_type()).collect())
+        .unwrap_or_default();
+    SharedFeatures::new(
+        &scalar_actuator_types,
+        attributes.rotate_cmd().as_ref().map_or(0, |attributes| attributes.len()),
+        attributes.linear_cmd().as_ref().map_or(0, |attributes| attributes.len()),
+    )
+}
+
+/// Measure how long the device with `identifier` takes to acknowledge scalar commands, by alternately setting its first
+/// scalar motor to its lowest level and to zero. Other commands to the device wait until the test is over, and the
+/// motor is then set back to what it was last sent.
+pub async fn measure_latency(application_state_db: &ApplicationStateDb, sent_command_dbs: &SentCommandDbs, identifier: &str) -> Result<LatencyReport, String> {
+    let (device, query_timeout) = {
+        let application_state_mutex = application_state_db.read().await;
+        let application_state = application_state_mutex.as_ref().ok_or("device server is not running")?;
+        let device = application_state.devices().into_iter()
+            .find(|device| id_from_device(device, &application_state.device_manager).as_deref() == Some(identifier))
+            .ok_or("device is no longer connected")?;
+        (device, application_state.configuration.device_query_timeout())
+    };
+    let attributes = device.message_attributes().scalar_cmd().as_ref()
+        .and_then(|attributes| attributes.first())
+        .ok_or("device has no scalar motors to test with")?;
+    let lowest_level = 1.0 / f64::from((*attributes.step_count()).max(1));
+    let actuator_type = *attributes.actuator_type();
+
+    let _send_guard = sent_command_dbs.lock_device(device.index()).await;
+    let previous_intensity = sent_command_dbs.running_motors.read().await.scalars(device.index())
+        .get(&0)
+        .map_or(0.0, |(intensity, _)| *intensity);
+    // the haptic pipeline skips scalars it thinks the device already has, so it must forget what it last sent here
+    if let Some(sent_scalars) = sent_command_dbs.scalars.write().await.get_mut(&device.index()) {
+        sent_scalars.remove(&0);
+    }
+
+    let set_level = |intensity: f64| time::timeout(query_timeout, device.scalar(&ScalarCommand::ScalarMap(HashMap::from([(0, (intensity, actuator_type))]))));
+    let mut samples = Vec::with_capacity(LATENCY_TEST_COMMANDS);
+    let mut error = None;
+    for command in 0..LATENCY_TEST_COMMANDS {
+        // the device server skips commands that don't change anything, so every command must change the level
+        let intensity = if command % 2 == 0 { lowest_level } else { 0.0 };
+        let start = Instant::now();
+        match set_level(intensity).await {
+            Ok(Ok(())) => {
+                samples.push(start.elapsed());
+                time::sleep(LATENCY_TEST_INTERVAL).await;
+            }
+            Ok(Err(e)) => {
+                error = Some(format!("command failed: {e}"));
+                break;
+            }
+            Err(_) => {
+                error = Some(format!("command took over {}ms", query_timeout.as_millis()));
+                break;
+            }
+        }
+    }
+
+    // a test that runs to completion leaves the motor off, but one that was cut short may not have
+    if previous_intensity != 0.0 || error.is_some() {
+        match set_level(previous_intensity).await {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => warn!("unable to restore the motor of {} after measuring its latency: {e}", device.name()),
+            Err(_) => warn!("unable to restore the motor of {} after measuring its latency: command took over {}ms", device.name(), query_timeout.as_millis()),
+        }
+    }
+
+    match error {
+        Some(error) => Err(error),
+        None => LatencyReport::from_samples(&samples).ok_or_else(|| "no commands were sent".to_string()),
+    }
+}
+
+/// Read a device's battery level as a 0-1 fraction, if it has one. Gives up and returns `None` if the device takes longer than `timeout` to respond.
+pub async fn battery_level(device: &ButtplugClientDevice, timeout: Duration, battery_scale: BatteryScale) -> Option<f64> {
+    if device.message_attributes().message_allowed(&ButtplugDeviceMessageType::BatteryLevelCmd) {
+        match time::timeout(timeout, device.battery_level()).await {
+            Ok(battery_level) => battery_level.ok().map(|battery_level| battery_scale.normalize(battery_level)),
+            Err(_) => {
+                warn!("Timed out reading battery level of {}", device.name());
+                None
+            }
+        }
+    } else {
+        None
+    }
+}
+
+async fn get_devices(application_state: &ApplicationState, device_scales: &HashMap<String, f64>, battery_history_db: &BatteryHistoryDb) -> DeviceList {
+    let mut devices = application_state.devices();
+    {
+        let device_numbering = application_state.device_numbering.lock().unwrap_or_else(|e| e.into_inner());
+        let identifiers: HashMap<u32, String> = devices.iter()
+            .filter_map(|device| Some((device.index(), id_from_device(device, &application_state.device_manager)?)))
+            .collect();
+        devices.sort_by_key(|device| (device_numbering.connection_order.ordinal(identifiers.get(&device.index()).map(String::as_str)), device.index()));
+    }
     let mut device_statuses: Vec<DeviceStatus> = Vec::with_capacity(devices.len());
 
     for device in devices.iter() {
-        let battery_level = if device.message_attributes().message_allowed(&ButtplugDeviceMessageType::BatteryLevelCmd) {
-            device.battery_level().await.ok()
-        } else {
-            None
-        };
+        let query_timeout = application_state.configuration.device_query_timeout();
+        let battery_level = battery_level(device, query_timeout, application_state.configuration.battery_scale).await;
         let rssi_level = if device.message_attributes().message_allowed(&ButtplugDeviceMessageType::RSSILevelCmd) {
-            device.rssi_level().await.ok()
+            match time::timeout(query_timeout, device.rssi_level()).await {
+                Ok(rssi_level) => rssi_level.ok(),
+                Err(_) => {
+                    warn!("Timed out reading RSSI level of {}", device.name());
+                    None
+                }
+            }
         } else {
             None
         };
-        let name: String = device.name().to_string();
-        device_statuses.push(DeviceStatus { name, battery_level, rssi_level })
+        let name: String = display_name_from_device(device, application_state);
+        let identifier = id_from_device(device, &application_state.device_manager);
+        if let (Some(battery_level), Some(identifier), Some(battery_history)) = (battery_level, &identifier, &application_state.configuration.battery_history) {
+            battery_history_db.write().await.record(identifier.clone(), name.clone(), battery_level, battery_history, Utc::now());
+        }
+        let device_info = application_state.device_manager.device_info(device.index());
+        let protocol = device_info.as_ref().map(|device_info| device_info.identifier().protocol().to_string());
+        let address = device_info.as_ref().map(|device_info| device_info.identifier().address().to_string());
+        let motor_count = motor_count(device);
+        let scale = identifier.as_ref().and_then(|identifier| device_scales.get(identifier)).copied().unwrap_or(1.0);
+        device_statuses.push(DeviceStatus { name, identifier, protocol, address, motor_count, battery_level, rssi_level, scale })
     }
 
-    let motors = motor_configuration_from_devices(devices, &application_state.device_manager);
+    let motors = motor_configuration_from_devices(devices, application_state);
 
     DeviceList {
         motors,
         devices: device_statuses,
     }
 }
+
+/// Drive one motor directly, outside of the haptic message pipeline. This is how the GUI's manual controls work.
+/// `value` is a strength from 0 to 1 for scalar motors or a signed speed from -1 to 1 for rotation motors, and the device's scale is applied to it.
+/// Linear motors are ignored. What was sent is recorded in the device's last commands, so it's resumed like any other command.
+pub async fn set_motor(application_state_db: &ApplicationStateDb, device_scale_db: &DeviceScaleDb, sent_command_dbs: &SentCommandDbs, motor: &MotorConfigurationV3, value: f64) {
+    let application_state_mutex = application_state_db.read().await;
+    let Some(application_state) = application_state_mutex.as_ref() else { return };
+    if !application_state.configuration.is_motor_type_allowed(&motor.feature_type) {
+        debug!("ignoring manual control of motor {motor}, as its actuator type is not allowed");
+        return;
+    }
+    let Some(device) = device_for_motor(application_state, motor) else {
+        debug!("no connected device for manually controlled motor {motor}");
+        return;
+    };
+    let scale = match &motor.device_identifier {
+        Some(identifier) => device_scale_db.read().await.get(identifier).copied().unwrap_or(1.0),
+        None => 1.0,
+    };
+
+    let last_command_identifier = motor.device_identifier.as_deref().filter(|_| application_state.configuration.resume_after_reconnect);
+    let _send_guard = sent_command_dbs.lock_device(device.index()).await;
+
+    match &motor.feature_type {
+        MotorTypeV3::Scalar { actuator_type } => {
+            // the haptic pipeline skips scalars it thinks the device already has, so it must forget what it last sent here
+            if let Some(sent_scalars) = sent_command_dbs.scalars.write().await.get_mut(&device.index()) {
+                sent_scalars.remove(&motor.feature_index);
+            }
+            let intensity = (value * scale).clamp(0.0, 1.0);
+            let scalar_map = HashMap::from([(motor.feature_index, (intensity, reported_actuator_type(&device, motor.feature_index, actuator_type.to_buttplug())))]);
+            match device.scalar(&ScalarCommand::ScalarMap(scalar_map.clone())).await {
+                Ok(()) => {
+                    sent_command_dbs.running_motors.write().await.record_scalars(device.index(), &scalar_map);
+                    if let Some(identifier) = last_command_identifier {
+                        sent_command_dbs.last_commands.write().await.entry(identifier).record_scalars(&scalar_map);
+                    }
+                }
+                Err(e) => warn!("error manually setting motor {motor}: {e:?}"),
+            }
+        }
+        MotorTypeV3::Rotation => {
+            if let Some(sent_rotations) = sent_command_dbs.rotations.write().await.get_mut(&device.index()) {
+                sent_rotations.remove(&motor.feature_index);
+            }
+            let speed = (value.abs() * scale).clamp(0.0, 1.0);
+            let rotate_map = HashMap::from([(motor.feature_index, (speed, value >= 0.0))]);
+            match device.rotate(&RotateCommand::RotateMap(rotate_map.clone())).await {
+                Ok(()) => {
+                    sent_command_dbs.running_motors.write().await.record_rotations(device.index(), &rotate_map);
+                    if let Some(identifier) = last_command_identifier {
+                        sent_command_dbs.last_commands.write().await.entry(identifier).record_rotations(&rotate_map);
+                    }
+                }
+                Err(e) => warn!("error manually setting motor {motor}: {e:?}"),
+            }
+        }
+        MotorTypeV3::Linear => (),
+    }
+}
+
+/// Start or stop scanning for devices. Returns `Ok(false)` if the device server was already doing so.
+/// The device server starts scanning again whenever it restarts, as devices have to be found again.
+pub async fn set_scanning(application_state_db: &ApplicationStateDb, scanning: bool) -> Result<bool, String> {
+    // the change is claimed before the application state is unlocked, so it isn't locked while waiting on the device server
+    let request = {
+        let mut application_state_mutex = application_state_db.write().await;
+        let application_state = application_state_mutex.as_mut().ok_or("device server is not running")?;
+        if application_state.scanning == scanning {
+            return Ok(false);
+        }
+        application_state.scanning = scanning;
+        if scanning {
+            application_state.client.start_scanning()
+        } else {
+            application_state.client.stop_scanning()
+        }
+    };
+    if let Err(e) = request.await {
+        if let Some(application_state) = application_state_db.write().await.as_mut() {
+            application_state.scanning = !scanning;
+        }
+        return Err(format!("failed to {} scanning: {e:?}", if scanning { "start" } else { "stop" }));
+    }
+    info!("device scan {}", if scanning { "started" } else { "stopped" });
+    Ok(true)
+}
+
+/// Stop every connected device, giving up after a couple of seconds so a misbehaving device can't hold up shutdown.
+/// This includes devices buttplug-lite isn't using, as anything that was left running should stop when it exits.
+/// What was sent is forgotten, so stopped devices are neither kept alive nor resumed if they reconnect.
+pub async fn stop_all_devices(application_state_db: &ApplicationStateDb, sent_command_dbs: &SentCommandDbs) {
+    sent_command_dbs.clear().await;
+    sent_command_dbs.last_commands.write().await.clear();
+    let application_state_mutex = application_state_db.read().await;
+    let Some(application_state) = application_state_mutex.as_ref() else { return };
+    match time::timeout(STOP_ALL_DEVICES_TIMEOUT, application_state.client.stop_all_devices()).await {
+        Ok(Ok(())) => info!("stopped all devices"),
+        Ok(Err(e)) => warn!("error stopping devices: {e:?}"),
+        Err(_) => warn!("timed out stopping devices"),
+    }
+}
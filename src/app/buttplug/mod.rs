@@ -2,8 +2,20 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
+pub use functions::battery_level;
+pub use functions::device_for_motor;
+pub use functions::display_name_from_device;
+pub use functions::feature_attributes;
 pub use functions::get_tagged_devices;
 pub use functions::id_from_device;
+pub use functions::measure_latency;
+pub use functions::motor_count;
+pub use functions::number_devices;
+pub use functions::reported_actuator_type;
+pub use functions::set_motor;
+pub use functions::set_scanning;
+pub use functions::shared_features;
+pub use functions::stop_all_devices;
 pub use startup::start_server;
 
 mod functions;
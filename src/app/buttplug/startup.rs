@@ -4,10 +4,10 @@
 
 //! The buttplug server startup code is so huge I'm putting it in its own file
 
-use std::ops::DerefMut as _;
+use std::sync::Arc;
 use std::time::Duration;
 
-use buttplug::client::{ButtplugClient, ButtplugClientEvent};
+use buttplug::client::{ButtplugClient, ButtplugClientDevice, ButtplugClientEvent, RotateCommand, ScalarCommand};
 use buttplug::core::connector::ButtplugInProcessClientConnectorBuilder;
 use buttplug::server::ButtplugServerBuilder;
 use buttplug::server::device::hardware::communication::{
@@ -19,13 +19,14 @@ use buttplug::server::device::hardware::communication::{
 };
 use buttplug::server::device::ServerDeviceManagerBuilder;
 use futures::StreamExt as _;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::task;
 use tracing::{info, warn};
 
-use crate::app::buttplug::functions::debug_name_from_device;
-use crate::app::structs::{ApplicationState, ApplicationStateDb};
+use crate::app::buttplug::functions::{debug_name_from_device, display_name_from_device, id_from_device, identifiers_with_display_name, motor_count, number_devices};
+use crate::app::structs::{ApplicationState, ApplicationStateDb, DeviceNumberingDb, DeviceServerRetryDb, LastCommand, SentCommandDbs};
 use crate::config;
+use crate::config::v3::CommManager;
 use crate::gui::subscription::ApplicationStatusEvent;
 
 // how long to wait before attempting a reconnect to the server
@@ -34,13 +35,25 @@ const BUTTPLUG_SERVER_RECONNECT_DELAY_MILLIS: u64 = 5000;
 // log prefixes:
 static LOG_PREFIX_BUTTPLUG_SERVER: &str = "buttplug_server";
 
-// name of this client from the buttplug.io server's perspective
+// name of this client from the buttplug.io server's perspective, unless an instance name is configured
 static BUTTPLUG_CLIENT_NAME: &str = "in-process-client";
 
+// name of the buttplug.io server, unless an instance name is configured
+static BUTTPLUG_SERVER_NAME: &str = "buttplug-lite";
+
+/// `device_connected_tx` is set to `true` once a device buttplug-lite will use has connected.
+/// `sent_command_dbs` is cleared for devices that connect or disconnect, as they won't remember what was last sent to them.
+/// Its last commands are used to resume devices that reconnect, if configured to.
+/// `device_server_retry_db` is used to wait for a retry once too many attempts to start the device server have failed.
+/// `device_numbering_db` is shared by each run of the device server, so devices keep their numbers when it restarts.
 pub async fn start_server(
     application_state: ApplicationStateDb,
     initial_config_loaded_tx: oneshot::Sender<()>,
     application_status_sender: mpsc::UnboundedSender<ApplicationStatusEvent>,
+    device_connected_tx: watch::Sender<bool>,
+    sent_command_dbs: SentCommandDbs,
+    device_server_retry_db: DeviceServerRetryDb,
+    device_numbering_db: DeviceNumberingDb,
 ) {
     let mut initial_config_loaded_tx = Some(initial_config_loaded_tx);
 
@@ -48,24 +61,51 @@ pub async fn start_server(
     // when the server is connected this functions as the event reader
     // when the server is disconnected it attempts to reconnect after a delay
     task::spawn(async move {
+        let mut failed_attempts: u32 = 0;
         loop {
             // we reconnect here regardless of server state
-            start_server_internal(application_state.clone(), initial_config_loaded_tx, application_status_sender.clone()).await; // will "block" until disconnect
-            initial_config_loaded_tx = None; // only Some() for the first loop
+            // initial_config_loaded_tx is only Some() for the first loop
+            let outcome = start_server_internal(application_state.clone(), initial_config_loaded_tx.take(), application_status_sender.clone(), &device_connected_tx, &sent_command_dbs, &device_numbering_db).await; // will "block" until disconnect
+            match outcome {
+                ServerOutcome::Disconnected => failed_attempts = 0,
+                ServerOutcome::FailedToStart { max_reconnect_attempts } => {
+                    failed_attempts += 1;
+                    if max_reconnect_attempts.is_some_and(|max_reconnect_attempts| failed_attempts >= max_reconnect_attempts) {
+                        warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: giving up after {failed_attempts} failed attempts to start the device server. Retry from the GUI or with POST /reconnect.");
+                        // the GUI may already be gone if this happens while shutting down
+                        let _ = application_status_sender.send(ApplicationStatusEvent::ServerUnavailable);
+                        device_server_retry_db.give_up().await;
+                        info!("{LOG_PREFIX_BUTTPLUG_SERVER}: retrying device server startup");
+                        failed_attempts = 0;
+                        continue; // a retry was asked for, so there's no need to wait
+                    }
+                }
+            }
             tokio::time::sleep(Duration::from_millis(BUTTPLUG_SERVER_RECONNECT_DELAY_MILLIS)).await; // reconnect delay
         }
     });
 }
 
+/// how a single run of the device server ended
+enum ServerOutcome {
+    /// the device server started, and has since disconnected
+    Disconnected,
+    /// the device server could not be started. The configured limit on consecutive attempts is included, as the
+    /// configuration is reloaded for each attempt.
+    FailedToStart { max_reconnect_attempts: Option<u32> },
+}
+
 // start server, then while running process events
-// returns only when we disconnect from the server
+// returns only when we disconnect from the server, or fail to connect to it
 async fn start_server_internal(
     application_state_db: ApplicationStateDb,
     initial_config_loaded_tx: Option<oneshot::Sender<()>>,
     application_status_event_sender: mpsc::UnboundedSender<ApplicationStatusEvent>,
-) {
+    device_connected_tx: &watch::Sender<bool>,
+    sent_command_dbs: &SentCommandDbs,
+    device_numbering_db: &DeviceNumberingDb,
+) -> ServerOutcome {
     let mut application_state_mutex = application_state_db.write().await;
-    let buttplug_client = ButtplugClient::new(BUTTPLUG_CLIENT_NAME);
 
     // buttplug::util::in_process_client has a good example of how to do this, and so does https://github.com/buttplugio/docs.buttplug.io/blob/master/examples/rust/src/bin/embedded_connector.rs
     let mut device_configuration_manager_builder = buttplug::util::device_configuration::load_protocol_configs(&None, &None, false).expect("Failed to load protocol configs");
@@ -73,21 +113,37 @@ async fn start_server_internal(
         .allow_raw_messages(false)
         .finish()
         .expect("Failed to build device configuration manager");
+    // reuse old config, or load from disk if this is the initial connection
+    let configuration = match application_state_mutex.as_ref() {
+        Some(ApplicationState { configuration, .. }) => configuration.clone(),
+        None => config::load_configuration().await,
+    };
+    let buttplug_client = ButtplugClient::new(configuration.instance_name.as_deref().unwrap_or(BUTTPLUG_CLIENT_NAME));
+
     let mut device_manager_builder = ServerDeviceManagerBuilder::new(device_configuration_manager);
-    device_manager_builder
-        .comm_manager(BtlePlugCommunicationManagerBuilder::default())
-        .comm_manager(SerialPortCommunicationManagerBuilder::default())
-        .comm_manager(LovenseHIDDongleCommunicationManagerBuilder::default())
-        .comm_manager(LovenseSerialDongleCommunicationManagerBuilder::default())
-        .comm_manager(LovenseConnectServiceCommunicationManagerBuilder::default());
-
-    #[cfg(target_os = "windows")] {
-        use buttplug::server::device::hardware::communication::xinput::XInputDeviceCommunicationManagerBuilder;
-        device_manager_builder.comm_manager(XInputDeviceCommunicationManagerBuilder::default());
+    for comm_manager in CommManager::available() {
+        if !configuration.is_comm_manager_enabled(comm_manager) {
+            info!("{LOG_PREFIX_BUTTPLUG_SERVER}: {} communication manager is disabled", comm_manager.name());
+            continue;
+        }
+        match comm_manager {
+            CommManager::Bluetooth => device_manager_builder.comm_manager(BtlePlugCommunicationManagerBuilder::default()),
+            CommManager::Serial => device_manager_builder.comm_manager(SerialPortCommunicationManagerBuilder::default()),
+            CommManager::LovenseHidDongle => device_manager_builder.comm_manager(LovenseHIDDongleCommunicationManagerBuilder::default()),
+            CommManager::LovenseSerialDongle => device_manager_builder.comm_manager(LovenseSerialDongleCommunicationManagerBuilder::default()),
+            CommManager::LovenseConnect => device_manager_builder.comm_manager(LovenseConnectServiceCommunicationManagerBuilder::default()),
+            #[cfg(target_os = "windows")]
+            CommManager::XInput => {
+                use buttplug::server::device::hardware::communication::xinput::XInputDeviceCommunicationManagerBuilder;
+                device_manager_builder.comm_manager(XInputDeviceCommunicationManagerBuilder::default())
+            }
+            #[cfg(not(target_os = "windows"))]
+            CommManager::XInput => continue,
+        };
     }
 
     let server = ButtplugServerBuilder::new(device_manager_builder.finish().expect("Failed to build device manager"))
-        .name("buttplug-lite")
+        .name(configuration.instance_name.as_deref().unwrap_or(BUTTPLUG_SERVER_NAME))
         .finish()
         .expect("Failed to initialize buttplug server");
 
@@ -103,57 +159,152 @@ async fn start_server_internal(
         .server(server)
         .finish();
 
-    match buttplug_client.connect(connector).await {
+    let connect_result = match configuration.server_connect_timeout() {
+        Some(connect_timeout) => match tokio::time::timeout(connect_timeout, buttplug_client.connect(connector)).await {
+            Ok(connect_result) => connect_result.map_err(|e| format!("{e:?}")),
+            Err(_) => Err(format!("timed out after {}ms", connect_timeout.as_millis())),
+        },
+        None => buttplug_client.connect(connector).await.map_err(|e| format!("{e:?}")),
+    };
+    match connect_result {
         Ok(()) => {
             info!("{LOG_PREFIX_BUTTPLUG_SERVER}: Device server started!");
             let mut event_stream = buttplug_client.event_stream();
-            match buttplug_client.start_scanning().await {
-                Ok(()) => info!("{LOG_PREFIX_BUTTPLUG_SERVER}: starting device scan"),
-                Err(e) => warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: scan failure: {e:?}")
-            };
-
-            // reuse old config, or load from disk if this is the initial connection
-            let previous_state = application_state_mutex.deref_mut().take();
-            let configuration = match previous_state {
-                Some(ApplicationState { configuration, .. }) => configuration,
-                None => {
-                    config::load_configuration().await
+            if let Some(scan_delay) = configuration.scan_delay() {
+                info!("{LOG_PREFIX_BUTTPLUG_SERVER}: waiting {}ms before starting device scan", scan_delay.as_millis());
+                tokio::time::sleep(scan_delay).await;
+            }
+            let scanning = match buttplug_client.start_scanning().await {
+                Ok(()) => {
+                    info!("{LOG_PREFIX_BUTTPLUG_SERVER}: starting device scan");
+                    true
+                }
+                Err(e) => {
+                    warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: scan failure: {e:?}");
+                    false
                 }
             };
 
-            *application_state_mutex = Some(ApplicationState { client: buttplug_client, configuration, device_manager: device_manager.clone() });
+            // device indices start over with a new server, but devices keep their numbers
+            sent_command_dbs.clear().await;
+            *application_state_mutex = Some(ApplicationState { client: buttplug_client, configuration, device_manager: device_manager.clone(), device_numbering: device_numbering_db.clone(), scanning });
             drop(application_state_mutex); // prevent this section from requiring two locks
 
             if let Some(sender) = initial_config_loaded_tx {
                 sender.send(()).expect("failed to send config-loaded signal");
+            } else {
+                application_status_event_sender.send(ApplicationStatusEvent::ServerConnected).expect("failed to send server connected event");
+                // reconnecting reloads the configuration from disk, which the GUI needs to know about
+                application_status_event_sender.send(ApplicationStatusEvent::ConfigurationReloaded).expect("failed to send configuration reloaded event");
             }
 
             loop {
                 match event_stream.next().await {
                     Some(event) => match event {
                         ButtplugClientEvent::DeviceAdded(dev) => {
-                            info!("{LOG_PREFIX_BUTTPLUG_SERVER}: device connected: {}", debug_name_from_device(&dev, &device_manager));
-                            application_status_event_sender.send(ApplicationStatusEvent::DeviceAdded).expect("failed to send device added event");
+                            // a device may reuse the index of one that left, and a reconnected device doesn't remember what it was sent
+                            sent_command_dbs.forget_device(dev.index()).await;
+                            let (allowed, used, resume) = application_state_db.write().await.as_mut()
+                                .map_or((true, true, false), |application_state| {
+                                    if let Some(identifier) = id_from_device(&dev, &device_manager) {
+                                        application_state.device_numbering.lock().unwrap_or_else(|e| e.into_inner()).connection_order.record(&identifier);
+                                    }
+                                    number_devices(application_state);
+                                    (application_state.is_device_allowed(&dev), application_state.is_device_used(&dev), application_state.configuration.resume_after_reconnect)
+                                });
+                            if used {
+                                info!("{LOG_PREFIX_BUTTPLUG_SERVER}: device connected: {}", debug_name_from_device(&dev, &device_manager));
+                                device_connected_tx.send_replace(true);
+                                log_duplicate_devices(&application_state_db, &dev).await;
+                                let last_command = match id_from_device(&dev, &device_manager) {
+                                    Some(identifier) if resume => sent_command_dbs.last_commands.read().await.get(&identifier).cloned(),
+                                    _ => None,
+                                };
+                                if let Some(last_command) = last_command {
+                                    info!("{LOG_PREFIX_BUTTPLUG_SERVER}: resuming the last commands sent to reconnected device: {}", debug_name_from_device(&dev, &device_manager));
+                                    // don't hold up other events while a slow device is sent its commands
+                                    task::spawn(resume_device(dev.clone(), last_command));
+                                }
+                            } else if allowed {
+                                info!("{LOG_PREFIX_BUTTPLUG_SERVER}: ignoring device until another disconnects, as the configured max_devices limit has been reached: {}", debug_name_from_device(&dev, &device_manager));
+                            } else {
+                                info!("{LOG_PREFIX_BUTTPLUG_SERVER}: ignoring device excluded by the device allowlist or blocklist: {}", debug_name_from_device(&dev, &device_manager));
+                            }
+                            if motor_count(&dev) == 0 {
+                                // raw messages are disabled for safety, and buttplug-lite has no way to send them anyways
+                                warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: device has no scalar, rotation, or linear motors, so it can't be controlled. It may only support raw messages, which buttplug-lite does not send: {}", debug_name_from_device(&dev, &device_manager));
+                            }
+                            if allowed {
+                                // the GUI explains devices left out by the limit, so it needs to know about them too
+                                application_status_event_sender.send(ApplicationStatusEvent::DeviceAdded).expect("failed to send device added event");
+                            }
                         }
                         ButtplugClientEvent::DeviceRemoved(dev) => {
+                            sent_command_dbs.forget_device(dev.index()).await;
                             info!("{LOG_PREFIX_BUTTPLUG_SERVER}: device disconnected: {}", debug_name_from_device(&dev, &device_manager));
                             application_status_event_sender.send(ApplicationStatusEvent::DeviceRemoved).expect("failed to send device removed event");
                         }
                         ButtplugClientEvent::PingTimeout => info!("{LOG_PREFIX_BUTTPLUG_SERVER}: ping timeout"),
                         ButtplugClientEvent::Error(e) => info!("{LOG_PREFIX_BUTTPLUG_SERVER}: server error: {e:?}"),
-                        ButtplugClientEvent::ScanningFinished => info!("{LOG_PREFIX_BUTTPLUG_SERVER}: device scan finished"),
+                        ButtplugClientEvent::ScanningFinished => {
+                            info!("{LOG_PREFIX_BUTTPLUG_SERVER}: device scan finished");
+                            if let Some(application_state) = application_state_db.write().await.as_mut() {
+                                application_state.scanning = false;
+                            }
+                        }
                         ButtplugClientEvent::ServerConnect => info!("{LOG_PREFIX_BUTTPLUG_SERVER}: server connected"),
                         ButtplugClientEvent::ServerDisconnect => {
                             info!("{LOG_PREFIX_BUTTPLUG_SERVER}: server disconnected");
                             let mut application_state_mutex = application_state_db.write().await;
                             *application_state_mutex = None; // not strictly required but will give more sane error messages
+                            // this disconnect may be part of shutting down, in which case the GUI is already gone
+                            let _ = application_status_event_sender.send(ApplicationStatusEvent::ServerDisconnected);
                             break;
                         }
                     },
                     None => warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: error reading haptic event")
                 };
             }
+            ServerOutcome::Disconnected
+        }
+        Err(e) => {
+            warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: failed to connect to server. Will retry shortly… ({e})"); // will try to reconnect later, may not need to log this error
+            ServerOutcome::FailedToStart { max_reconnect_attempts: configuration.max_reconnect_attempts }
+        }
+    }
+}
+
+/// Re-send the last scalar and rotate commands to a device that has reconnected
+async fn resume_device(device: Arc<ButtplugClientDevice>, last_command: LastCommand) {
+    if !last_command.scalar_map.is_empty() {
+        if let Err(e) = device.scalar(&ScalarCommand::ScalarMap(last_command.scalar_map)).await {
+            warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: error resuming scalar motors: {e:?}");
         }
-        Err(e) => warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: failed to connect to server. Will retry shortly… ({e:?})") // will try to reconnect later, may not need to log this error
+    }
+    if !last_command.rotate_map.is_empty() {
+        if let Err(e) = device.rotate(&RotateCommand::RotateMap(last_command.rotate_map)).await {
+            warn!("{LOG_PREFIX_BUTTPLUG_SERVER}: error resuming rotation motors: {e:?}");
+        }
+    }
+}
+
+/// Log the identifiers of every connected device that shares a display name with a device that just connected, so
+/// duplicate devices can be told apart
+async fn log_duplicate_devices(application_state_db: &ApplicationStateDb, device: &ButtplugClientDevice) {
+    let application_state_mutex = application_state_db.read().await;
+    let Some(application_state) = application_state_mutex.as_ref() else { return };
+    let name = application_state.configuration.device_display_name(device.name());
+    let identifiers = identifiers_with_display_name(application_state, &name);
+    if identifiers.len() < 2 {
+        return;
+    }
+    if application_state.configuration.number_duplicate_devices {
+        let numbered_devices: Vec<String> = application_state.devices().iter()
+            .filter(|device| application_state.configuration.device_display_name(device.name()) == name)
+            .filter_map(|device| Some(format!("{} is {}", display_name_from_device(device, application_state), id_from_device(device, &application_state.device_manager)?)))
+            .collect();
+        info!("{LOG_PREFIX_BUTTPLUG_SERVER}: {} connected devices are named {name:?}: {}", identifiers.len(), numbered_devices.join(", "));
+    } else {
+        info!("{LOG_PREFIX_BUTTPLUG_SERVER}: {} connected devices are named {name:?}, so their motors can't be told apart until number_duplicate_devices is enabled: {}", identifiers.len(), identifiers.join(", "));
     }
 }
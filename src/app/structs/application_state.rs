@@ -3,9 +3,10 @@
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
 use std::sync::Arc;
-use buttplug::client::ButtplugClient;
+use buttplug::client::{ButtplugClient, ButtplugClientDevice};
 use buttplug::server::device::ServerDeviceManager;
 use tokio::sync::RwLock;
+use crate::app::structs::DeviceNumberingDb;
 use crate::config::v3::ConfigurationV3;
 
 // global state types
@@ -16,4 +17,45 @@ pub struct ApplicationState {
     pub client: ButtplugClient,
     pub configuration: ConfigurationV3,
     pub device_manager: Arc<ServerDeviceManager>,
+    /// outlives this state, so devices keep their numbers when the device server restarts
+    pub device_numbering: DeviceNumberingDb,
+    /// if the device server is scanning for devices
+    pub scanning: bool,
+}
+
+impl ApplicationState {
+    /// connected devices that the configuration lets buttplug-lite use
+    pub fn devices(&self) -> Vec<Arc<ButtplugClientDevice>> {
+        let mut devices = self.allowed_devices();
+        if let Some(max_devices) = self.configuration.max_devices {
+            // devices that connected first keep their place
+            devices.sort_unstable_by_key(|device| device.index());
+            devices.truncate(max_devices);
+        }
+        devices
+    }
+
+    /// if this device is one of the [`ApplicationState::devices`] buttplug-lite uses
+    pub fn is_device_used(&self, device: &ButtplugClientDevice) -> bool {
+        self.devices().iter().any(|used_device| used_device.index() == device.index())
+    }
+
+    /// number of allowed devices left unused because of the configured device limit
+    pub fn devices_over_limit(&self) -> usize {
+        self.allowed_devices().len().saturating_sub(self.devices().len())
+    }
+
+    fn allowed_devices(&self) -> Vec<Arc<ButtplugClientDevice>> {
+        self.client.devices().into_iter()
+            .filter(|device| self.is_device_allowed(device))
+            .collect()
+    }
+
+    /// check a device against the configured allowlist and blocklist
+    pub fn is_device_allowed(&self, device: &ButtplugClientDevice) -> bool {
+        let device_info = self.device_manager.device_info(device.index());
+        let identity = device_info.as_ref()
+            .map(|device_info| (device_info.identifier().protocol().as_str(), device_info.identifier().address().as_str()));
+        self.configuration.is_device_allowed(identity)
+    }
 }
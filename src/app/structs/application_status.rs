@@ -2,6 +2,8 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
+use std::collections::HashMap;
+
 use crate::app::structs::DeviceStatus;
 use crate::config::v3::ConfigurationV3;
 use crate::gui::TaggedMotor;
@@ -11,5 +13,11 @@ use crate::gui::TaggedMotor;
 pub struct ApplicationStatus {
     pub motors: Vec<TaggedMotor>,
     pub devices: Vec<DeviceStatus>,
+    /// number of connected devices left out of `devices` because of the configured device limit
+    pub devices_over_limit: usize,
+    /// live master scales of motor groups, keyed by group name. Groups without one are at 1.
+    pub group_scales: HashMap<String, f64>,
+    /// if the device server is scanning for devices
+    pub scanning: bool,
     pub configuration: ConfigurationV3,
 }
@@ -0,0 +1,139 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+use crate::config::v3::BatteryHistoryV3;
+
+pub type BatteryHistoryDb = Arc<RwLock<BatteryHistory>>;
+
+/// Recent battery readings of each device, keyed by device identifier so devices that share a name are kept apart
+#[derive(Debug, Default)]
+pub struct BatteryHistory {
+    devices: HashMap<String, DeviceBatteryHistory>,
+}
+
+#[derive(Debug)]
+struct DeviceBatteryHistory {
+    /// the device's display name when it was last read
+    name: String,
+    /// oldest first
+    readings: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+/// One device's readings, as listed by `GET /batteryhistory`
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceBatteryReadings {
+    pub name: String,
+    /// oldest first
+    pub readings: Vec<BatteryReading>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatteryReading {
+    /// when the battery was read, as an RFC 3339 UTC timestamp
+    pub time: String,
+    /// from 0 to 1
+    pub level: f64,
+}
+
+impl BatteryHistory {
+    /// Remember a battery reading taken at `now`, forgetting readings that are too old or too many
+    pub fn record(&mut self, identifier: String, name: String, level: f64, settings: &BatteryHistoryV3, now: DateTime<Utc>) {
+        let device = self.devices.entry(identifier).or_insert_with(|| DeviceBatteryHistory { name: String::new(), readings: VecDeque::new() });
+        device.name = name;
+        let readings = &mut device.readings;
+        readings.push_back((now, level));
+        while readings.len() > settings.max_samples {
+            readings.pop_front();
+        }
+        while readings.front().is_some_and(|(time, _)| now - *time > settings.retention()) {
+            readings.pop_front();
+        }
+    }
+
+    /// every device's readings that are still within the retention window at `now`, keyed by device identifier
+    pub fn readings(&self, settings: &BatteryHistoryV3, now: DateTime<Utc>) -> BTreeMap<String, DeviceBatteryReadings> {
+        self.devices.iter()
+            .map(|(identifier, device)| {
+                let readings = device.readings.iter()
+                    .filter(|(time, _)| now - *time <= settings.retention())
+                    .map(|(time, level)| BatteryReading { time: time.to_rfc3339_opts(SecondsFormat::Millis, true), level: *level })
+                    .collect::<Vec<_>>();
+                (identifier.clone(), DeviceBatteryReadings { name: device.name.clone(), readings })
+            })
+            .filter(|(_, device)| !device.readings.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    fn levels(battery_history: &BatteryHistory, settings: &BatteryHistoryV3, now: DateTime<Utc>, identifier: &str) -> Vec<f64> {
+        battery_history.readings(settings, now).get(identifier)
+            .map(|device| device.readings.iter().map(|reading| reading.level).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn devices_with_the_same_name_are_kept_apart() {
+        let settings = BatteryHistoryV3 { retention_s: 60, max_samples: 10 };
+        let now = Utc::now();
+        let mut battery_history = BatteryHistory::default();
+        battery_history.record("lovense://a".to_string(), "Lovense Max".to_string(), 0.5, &settings, now);
+        battery_history.record("lovense://b".to_string(), "Lovense Max".to_string(), 0.25, &settings, now);
+        battery_history.record("lovense://a".to_string(), "Left Max".to_string(), 0.45, &settings, now);
+
+        let readings = battery_history.readings(&settings, now);
+        assert_eq!(readings.len(), 2);
+        // the most recent display name is used
+        assert_eq!(readings["lovense://a"].name, "Left Max");
+        assert_eq!(levels(&battery_history, &settings, now, "lovense://a"), vec![0.5, 0.45]);
+        assert_eq!(levels(&battery_history, &settings, now, "lovense://b"), vec![0.25]);
+    }
+
+    #[test]
+    fn readings_expire_after_retention() {
+        let settings = BatteryHistoryV3 { retention_s: 60, max_samples: 10 };
+        let start = Utc::now();
+        let mut battery_history = BatteryHistory::default();
+        battery_history.record("a".to_string(), "toy".to_string(), 0.9, &settings, start);
+        battery_history.record("a".to_string(), "toy".to_string(), 0.8, &settings, start + TimeDelta::seconds(30));
+
+        // readings exactly as old as the retention window are kept
+        assert_eq!(levels(&battery_history, &settings, start + TimeDelta::seconds(60), "a"), vec![0.9, 0.8]);
+        assert_eq!(levels(&battery_history, &settings, start + TimeDelta::seconds(61), "a"), vec![0.8]);
+        // devices whose readings all expired are left out
+        assert!(battery_history.readings(&settings, start + TimeDelta::seconds(91)).is_empty());
+
+        // recording drops expired readings for good
+        battery_history.record("a".to_string(), "toy".to_string(), 0.7, &settings, start + TimeDelta::seconds(75));
+        assert_eq!(levels(&battery_history, &settings, start, "a"), vec![0.8, 0.7]);
+    }
+
+    #[test]
+    fn oldest_readings_are_pruned_past_max_samples() {
+        let settings = BatteryHistoryV3 { retention_s: 60, max_samples: 3 };
+        let start = Utc::now();
+        let mut battery_history = BatteryHistory::default();
+        for (second, level) in [0.9, 0.8, 0.7, 0.6, 0.5].into_iter().enumerate() {
+            battery_history.record("a".to_string(), "toy".to_string(), level, &settings, start + TimeDelta::seconds(second as i64));
+        }
+        battery_history.record("b".to_string(), "toy".to_string(), 1.0, &settings, start);
+
+        let now = start + TimeDelta::seconds(5);
+        assert_eq!(levels(&battery_history, &settings, now, "a"), vec![0.7, 0.6, 0.5]);
+        // each device has its own limit
+        assert_eq!(levels(&battery_history, &settings, now, "b"), vec![1.0]);
+    }
+}
@@ -2,6 +2,8 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
+use std::path::PathBuf;
+
 use clap::Parser;
 
 /// struct used to derive Clap arguments
@@ -31,4 +33,36 @@ pub struct CliArgs {
     /// Enables the custom panic handler in stdout logs. Has no effect if file logging is used. Note that file logging is the default without an explicit `--stdout`.
     #[arg(long)]
     pub force_panic_handler: bool,
+
+    /// Run sample commands through the haptic command pipeline without any devices, then exit. The exit code is 0 if every check passed, or 1 otherwise. Combine with `--stdout` to see the results.
+    #[arg(long)]
+    pub test_pipeline: bool,
+
+    /// Write a diagnostic bundle for bug reports to <PATH>, then exit. The bundle is a zip file containing build and OS information, the configuration with any commands redacted, and the most recent log file.
+    #[arg(long, value_name = "PATH")]
+    pub diagnostics: Option<PathBuf>,
+
+    /// Show which tags migrating an outdated configuration file would drop or change, then exit without writing anything.
+    #[arg(long)]
+    pub migrate_dry_run: bool,
+
+    /// List the MIDI input ports `midi_input` can read from, then exit.
+    #[arg(long)]
+    pub list_midi_ports: bool,
+
+    /// Render the GUI without antialiasing, regardless of the `antialiasing` setting.
+    #[arg(long)]
+    pub no_antialiasing: bool,
+
+    /// Render the GUI with OpenGL, regardless of the `prefer_opengl` setting. Try this if the window is black or fails to open.
+    #[arg(long)]
+    pub prefer_opengl: bool,
+
+    /// Load a shared base configuration from <PATH>, with the configuration file layered over it. Tags are merged, with the configuration file's tags replacing base tags of the same name, and every other setting in the configuration file replaces the base's.
+    #[arg(long, value_name = "PATH")]
+    pub base_config: Option<PathBuf>,
+
+    /// Layer the configuration at <PATH> over `--base-config` instead of the configuration file.
+    #[arg(long, value_name = "PATH", requires = "base_config")]
+    pub override_config: Option<PathBuf>,
 }
@@ -0,0 +1,117 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Kept apart from the application state, which is thrown away whenever the device server restarts. This is a blocking
+/// mutex, as it's used while naming devices with the application state already locked. Never hold it across an await.
+pub type DeviceNumberingDb = Arc<Mutex<DeviceNumbering>>;
+
+/// What buttplug-lite remembers about devices for as long as it runs, however devices and the device server come and go
+#[derive(Debug, Default)]
+pub struct DeviceNumbering {
+    pub connection_order: ConnectionOrder,
+    pub device_numbers: DeviceNumbers,
+}
+
+/// Remembers the order devices connected in, keyed by device identifier, as device indices start over with a new device server
+#[derive(Debug, Default)]
+pub struct ConnectionOrder {
+    ordinals: HashMap<String, u64>,
+    next_ordinal: u64,
+}
+
+impl ConnectionOrder {
+    /// note that a device just connected. A device that reconnects moves to the end.
+    pub fn record(&mut self, identifier: &str) {
+        self.ordinals.insert(identifier.to_string(), self.next_ordinal);
+        self.next_ordinal += 1;
+    }
+
+    /// Sort key for a device. Devices without an identifier, or that connected before anything was recorded, go last.
+    pub fn ordinal(&self, identifier: Option<&str>) -> u64 {
+        identifier.and_then(|identifier| self.ordinals.get(identifier)).copied().unwrap_or(u64::MAX)
+    }
+}
+
+/// The numbers given to devices that share a display name, so each device keeps its number however devices come and go.
+/// Numbers start at 1.
+#[derive(Debug, Default)]
+pub struct DeviceNumbers {
+    /// keyed by display name and then device identifier
+    numbers: HashMap<String, HashMap<String, usize>>,
+}
+
+impl DeviceNumbers {
+    /// Number a device, unless it already has a number. The `preferred` number is used if no other device with this
+    /// name has it, otherwise the lowest free number is.
+    pub fn assign(&mut self, name: &str, identifier: &str, preferred: Option<usize>) -> usize {
+        let numbers = self.numbers.entry(name.to_string()).or_default();
+        if let Some(number) = numbers.get(identifier) {
+            return *number;
+        }
+        let is_free = |number: &usize| *number > 0 && !numbers.values().any(|taken| taken == number);
+        let number = preferred.filter(is_free)
+            .or_else(|| (1..).find(is_free))
+            .expect("ran out of device numbers");
+        numbers.insert(identifier.to_string(), number);
+        number
+    }
+
+    pub fn get(&self, name: &str, identifier: &str) -> Option<usize> {
+        self.numbers.get(name)?.get(identifier).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_numbers_are_kept() {
+        let mut device_numbers = DeviceNumbers::default();
+        assert_eq!(device_numbers.assign("toy", "b", None), 1);
+        assert_eq!(device_numbers.assign("toy", "a", None), 2);
+        // a device that sorts first doesn't take over an existing number, and asking again changes nothing
+        assert_eq!(device_numbers.assign("toy", "b", None), 1);
+        assert_eq!(device_numbers.get("toy", "a"), Some(2));
+        assert_eq!(device_numbers.get("toy", "c"), None);
+    }
+
+    #[test]
+    fn device_numbers_prefer_free_numbers() {
+        let mut device_numbers = DeviceNumbers::default();
+        assert_eq!(device_numbers.assign("toy", "a", Some(3)), 3);
+        assert_eq!(device_numbers.assign("toy", "b", Some(3)), 1);
+        assert_eq!(device_numbers.assign("toy", "c", Some(0)), 2);
+        // numbers are per display name
+        assert_eq!(device_numbers.assign("other toy", "d", Some(3)), 3);
+    }
+
+    #[test]
+    fn device_numbers_survive_device_server_restart() {
+        let device_numbering = DeviceNumberingDb::default();
+        // each run of the device server gets its own clone, the same as each new application state does
+        let first_server = device_numbering.clone();
+        for identifier in ["b", "a"] {
+            let mut numbering = first_server.lock().unwrap();
+            numbering.connection_order.record(identifier);
+            numbering.device_numbers.assign("toy", identifier, None);
+        }
+        drop(first_server);
+
+        // the devices reconnect to the restarted device server the other way around
+        let second_server = device_numbering.clone();
+        let mut numbering = second_server.lock().unwrap();
+        for identifier in ["a", "b"] {
+            numbering.connection_order.record(identifier);
+            numbering.device_numbers.assign("toy", identifier, None);
+        }
+        assert_eq!(numbering.device_numbers.get("toy", "b"), Some(1));
+        assert_eq!(numbering.device_numbers.get("toy", "a"), Some(2));
+        assert!(numbering.connection_order.ordinal(Some("a")) < numbering.connection_order.ordinal(Some("b")));
+        assert_eq!(numbering.connection_order.ordinal(None), u64::MAX);
+    }
+}
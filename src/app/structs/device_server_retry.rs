@@ -0,0 +1,40 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+pub type DeviceServerRetryDb = Arc<DeviceServerRetry>;
+
+/// Tracks whether the device server reconnect loop has given up after too many failed attempts, and lets it be told to
+/// start trying again.
+#[derive(Debug, Default)]
+pub struct DeviceServerRetry {
+    given_up: AtomicBool,
+    retry_requested: Notify,
+}
+
+impl DeviceServerRetry {
+    /// if the reconnect loop has stopped trying to start the device server
+    pub fn has_given_up(&self) -> bool {
+        self.given_up.load(Ordering::Relaxed)
+    }
+
+    /// Stop trying to start the device server until [`DeviceServerRetry::retry`] is called
+    pub async fn give_up(&self) {
+        self.given_up.store(true, Ordering::Relaxed);
+        self.retry_requested.notified().await;
+    }
+
+    /// Start trying to start the device server again, returning `false` if the reconnect loop hadn't given up
+    pub fn retry(&self) -> bool {
+        let given_up = self.given_up.swap(false, Ordering::Relaxed);
+        if given_up {
+            self.retry_requested.notify_one();
+        }
+        given_up
+    }
+}
@@ -10,8 +10,17 @@ use std::fmt;
 #[derive(Clone, Debug)]
 pub struct DeviceStatus {
     pub name: String,
+    pub identifier: Option<String>,
+    /// the device's communication protocol, such as `lovense`
+    pub protocol: Option<String>,
+    /// the device's address within its protocol, such as a Bluetooth MAC address
+    pub address: Option<String>,
+    /// how many scalar, rotation, and linear motors the device has
+    pub motor_count: usize,
     pub battery_level: Option<f64>,
     pub rssi_level: Option<i32>,
+    /// live intensity multiplier applied to all of this device's motors
+    pub scale: f64,
 }
 
 impl Display for DeviceStatus {
@@ -41,6 +50,17 @@ impl Display for DeviceStatus {
     }
 }
 
+impl DeviceStatus {
+    /// a description of the device's protocol, address, and motors, to help tell identical devices apart
+    pub fn details(&self) -> String {
+        let motors = if self.motor_count == 1 { "motor" } else { "motors" };
+        match (&self.protocol, &self.address) {
+            (Some(protocol), Some(address)) => format!("{protocol} @ {address}, {} {motors}", self.motor_count),
+            _ => format!("{} {motors}", self.motor_count),
+        }
+    }
+}
+
 impl Eq for DeviceStatus {}
 
 impl PartialEq for DeviceStatus {
@@ -0,0 +1,59 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+pub type HapticErrorDb = Arc<RwLock<HapticErrors>>;
+
+/// Longest part of a rejected message that is kept, in bytes. Oversized messages are one of the things that get rejected.
+const MAX_RECORDED_MESSAGE_LENGTH: usize = 256;
+
+/// The most recent `/haptic` messages that were rejected, oldest first
+#[derive(Debug, Default)]
+pub struct HapticErrors {
+    errors: VecDeque<HapticError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HapticError {
+    /// when the message was rejected, as an RFC 3339 UTC timestamp
+    pub time: String,
+    /// which `/haptic` connection sent the message, matching the connection ids in the logs
+    pub connection: u64,
+    /// the start of the rejected message
+    pub message: String,
+    pub error: String,
+}
+
+impl HapticErrors {
+    /// Remember a rejected message, forgetting the oldest ones so that at most `capacity` are kept
+    pub fn record(&mut self, connection: u64, message: &str, error: String, capacity: usize) {
+        let mut message_end = message.len().min(MAX_RECORDED_MESSAGE_LENGTH);
+        while !message.is_char_boundary(message_end) {
+            message_end -= 1;
+        }
+        self.errors.push_back(HapticError {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            connection,
+            message: message[..message_end].to_string(),
+            error,
+        });
+        while self.errors.len() > capacity {
+            self.errors.pop_front();
+        }
+    }
+
+    pub fn errors(&self) -> Vec<HapticError> {
+        self.errors.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.errors.clear();
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::RwLock;
+
+pub type HapticSessionDb = Arc<RwLock<HapticSessions>>;
+
+/// Most sessions remembered at once. Starting a new session beyond this forgets the least recently used one.
+const MAX_SESSIONS: usize = 64;
+
+/// The last value each resumable `/haptic` session sent to each of its scalar and rotation tags, keyed by session token
+#[derive(Debug, Default)]
+pub struct HapticSessions {
+    sessions: HashMap<String, HapticSession>,
+}
+
+#[derive(Debug)]
+struct HapticSession {
+    last_used: Instant,
+    tag_values: HashMap<String, f64>,
+}
+
+impl HapticSessions {
+    /// Start using a session, returning the tag values it last sent. A session that does not exist yet is created with no values.
+    pub fn resume(&mut self, token: &str, now: Instant) -> HashMap<String, f64> {
+        self.session_mut(token, now).tag_values.clone()
+    }
+
+    /// Remember the values a session just sent to some tags
+    pub fn record(&mut self, token: &str, tag_values: impl IntoIterator<Item = (String, f64)>, now: Instant) {
+        self.session_mut(token, now).tag_values.extend(tag_values);
+    }
+
+    fn session_mut(&mut self, token: &str, now: Instant) -> &mut HapticSession {
+        if !self.sessions.contains_key(token) && self.sessions.len() >= MAX_SESSIONS {
+            let least_recently_used = self.sessions.iter()
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(token, _)| token.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                self.sessions.remove(&least_recently_used);
+            }
+        }
+        let session = self.sessions.entry(token.to_string())
+            .or_insert_with(|| HapticSession { last_used: now, tag_values: HashMap::new() });
+        session.last_used = now;
+        session
+    }
+}
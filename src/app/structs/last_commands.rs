@@ -0,0 +1,56 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use buttplug::core::message::ActuatorType;
+use tokio::sync::RwLock;
+
+pub type LastCommandDb = Arc<RwLock<LastCommands>>;
+
+/// The last scalar and rotate commands sent to each device, keyed by device identifier, so devices that briefly
+/// disconnect can resume where they left off. This must be cleared whenever devices are halted.
+#[derive(Debug, Default)]
+pub struct LastCommands {
+    devices: HashMap<String, LastCommand>,
+}
+
+/// What a single device was last commanded to do. Stopped motors are left out.
+#[derive(Clone, Debug, Default)]
+pub struct LastCommand {
+    pub scalar_map: HashMap<u32, (f64, ActuatorType)>,
+    pub rotate_map: HashMap<u32, (f64, bool)>,
+}
+
+impl LastCommands {
+    /// the last commands for a device, to be added to as more are sent
+    pub fn entry(&mut self, identifier: &str) -> &mut LastCommand {
+        self.devices.entry(identifier.to_string()).or_default()
+    }
+
+    /// the last commands for a device, if any of its motors are still running
+    pub fn get(&self, identifier: &str) -> Option<&LastCommand> {
+        self.devices.get(identifier)
+            .filter(|last_command| !last_command.scalar_map.is_empty() || !last_command.rotate_map.is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        self.devices.clear();
+    }
+}
+
+impl LastCommand {
+    /// Remember scalar commands that were just sent
+    pub fn record_scalars(&mut self, scalar_map: &HashMap<u32, (f64, ActuatorType)>) {
+        self.scalar_map.extend(scalar_map);
+        self.scalar_map.retain(|_, (value, _)| *value != 0.0);
+    }
+
+    /// Remember rotate commands that were just sent
+    pub fn record_rotations(&mut self, rotate_map: &HashMap<u32, (f64, bool)>) {
+        self.rotate_map.extend(rotate_map);
+        self.rotate_map.retain(|_, (speed, _)| *speed != 0.0);
+    }
+}
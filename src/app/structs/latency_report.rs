@@ -0,0 +1,33 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::fmt::{Display, Formatter};
+use std::fmt;
+use std::time::Duration;
+
+/// How long a device took to acknowledge a series of commands
+#[derive(Clone, Debug)]
+pub struct LatencyReport {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+}
+
+impl LatencyReport {
+    /// Summarize the time each command took. Returns `None` if there are no samples.
+    pub fn from_samples(samples: &[Duration]) -> Option<LatencyReport> {
+        let count = u32::try_from(samples.len()).ok().filter(|count| *count > 0)?;
+        Some(LatencyReport {
+            min: *samples.iter().min()?,
+            avg: samples.iter().sum::<Duration>() / count,
+            max: *samples.iter().max()?,
+        })
+    }
+}
+
+impl Display for LatencyReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "latency min={}ms avg={}ms max={}ms", self.min.as_millis(), self.avg.as_millis(), self.max.as_millis())
+    }
+}
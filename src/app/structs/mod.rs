@@ -7,12 +7,48 @@
 
 pub use application_state::*;
 pub use application_status::ApplicationStatus;
+pub use battery_history::BatteryHistoryDb;
 pub use cli_args::CliArgs;
+pub use device_numbering::DeviceNumberingDb;
+pub use device_server_retry::DeviceServerRetryDb;
 pub use device_status::DeviceStatus;
-pub use motor_settings::MotorSettings;
+pub use haptic_errors::HapticErrorDb;
+pub use haptic_sessions::HapticSessionDb;
+pub use last_commands::{LastCommand, LastCommandDb};
+pub use latency_report::LatencyReport;
+pub use motor_holds::{MotorHoldDb, MotorHolds};
+pub use motor_ramps::{MotorRampDb, MotorRamps};
+pub use motor_settings::{MotorSettings, SharedFeatures};
+pub use pending_halts::{PendingHaltDb, PendingHaltKey};
+pub use playing_pattern::PlayingPatternDb;
+pub use running_motors::RunningMotorDb;
+pub use scales::{set_scale, DeviceScaleDb, GroupScaleDb};
+pub use sent_commands::SentCommandDbs;
+pub use sent_rotations::SentRotationDb;
+pub use sent_scalars::SentScalarDb;
+pub use tag_claims::TagClaimDb;
+pub use tag_values::TagValueDb;
 
 mod application_state;
 mod application_status;
+mod battery_history;
 mod cli_args;
+mod device_numbering;
+mod device_server_retry;
 mod device_status;
+mod haptic_errors;
+mod haptic_sessions;
+mod last_commands;
+mod latency_report;
+mod motor_holds;
+mod motor_ramps;
 mod motor_settings;
+mod pending_halts;
+mod playing_pattern;
+mod running_motors;
+mod scales;
+mod sent_commands;
+mod sent_rotations;
+mod sent_scalars;
+mod tag_claims;
+mod tag_values;
@@ -0,0 +1,75 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+
+pub type MotorHoldDb = Arc<RwLock<MotorHolds>>;
+
+/// Keeps motors with a minimum on-time running after they are commanded to stop, keyed by tag name.
+/// A motor that is held becomes due for release once its minimum on-time has passed.
+#[derive(Debug, Default)]
+pub struct MotorHolds {
+    holds: HashMap<String, MotorHold>,
+    new_releases: Vec<(String, Instant)>,
+}
+
+#[derive(Debug)]
+struct MotorHold {
+    /// when the motor last went from off to on
+    on_since: Instant,
+    /// the last nonzero value commanded, which is what the motor is held at
+    value: f64,
+    /// if the motor has been commanded to stop, but its minimum on-time has not passed yet
+    release_pending: bool,
+}
+
+impl MotorHolds {
+    /// Returns the value that should actually be sent to a motor that was commanded to `value` at `now`.
+    /// If this holds a motor on when it previously wasn't, the tag and its release deadline are queued in [`MotorHolds::take_new_releases`].
+    pub fn apply(&mut self, tag: &str, min_on_time: Duration, value: f64, now: Instant) -> f64 {
+        if value != 0.0 {
+            self.holds.entry(tag.to_string())
+                .and_modify(|hold| {
+                    hold.value = value;
+                    hold.release_pending = false;
+                })
+                .or_insert(MotorHold { on_since: now, value, release_pending: false });
+            value
+        } else if let Some(hold) = self.holds.get_mut(tag) {
+            let release_at = hold.on_since + min_on_time;
+            if now >= release_at {
+                self.holds.remove(tag);
+                value
+            } else {
+                if !hold.release_pending {
+                    hold.release_pending = true;
+                    self.new_releases.push((tag.to_string(), release_at));
+                }
+                hold.value
+            }
+        } else {
+            value
+        }
+    }
+
+    /// if this motor is still being held on after being commanded to stop
+    pub fn is_release_pending(&self, tag: &str) -> bool {
+        self.holds.get(tag).is_some_and(|hold| hold.release_pending)
+    }
+
+    /// Take the motors that started being held since the last call, along with when each should be released
+    pub fn take_new_releases(&mut self) -> Vec<(String, Instant)> {
+        std::mem::take(&mut self.new_releases)
+    }
+
+    /// Forget the holds on every motor whose tag `keep` returns `false` for, for example because those motors were just halted
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.holds.retain(|tag, _| keep(tag));
+        self.new_releases.retain(|(tag, _)| keep(tag));
+    }
+}
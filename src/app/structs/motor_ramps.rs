@@ -0,0 +1,83 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+
+pub type MotorRampDb = Arc<RwLock<MotorRamps>>;
+
+/// Ramps motors up from zero when they are turned on after sitting idle, keyed by tag name.
+/// Motors that have never been commanded count as having been idle forever.
+#[derive(Debug, Default)]
+pub struct MotorRamps {
+    states: HashMap<String, RampState>,
+    new_ramps: Vec<String>,
+}
+
+#[derive(Debug)]
+enum RampState {
+    /// the motor was last commanded to zero at this time
+    Idle(Instant),
+    /// the motor is ramping up to its commanded value, which started at this time
+    Ramping(Instant),
+    /// the motor is running at its commanded value
+    Running,
+}
+
+impl MotorRamps {
+    /// Returns the value that should actually be sent to a motor that was commanded to `value` at `now`.
+    /// Motors turned on after being idle for at least `idle` ramp up to their commanded value over `duration`.
+    /// If this starts a ramp, the tag is queued in [`MotorRamps::take_new_ramps`].
+    pub fn apply(&mut self, tag: &str, idle: Duration, duration: Duration, value: f64, now: Instant) -> f64 {
+        if value == 0.0 {
+            if !matches!(self.states.get(tag), Some(RampState::Idle(_))) {
+                self.states.insert(tag.to_string(), RampState::Idle(now));
+            }
+            return value;
+        }
+
+        let state = match self.states.get(tag) {
+            None => RampState::Ramping(now),
+            Some(RampState::Idle(idle_since)) if now.saturating_duration_since(*idle_since) >= idle => RampState::Ramping(now),
+            Some(RampState::Idle(_)) | Some(RampState::Running) => RampState::Running,
+            Some(RampState::Ramping(ramp_start)) => RampState::Ramping(*ramp_start),
+        };
+        let (state, value) = match state {
+            RampState::Ramping(ramp_start) => {
+                if !matches!(self.states.get(tag), Some(RampState::Ramping(_))) {
+                    self.new_ramps.push(tag.to_string());
+                }
+                let progress = now.saturating_duration_since(ramp_start).as_secs_f64() / duration.as_secs_f64();
+                if progress >= 1.0 {
+                    (RampState::Running, value)
+                } else {
+                    (RampState::Ramping(ramp_start), value * progress)
+                }
+            }
+            state => (state, value),
+        };
+        self.states.insert(tag.to_string(), state);
+        value
+    }
+
+    /// if this motor is still ramping up to its commanded value
+    pub fn is_ramping(&self, tag: &str) -> bool {
+        matches!(self.states.get(tag), Some(RampState::Ramping(_)))
+    }
+
+    /// Take the motors that started ramping since the last call
+    pub fn take_new_ramps(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.new_ramps)
+    }
+
+    /// Forget every motor whose tag `keep` returns `false` for, for example because those motors were just halted.
+    /// Forgotten motors ramp up the next time they are turned on.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.states.retain(|tag, _| keep(tag));
+        self.new_ramps.retain(|tag| keep(tag));
+    }
+}
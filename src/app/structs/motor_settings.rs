@@ -6,6 +6,9 @@ use std::collections::HashMap;
 
 use buttplug::core::message::ActuatorType;
 
+use crate::config::v3::CommandConflictPolicy;
+use crate::util::extensions::FloatExtensions as _;
+
 /// Desired settings for all the motors in a single device
 #[derive(Default)]
 pub struct MotorSettings {
@@ -13,3 +16,207 @@ pub struct MotorSettings {
     pub rotate_map: HashMap<u32, (f64, bool)>,
     pub linear_map: HashMap<u32, (u32, f64)>,
 }
+
+/// The features of a device that can be sent both scalar commands and rotate or linear commands. Buttplug numbers the
+/// features for each command type separately, so scalar index 0 and rotate index 0 are usually different features.
+#[derive(Debug, Default, PartialEq)]
+pub struct SharedFeatures {
+    /// rotate index of each scalar index whose feature also takes rotate commands
+    pub rotate: HashMap<u32, u32>,
+    /// linear index of each scalar index whose feature also takes linear commands
+    pub linear: HashMap<u32, u32>,
+}
+
+impl SharedFeatures {
+    /// Buttplug lists a device's features in the same order for every command type, so the nth scalar `Rotate` feature
+    /// is the nth rotate feature, and the nth scalar `Position` feature is the nth linear feature. That only holds if
+    /// there are as many of one as the other, so otherwise no features are treated as shared.
+    pub fn new(scalar_actuator_types: &[ActuatorType], rotate_count: usize, linear_count: usize) -> Self {
+        let shared = |shared_type: ActuatorType, count: usize| -> HashMap<u32, u32> {
+            let scalar_indices: Vec<u32> = (0..).zip(scalar_actuator_types)
+                .filter(|(_, actuator_type)| **actuator_type == shared_type)
+                .map(|(scalar_index, _)| scalar_index)
+                .collect();
+            if scalar_indices.len() == count {
+                scalar_indices.into_iter().zip(0..).collect()
+            } else {
+                HashMap::new()
+            }
+        };
+        SharedFeatures {
+            rotate: shared(ActuatorType::Rotate, rotate_count),
+            linear: shared(ActuatorType::Position, linear_count),
+        }
+    }
+}
+
+impl MotorSettings {
+    /// Find features addressed by both a scalar command and the equivalent rotate or linear command, then resolve them
+    /// according to `policy`. Returns the scalar indices of any conflicting features.
+    pub fn resolve_conflicts(&mut self, shared_features: &SharedFeatures, policy: CommandConflictPolicy) -> Vec<u32> {
+        let conflicts: Vec<(u32, ActuatorType, u32)> = self.scalar_map.iter()
+            .filter_map(|(scalar_index, (_, actuator_type))| match actuator_type {
+                ActuatorType::Rotate => shared_features.rotate.get(scalar_index)
+                    .filter(|rotate_index| self.rotate_map.contains_key(rotate_index))
+                    .map(|rotate_index| (*scalar_index, *actuator_type, *rotate_index)),
+                ActuatorType::Position => shared_features.linear.get(scalar_index)
+                    .filter(|linear_index| self.linear_map.contains_key(linear_index))
+                    .map(|linear_index| (*scalar_index, *actuator_type, *linear_index)),
+                _ => None,
+            })
+            .collect();
+
+        for (scalar_index, actuator_type, other_index) in &conflicts {
+            match policy {
+                CommandConflictPolicy::SendBoth => (),
+                CommandConflictPolicy::PreferScalar => {
+                    if *actuator_type == ActuatorType::Rotate {
+                        self.rotate_map.remove(other_index);
+                    } else {
+                        self.linear_map.remove(other_index);
+                    }
+                }
+                CommandConflictPolicy::PreferRotateOrLinear => {
+                    self.scalar_map.remove(scalar_index);
+                }
+            }
+        }
+
+        conflicts.into_iter().map(|(scalar_index, _, _)| scalar_index).collect()
+    }
+
+    /// Snap scalar intensities to the levels the device actually supports. `step_counts` is indexed by feature index.
+    pub fn quantize_scalars(&mut self, step_counts: &[u32]) {
+        for (feature_index, (intensity, _)) in self.scalar_map.iter_mut() {
+            if let Some(step_count) = step_counts.get(*feature_index as usize) {
+                *intensity = intensity.quantize(*step_count);
+            }
+        }
+    }
+
+    /// Snap rotation speeds to the levels the device actually supports. `step_counts` is indexed by feature index.
+    pub fn quantize_rotations(&mut self, step_counts: &[u32]) {
+        for (feature_index, (speed, _)) in self.rotate_map.iter_mut() {
+            if let Some(step_count) = step_counts.get(*feature_index as usize) {
+                *speed = speed.quantize(*step_count);
+            }
+        }
+    }
+
+    /// Add the settings from `other`, replacing any settings already here for the same features
+    pub fn merge(&mut self, other: MotorSettings) {
+        self.scalar_map.extend(other.scalar_map);
+        self.rotate_map.extend(other.rotate_map);
+        self.linear_map.extend(other.linear_map);
+    }
+
+    /// Multiply all scalar intensities and rotation speeds by `scale`. Linear positions are left alone.
+    pub fn scale(&mut self, scale: f64) {
+        for (intensity, _) in self.scalar_map.values_mut() {
+            *intensity = (*intensity * scale).clamp(0.0, 1.0);
+        }
+        for (speed, _) in self.rotate_map.values_mut() {
+            *speed = (*speed * scale).clamp(0.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_replaces_settings_for_the_same_features() {
+        let mut motor_settings = MotorSettings {
+            scalar_map: HashMap::from([(0, (0.5, ActuatorType::Vibrate)), (1, (0.25, ActuatorType::Vibrate))]),
+            rotate_map: HashMap::from([(2, (0.5, true))]),
+            linear_map: HashMap::from([(3, (100, 0.5))]),
+        };
+        motor_settings.merge(MotorSettings {
+            scalar_map: HashMap::from([(1, (0.75, ActuatorType::Oscillate)), (4, (1.0, ActuatorType::Vibrate))]),
+            rotate_map: HashMap::from([(2, (0.25, false))]),
+            linear_map: HashMap::new(),
+        });
+
+        // features only set before are kept, and features set again take the newer setting
+        assert_eq!(motor_settings.scalar_map, HashMap::from([
+            (0, (0.5, ActuatorType::Vibrate)),
+            (1, (0.75, ActuatorType::Oscillate)),
+            (4, (1.0, ActuatorType::Vibrate)),
+        ]));
+        assert_eq!(motor_settings.rotate_map, HashMap::from([(2, (0.25, false))]));
+        assert_eq!(motor_settings.linear_map, HashMap::from([(3, (100, 0.5))]));
+    }
+
+    #[test]
+    fn merge_keeps_scalar_and_rotate_commands_for_one_feature_apart() {
+        let mut motor_settings = MotorSettings {
+            scalar_map: HashMap::from([(0, (0.5, ActuatorType::Rotate))]),
+            ..Default::default()
+        };
+        motor_settings.merge(MotorSettings {
+            rotate_map: HashMap::from([(0, (0.25, true))]),
+            ..Default::default()
+        });
+
+        // conflicts between them are resolved later, by resolve_conflicts, once the device is known
+        assert_eq!(motor_settings.scalar_map, HashMap::from([(0, (0.5, ActuatorType::Rotate))]));
+        assert_eq!(motor_settings.rotate_map, HashMap::from([(0, (0.25, true))]));
+    }
+
+    /// a vibrator with a rotator, which takes both scalar and rotate commands, and a stroker
+    fn shared_features() -> SharedFeatures {
+        SharedFeatures::new(&[ActuatorType::Vibrate, ActuatorType::Rotate, ActuatorType::Position], 1, 1)
+    }
+
+    #[test]
+    fn shared_features_are_matched_in_order() {
+        assert_eq!(shared_features(), SharedFeatures {
+            rotate: HashMap::from([(1, 0)]),
+            linear: HashMap::from([(2, 0)]),
+        });
+        // a rotator that only takes rotate commands can't be matched up with a scalar feature
+        assert_eq!(SharedFeatures::new(&[ActuatorType::Rotate], 2, 0), SharedFeatures::default());
+    }
+
+    #[test]
+    fn conflicts_follow_policy() {
+        let settings = || MotorSettings {
+            scalar_map: HashMap::from([(0, (0.5, ActuatorType::Vibrate)), (1, (0.5, ActuatorType::Rotate)), (2, (0.5, ActuatorType::Position))]),
+            rotate_map: HashMap::from([(0, (0.25, true))]),
+            linear_map: HashMap::from([(0, (100, 0.25))]),
+        };
+
+        let mut motor_settings = settings();
+        let mut conflicts = motor_settings.resolve_conflicts(&shared_features(), CommandConflictPolicy::SendBoth);
+        conflicts.sort_unstable();
+        assert_eq!(conflicts, vec![1, 2]);
+        assert_eq!(motor_settings.scalar_map.len(), 3);
+        assert_eq!(motor_settings.rotate_map.len(), 1);
+        assert_eq!(motor_settings.linear_map.len(), 1);
+
+        let mut motor_settings = settings();
+        motor_settings.resolve_conflicts(&shared_features(), CommandConflictPolicy::PreferScalar);
+        assert_eq!(motor_settings.scalar_map.len(), 3);
+        assert!(motor_settings.rotate_map.is_empty());
+        assert!(motor_settings.linear_map.is_empty());
+
+        let mut motor_settings = settings();
+        motor_settings.resolve_conflicts(&shared_features(), CommandConflictPolicy::PreferRotateOrLinear);
+        assert_eq!(motor_settings.scalar_map, HashMap::from([(0, (0.5, ActuatorType::Vibrate))]));
+        assert_eq!(motor_settings.rotate_map.len(), 1);
+        assert_eq!(motor_settings.linear_map.len(), 1);
+    }
+
+    #[test]
+    fn commands_for_different_features_do_not_conflict() {
+        // the vibrator is scalar index 0 and the rotator is rotate index 0, but they are different features
+        let mut motor_settings = MotorSettings {
+            scalar_map: HashMap::from([(0, (0.5, ActuatorType::Vibrate))]),
+            rotate_map: HashMap::from([(0, (0.25, true))]),
+            ..Default::default()
+        };
+        assert!(motor_settings.resolve_conflicts(&shared_features(), CommandConflictPolicy::PreferScalar).is_empty());
+        assert_eq!(motor_settings.rotate_map.len(), 1);
+    }
+}
@@ -0,0 +1,31 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+use tokio::task::AbortHandle;
+
+/// Device halts scheduled for after `/haptic` clients close their connection. A client that connects again with the
+/// same session token before its halt runs cancels it.
+pub type PendingHaltDb = Arc<RwLock<HashMap<PendingHaltKey, AbortHandle>>>;
+
+/// The client a pending halt belongs to
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum PendingHaltKey {
+    /// a client that connected with this session token
+    Session(String),
+    /// a client that connected without a session token, which nothing else can claim to be
+    Connection(u64),
+}
+
+impl PendingHaltKey {
+    pub fn new(connection_id: u64, session: Option<&str>) -> Self {
+        match session {
+            Some(session) => PendingHaltKey::Session(session.to_string()),
+            None => PendingHaltKey::Connection(connection_id),
+        }
+    }
+}
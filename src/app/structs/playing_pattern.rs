@@ -0,0 +1,11 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+use tokio::task::AbortHandle;
+
+/// The pattern or fade-out that is currently playing, if any. At most one plays at a time.
+pub type PlayingPatternDb = Arc<RwLock<Option<AbortHandle>>>;
@@ -0,0 +1,74 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use buttplug::core::message::ActuatorType;
+use tokio::sync::RwLock;
+
+pub type RunningMotorDb = Arc<RwLock<RunningMotors>>;
+
+/// The scalar and rotation motors that were last sent a non-zero value, keyed by buttplug device index and then feature
+/// index, so the keepalive knows what to refresh. Unlike the quantization caches this is kept whether or not
+/// quantization is on. It must be cleared whenever devices are halted.
+#[derive(Debug, Default)]
+pub struct RunningMotors {
+    scalars: HashMap<u32, HashMap<u32, (f64, ActuatorType)>>,
+    rotations: HashMap<u32, HashMap<u32, (f64, bool)>>,
+}
+
+impl RunningMotors {
+    /// Remember scalar commands that were just sent to a device
+    pub fn record_scalars(&mut self, device_index: u32, scalar_map: &HashMap<u32, (f64, ActuatorType)>) {
+        let scalars = self.scalars.entry(device_index).or_default();
+        scalars.extend(scalar_map);
+        scalars.retain(|_, (intensity, _)| *intensity != 0.0);
+    }
+
+    /// Remember rotate commands that were just sent to a device
+    pub fn record_rotations(&mut self, device_index: u32, rotate_map: &HashMap<u32, (f64, bool)>) {
+        let rotations = self.rotations.entry(device_index).or_default();
+        rotations.extend(rotate_map);
+        rotations.retain(|_, (speed, _)| *speed != 0.0);
+    }
+
+    /// the running scalar motors of a device
+    pub fn scalars(&self, device_index: u32) -> HashMap<u32, (f64, ActuatorType)> {
+        self.scalars.get(&device_index).cloned().unwrap_or_default()
+    }
+
+    /// the running rotation motors of a device
+    pub fn rotations(&self, device_index: u32) -> HashMap<u32, (f64, bool)> {
+        self.rotations.get(&device_index).cloned().unwrap_or_default()
+    }
+
+    pub fn forget_device(&mut self, device_index: u32) {
+        self.scalars.remove(&device_index);
+        self.rotations.remove(&device_index);
+    }
+
+    pub fn clear(&mut self) {
+        self.scalars.clear();
+        self.rotations.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stopped_motors_are_forgotten() {
+        let mut running_motors = RunningMotors::default();
+        running_motors.record_scalars(0, &HashMap::from([(0, (0.5, ActuatorType::Vibrate)), (1, (0.25, ActuatorType::Vibrate))]));
+        running_motors.record_rotations(0, &HashMap::from([(0, (0.5, false))]));
+        running_motors.record_scalars(0, &HashMap::from([(1, (0.0, ActuatorType::Vibrate))]));
+        running_motors.record_rotations(0, &HashMap::from([(0, (0.0, true))]));
+
+        assert_eq!(running_motors.scalars(0), HashMap::from([(0, (0.5, ActuatorType::Vibrate))]));
+        assert!(running_motors.rotations(0).is_empty());
+        assert!(running_motors.scalars(1).is_empty());
+    }
+}
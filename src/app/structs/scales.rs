@@ -0,0 +1,29 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+
+/// Live multipliers for the values sent to motors, keyed by what they scale. A scale of 1 is never stored, so
+/// anything missing is unscaled. Scales only live in memory, and reset when buttplug-lite restarts.
+pub type ScaleDb = Arc<RwLock<HashMap<String, f64>>>;
+
+/// Intensity multipliers for every motor of a device, keyed by device identifier, so a device can be made stronger or
+/// weaker without retagging it
+pub type DeviceScaleDb = ScaleDb;
+
+/// Master scales of the configured motor groups, keyed by group name. A motor in several groups is scaled by each.
+pub type GroupScaleDb = ScaleDb;
+
+/// set a live multiplier, where a multiplier of 1 removes it entirely
+pub async fn set_scale(scale_db: &ScaleDb, key: String, scale: f64) {
+    let mut scales = scale_db.write().await;
+    if scale == 1.0 {
+        scales.remove(&key);
+    } else {
+        scales.insert(key, scale);
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OwnedMutexGuard;
+
+use crate::app::structs::{LastCommandDb, RunningMotorDb, SentRotationDb, SentScalarDb};
+
+/// What was last sent to each device.
+/// The caches keyed by buttplug device index must be forgotten whenever a device comes or goes, as devices don't
+/// remember what they were sent once they disconnect, and a newly connected device may reuse the index of one that left.
+/// `last_commands` is keyed by device identifier instead, and outlives disconnects so devices can be resumed.
+#[derive(Clone, Debug, Default)]
+pub struct SentCommandDbs {
+    pub scalars: SentScalarDb,
+    pub rotations: SentRotationDb,
+    pub last_commands: LastCommandDb,
+    pub running_motors: RunningMotorDb,
+    /// one lock per device index, see [`SentCommandDbs::lock_device`]
+    send_locks: Arc<Mutex<HashMap<u32, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl SentCommandDbs {
+    /// Wait until nothing else is sending to the device with this index. Hold the returned guard from reading the
+    /// device's caches until they have been updated with what was sent, so a send that read them earlier can't
+    /// overwrite a newer command. Sends to other devices aren't held up.
+    pub async fn lock_device(&self, device_index: u32) -> OwnedMutexGuard<()> {
+        let send_lock = self.send_locks.lock().unwrap_or_else(|e| e.into_inner())
+            .entry(device_index)
+            .or_default()
+            .clone();
+        send_lock.lock_owned().await
+    }
+
+    /// Forget what was sent to the device with this index
+    pub async fn forget_device(&self, device_index: u32) {
+        self.scalars.write().await.remove(&device_index);
+        self.rotations.write().await.remove(&device_index);
+        self.running_motors.write().await.forget_device(device_index);
+    }
+
+    /// Forget what was sent to every device index, for example because the device server restarted and indices start over
+    pub async fn clear(&self) {
+        self.scalars.write().await.clear();
+        self.rotations.write().await.clear();
+        self.running_motors.write().await.clear();
+    }
+}
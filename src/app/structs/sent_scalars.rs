@@ -0,0 +1,15 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use buttplug::core::message::ActuatorType;
+use tokio::sync::RwLock;
+
+/// The last scalar command successfully sent to each device feature, keyed by buttplug device index and then feature index.
+pub type SentScalars = HashMap<u32, HashMap<u32, (f64, ActuatorType)>>;
+
+/// Shared [`SentScalars`]. This is used to skip commands that would not change anything, so it must be cleared whenever devices are halted.
+pub type SentScalarDb = Arc<RwLock<SentScalars>>;
@@ -0,0 +1,36 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+
+pub type TagClaimDb = Arc<RwLock<TagClaims>>;
+
+/// Tags claimed by `/haptic` connections, keyed by tag name. Commands for a claimed tag are only accepted from the
+/// connection that claimed it.
+#[derive(Debug, Default)]
+pub struct TagClaims {
+    claims: HashMap<String, u64>,
+}
+
+impl TagClaims {
+    /// Claim tags for a connection. Tags already claimed by another connection stay with it, and are returned.
+    pub fn claim<'a>(&mut self, connection: u64, tags: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+        tags.into_iter()
+            .filter(|tag| *self.claims.entry(tag.to_string()).or_insert(connection) != connection)
+            .collect()
+    }
+
+    /// if a connection may command this tag, as it is either unclaimed or claimed by that connection
+    pub fn allows(&self, connection: u64, tag: &str) -> bool {
+        self.claims.get(tag).is_none_or(|owner| *owner == connection)
+    }
+
+    /// Release every tag claimed by a connection, for example because it disconnected
+    pub fn release(&mut self, connection: u64) {
+        self.claims.retain(|_, owner| *owner != connection);
+    }
+}
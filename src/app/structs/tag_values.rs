@@ -0,0 +1,13 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+
+/// Last value commanded for each tag, keyed by tag name.
+/// Scalar values are intensities in `[0, 1]`, rotation values are signed speeds in `[-1, 1]`.
+/// Linear motors are not tracked, as their commands are movements rather than a steady state.
+pub type TagValueDb = Arc<RwLock<HashMap<String, f64>>>;
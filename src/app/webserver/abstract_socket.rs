@@ -0,0 +1,24 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! Listens on a Linux abstract namespace socket, which is named rather than living at a filesystem path
+
+use std::io;
+use std::os::linux::net::SocketAddrExt as _;
+use std::os::unix::net::{SocketAddr, UnixListener as StdUnixListener};
+
+use futures::Stream;
+use tokio::net::{UnixListener, UnixStream};
+
+/// Bind the abstract socket `name`, returning its incoming connections. The socket goes away once the stream is dropped.
+pub fn bind(name: &str) -> io::Result<impl Stream<Item=io::Result<UnixStream>> + Send> {
+    let address = SocketAddr::from_abstract_name(name.as_bytes())?;
+    let listener = StdUnixListener::bind_addr(&address)?;
+    listener.set_nonblocking(true)?;
+    let listener = UnixListener::from_std(listener)?;
+    Ok(futures::stream::unfold(listener, |listener| async move {
+        let connection = listener.accept().await.map(|(stream, _)| stream);
+        Some((connection, listener))
+    }))
+}
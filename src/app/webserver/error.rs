@@ -0,0 +1,39 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::error::Error as _;
+use std::io;
+
+use thiserror::Error;
+
+/// Errors that can stop the web server
+#[derive(Debug, Error)]
+pub enum WebserverError {
+    #[error("port {0} is already in use")]
+    PortInUse(u16),
+    #[error("failed to start web server: {0}")]
+    Bind(warp::Error),
+}
+
+impl WebserverError {
+    /// classify an error from binding the web server to `port`
+    pub fn from_bind_error(port: u16, error: warp::Error) -> WebserverError {
+        let mut source = error.source();
+        while let Some(cause) = source {
+            if cause.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::AddrInUse) {
+                return WebserverError::PortInUse(port);
+            }
+            source = cause.source();
+        }
+        WebserverError::Bind(error)
+    }
+
+    /// process exit code to use when this error prevents the application from starting
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WebserverError::PortInUse(_) => 2,
+            WebserverError::Bind(_) => 1,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! A synthetic device and configuration, shared by the `--test-pipeline` check and the webserver's unit tests
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::app::structs::{MotorHolds, MotorRamps, MotorSettings};
+use crate::app::webserver::routes::build_vibration_map;
+use crate::app::webserver::structs::{DeviceId, PipelineState};
+use crate::config::v3::{ConfigurationV3, MotorConfigurationV3, MotorTypeV3};
+
+pub(super) static DEVICE_NAME: &str = "Test Device";
+pub(super) static DEVICE_IDENTIFIER: &str = "test://device";
+
+/// a configuration with the given tags, each pointing at a motor of the synthetic device
+pub(super) fn configuration(tags: &[(&str, MotorTypeV3, u32)]) -> ConfigurationV3 {
+    let mut configuration = ConfigurationV3::default();
+    for (tag, feature_type, feature_index) in tags {
+        configuration.tags_mut().insert(tag.to_string(), motor(feature_type.clone(), *feature_index));
+    }
+    configuration
+}
+
+/// a motor of the synthetic device
+pub(super) fn motor(feature_type: MotorTypeV3, feature_index: u32) -> MotorConfigurationV3 {
+    MotorConfigurationV3 {
+        device_name: DEVICE_NAME.to_string(),
+        device_identifier: Some(DEVICE_IDENTIFIER.to_string()),
+        feature_index,
+        feature_type,
+        enabled: true,
+        min_on_time_ms: None,
+        watchdog_exempt: false,
+        response_curve: None,
+    }
+}
+
+/// the key the synthetic device's settings are found under in a device map
+pub(super) fn device_id() -> DeviceId {
+    DeviceId {
+        name: DEVICE_NAME.to_string(),
+        identifier: Some(DEVICE_IDENTIFIER.to_string()),
+    }
+}
+
+/// A configuration and the state commands for it are applied to, owned in one place so commands can be sent without a
+/// device server. The state carries over from one command to the next, as it does in the webserver.
+#[derive(Default)]
+pub(super) struct Pipeline {
+    pub configuration: ConfigurationV3,
+    pub tag_values: HashMap<String, f64>,
+    pub device_scales: HashMap<String, f64>,
+    pub group_scales: HashMap<String, f64>,
+    pub motor_holds: MotorHolds,
+    pub motor_ramps: MotorRamps,
+}
+
+impl Pipeline {
+    pub fn new(configuration: ConfigurationV3) -> Self {
+        Pipeline {
+            configuration,
+            ..Default::default()
+        }
+    }
+
+    /// build the settings a command produces for each device
+    pub fn send(&mut self, command: &str) -> Result<HashMap<DeviceId, MotorSettings>, String> {
+        self.send_at(Instant::now(), command)
+    }
+
+    /// build the settings a command produces for each device, as if it arrived at `now`
+    pub fn send_at(&mut self, now: Instant, command: &str) -> Result<HashMap<DeviceId, MotorSettings>, String> {
+        let state = PipelineState {
+            tag_values: &mut self.tag_values,
+            device_scales: &self.device_scales,
+            group_scales: &self.group_scales,
+            motor_holds: &mut self.motor_holds,
+            motor_ramps: &mut self.motor_ramps,
+        };
+        build_vibration_map(&self.configuration, state, now, command)
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! Sends MIDI control changes read from a MIDI input port to tags, the same way `/haptic` messages are sent
+
+use std::time::Duration;
+
+use midir::{MidiInput, MidiInputConnection};
+use tokio::sync::mpsc;
+use tokio::{task, time};
+use tracing::{debug, info, warn};
+
+use crate::app::webserver::routes::{apply_haptic_message, HapticState};
+use crate::util::watchdog;
+use crate::util::watchdog::WatchdogTimeoutDb;
+
+static LOG_PREFIX_MIDI_INPUT: &str = "midi_input";
+
+/// how long to wait before opening a MIDI input port again after it fails or goes away. This is also how often an open
+/// port is checked for, as MIDI input doesn't say when a port goes away.
+const MIDI_PORT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// the name buttplug-lite's MIDI input goes by in the system's list of MIDI clients
+const MIDI_CLIENT_NAME: &str = "buttplug-lite";
+
+/// A MIDI control change, with the channel counted from 1
+#[derive(Debug, PartialEq, Eq)]
+struct ControlChange {
+    channel: u8,
+    controller: u8,
+    value: u8,
+}
+
+/// Picks control changes out of a raw MIDI byte stream, following running status and skipping every other message
+#[derive(Default)]
+struct MidiParser {
+    status: Option<u8>,
+    first_data: Option<u8>,
+}
+
+impl MidiParser {
+    fn push(&mut self, byte: u8) -> Option<ControlChange> {
+        match byte {
+            // real-time messages may appear anywhere, even in the middle of another message, and leave it alone
+            0xF8..=0xFF => None,
+            // system messages end running status, and their data, including system exclusive data, is skipped
+            0xF0..=0xF7 => {
+                self.status = None;
+                self.first_data = None;
+                None
+            }
+            0x80..=0xEF => {
+                self.status = Some(byte);
+                self.first_data = None;
+                None
+            }
+            data => {
+                let status = self.status?;
+                // program change and channel pressure messages only have one data byte
+                if matches!(status & 0xF0, 0xC0 | 0xD0) {
+                    return None;
+                }
+                match self.first_data.take() {
+                    None => {
+                        self.first_data = Some(data);
+                        None
+                    }
+                    Some(first_data) => (status & 0xF0 == 0xB0).then_some(ControlChange {
+                        channel: (status & 0x0F) + 1,
+                        controller: first_data,
+                        value: data,
+                    }),
+                }
+            }
+        }
+    }
+}
+
+/// Names of the MIDI input ports that can be read from
+pub fn input_port_names() -> Result<Vec<String>, String> {
+    let midi_input = MidiInput::new(MIDI_CLIENT_NAME).map_err(|e| e.to_string())?;
+    Ok(midi_input.ports().iter().filter_map(|port| midi_input.port_name(port).ok()).collect())
+}
+
+/// Read control changes forever from the first input port whose name contains `port`, or from the first input port if
+/// no port is given. The port is opened again whenever it fails or goes away.
+pub(super) async fn listen(port: Option<String>, haptic_state: HapticState, watchdog_time: WatchdogTimeoutDb) {
+    // control changes arrive on a thread of the MIDI backend's, so they're sent from here in the order they arrived
+    let (control_change_tx, mut control_change_rx) = mpsc::unbounded_channel();
+    task::spawn(async move {
+        while let Some(control_change) = control_change_rx.recv().await {
+            send_control_change(&control_change, &haptic_state, &watchdog_time).await;
+        }
+    });
+
+    loop {
+        let connect_port = port.clone();
+        let connect_control_change_tx = control_change_tx.clone();
+        match task::spawn_blocking(move || connect(connect_port.as_deref(), connect_control_change_tx)).await {
+            Ok(Ok((connection, name))) => {
+                info!("{LOG_PREFIX_MIDI_INPUT}: reading MIDI input port {name:?}");
+                loop {
+                    time::sleep(MIDI_PORT_RETRY_INTERVAL).await;
+                    let check_name = name.clone();
+                    let available = task::spawn_blocking(move || input_port_names().is_ok_and(|names| names.contains(&check_name))).await;
+                    if !matches!(available, Ok(true)) {
+                        break;
+                    }
+                }
+                drop(connection);
+                warn!("{LOG_PREFIX_MIDI_INPUT}: MIDI input port {name:?} went away");
+            }
+            Ok(Err(e)) => warn!("{LOG_PREFIX_MIDI_INPUT}: failed to open MIDI input port: {e}"),
+            Err(e) => warn!("{LOG_PREFIX_MIDI_INPUT}: failed to open MIDI input port: {e}"),
+        }
+        time::sleep(MIDI_PORT_RETRY_INTERVAL).await;
+    }
+}
+
+/// Open the first input port whose name contains `port`, or the first input port if no port is given. Control changes
+/// read from it are sent to `control_change_tx`. Returns the connection, which closes the port when dropped, and the
+/// port's name.
+fn connect(port: Option<&str>, control_change_tx: mpsc::UnboundedSender<ControlChange>) -> Result<(MidiInputConnection<MidiParser>, String), String> {
+    let midi_input = MidiInput::new(MIDI_CLIENT_NAME).map_err(|e| e.to_string())?;
+    let ports: Vec<_> = midi_input.ports().into_iter()
+        .filter_map(|input_port| Some((midi_input.port_name(&input_port).ok()?, input_port)))
+        .collect();
+    let Some((name, input_port)) = ports.iter().find(|(name, _)| port.is_none_or(|port| name.contains(port))) else {
+        let names: Vec<&str> = ports.iter().map(|(name, _)| name.as_str()).collect();
+        return Err(match port {
+            Some(port) => format!("no port name contains {port:?}. The available ports are {names:?}"),
+            None => "there are no MIDI input ports".to_string(),
+        });
+    };
+    let name = name.clone();
+    let connection = midi_input.connect(input_port, MIDI_CLIENT_NAME, move |_, bytes, parser| {
+        for byte in bytes {
+            if let Some(control_change) = parser.push(*byte) {
+                // the sending task only stops when buttplug-lite does
+                let _ = control_change_tx.send(control_change);
+            }
+        }
+    }, MidiParser::default()).map_err(|e| e.to_string())?;
+    Ok((connection, name))
+}
+
+/// Send a control change's value to every tag mapped to its controller. Mappings are looked up in the current
+/// configuration, so changes to them apply right away.
+async fn send_control_change(control_change: &ControlChange, haptic_state: &HapticState, watchdog_time: &WatchdogTimeoutDb) {
+    let message: String = match haptic_state.application_state_db.read().await.as_ref().and_then(|application_state| application_state.configuration.midi_input.as_ref()) {
+        Some(midi_input) => midi_input.tags_for(control_change.channel, control_change.controller)
+            .map(|tag| format!("{tag}:{};", f64::from(control_change.value) / 127.0))
+            .collect(),
+        None => return,
+    };
+    if message.is_empty() {
+        debug!("{LOG_PREFIX_MIDI_INPUT}: ignoring unmapped controller {} on channel {}", control_change.controller, control_change.channel);
+        return;
+    }
+    match apply_haptic_message(&message, haptic_state, None).await {
+        Ok(_) => watchdog::feed(watchdog_time, &haptic_state.application_state_db).await,
+        Err(e) => debug!("{LOG_PREFIX_MIDI_INPUT}: could not send {message}: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parser_follows_running_status() {
+        let mut parser = MidiParser::default();
+        let control_changes: Vec<ControlChange> = [0xB1, 7, 64, 0xF8, 8, 127, 0x90, 60, 100, 0xC0, 5, 0xB0, 1, 0]
+            .into_iter()
+            .filter_map(|byte| parser.push(byte))
+            .collect();
+        assert_eq!(control_changes, vec![
+            ControlChange { channel: 2, controller: 7, value: 64 },
+            ControlChange { channel: 2, controller: 8, value: 127 },
+            ControlChange { channel: 1, controller: 1, value: 0 },
+        ]);
+    }
+}
@@ -2,10 +2,19 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
-pub use routes::start_webserver;
+pub use error::WebserverError;
+pub use midi::input_port_names as midi_input_port_names;
+pub use pipeline_test::run as run_pipeline_test;
+pub use routes::{start_webserver, HapticState, WebserverChannels};
 
 pub use shutdown_message::ShutdownMessage;
 
+#[cfg(target_os = "linux")]
+mod abstract_socket;
+mod error;
+mod fixtures;
+mod midi;
+mod pipeline_test;
 mod routes;
 mod shutdown_message;
 mod structs;
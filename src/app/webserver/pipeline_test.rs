@@ -0,0 +1,139 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! A hardware-free check of the haptic command pipeline, run with `--test-pipeline`.
+//! Each case feeds a wire command through the webserver's command parsing using a synthetic configuration, then checks the
+//! resulting device settings. This doubles as a worked example of how commands map to device settings.
+
+use buttplug::core::message::ActuatorType as ButtplugActuatorType;
+use tracing::info;
+
+use crate::app::structs::MotorSettings;
+use crate::app::webserver::fixtures::{configuration, device_id, Pipeline};
+use crate::config::v3::{ActuatorType, MotorTypeV3};
+
+/// a command to run, and a check of the settings it should produce for the synthetic device
+struct Case {
+    command: &'static str,
+    check: fn(&MotorSettings) -> Result<(), String>,
+}
+
+/// Run every case in order, logging each one that passes. Returns an error describing the first failure.
+pub fn run() -> Result<(), String> {
+    run_cases(cases())
+}
+
+fn run_cases(cases: Vec<Case>) -> Result<(), String> {
+    let mut pipeline = Pipeline::new(configuration(&[
+        ("vibe", MotorTypeV3::Scalar { actuator_type: ActuatorType::Vibrate }, 0),
+        ("spin", MotorTypeV3::Rotation, 1),
+        ("stroke", MotorTypeV3::Linear, 2),
+    ]));
+
+    // cases run in order and share tag values, so later relative adjustments build on earlier commands
+    for case in cases {
+        let device_map = pipeline.send(case.command)
+            .map_err(|e| format!("{:?} was rejected: {e}", case.command))?;
+        let default_settings = MotorSettings::default();
+        let motor_settings = device_map.get(&device_id()).unwrap_or(&default_settings);
+        (case.check)(motor_settings).map_err(|e| format!("{:?}: {e}", case.command))?;
+        info!("pipeline check passed: {:?}", case.command);
+    }
+    Ok(())
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        // scalar motors take a strength from 0 to 1
+        Case {
+            command: "vibe:0.5",
+            check: |settings| expect(settings.scalar_map.get(&0), Some(&(0.5, ButtplugActuatorType::Vibrate))),
+        },
+        // out of range values are clamped
+        Case {
+            command: "vibe:2",
+            check: |settings| expect(settings.scalar_map.get(&0), Some(&(1.0, ButtplugActuatorType::Vibrate))),
+        },
+        // relative adjustments start from the last value sent to the tag
+        Case {
+            command: "vibe-0.25",
+            check: |settings| expect(settings.scalar_map.get(&0), Some(&(0.75, ButtplugActuatorType::Vibrate))),
+        },
+        // rotation speed is signed, with the sign picking the direction
+        Case {
+            command: "spin:-0.5",
+            check: |settings| expect(settings.rotate_map.get(&1), Some(&(0.5, false))),
+        },
+        // rotation motors may take a second speed for the device's next rotate feature
+        Case {
+            command: "spin:0.5:-0.25",
+            check: |settings| {
+                expect(settings.rotate_map.get(&1), Some(&(0.5, true)))?;
+                expect(settings.rotate_map.get(&2), Some(&(0.25, false)))
+            },
+        },
+        // linear motors take a duration in milliseconds and a position from 0 to 1
+        Case {
+            command: "stroke:500:0.25",
+            check: |settings| expect(settings.linear_map.get(&2), Some(&(500, 0.25))),
+        },
+        // several commands may be sent at once, and unknown tags are ignored
+        Case {
+            command: "vibe:0;spin:1;unknown:1",
+            check: |settings| {
+                expect(settings.scalar_map.get(&0), Some(&(0.0, ButtplugActuatorType::Vibrate)))?;
+                expect(settings.rotate_map.get(&1), Some(&(1.0, true)))
+            },
+        },
+    ]
+}
+
+fn expect<T: PartialEq + std::fmt::Debug>(actual: T, expected: T) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {expected:?}, got {actual:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_check_passes() {
+        assert_eq!(run(), Ok(()));
+    }
+
+    #[test]
+    fn pipeline_check_reports_failed_case() {
+        let cases = vec![
+            Case {
+                command: "vibe:0.5",
+                check: |settings| expect(settings.scalar_map.get(&0), Some(&(0.5, ButtplugActuatorType::Vibrate))),
+            },
+            Case {
+                command: "vibe:0.25",
+                check: |settings| expect(settings.scalar_map.get(&0), Some(&(0.5, ButtplugActuatorType::Vibrate))),
+            },
+            Case {
+                command: "vibe:0",
+                check: |_| Err("should not run".to_string()),
+            },
+        ];
+        assert_eq!(run_cases(cases), Err(r#""vibe:0.25": expected Some((0.5, Vibrate)), got Some((0.25, Vibrate))"#.to_string()));
+    }
+
+    #[test]
+    fn pipeline_check_reports_rejected_command() {
+        let cases = vec![
+            Case {
+                command: "vibe:loud",
+                check: |_| Ok(()),
+            },
+        ];
+        let error = run_cases(cases).unwrap_err();
+        assert!(error.starts_with(r#""vibe:loud" was rejected: "#), "{error}");
+    }
+}
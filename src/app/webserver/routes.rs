@@ -2,42 +2,108 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::ops::{Deref as _, DerefMut as _};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use buttplug::client::{LinearCommand, RotateCommand, ScalarCommand};
-use buttplug::core::message::ButtplugDeviceMessageType;
-use futures::StreamExt as _;
-use tokio::sync::{mpsc, oneshot};
-use tokio::task;
-use tracing::{debug, error, info, warn};
+use buttplug::client::{ButtplugClientDevice, LinearCommand, RotateCommand, ScalarCommand};
+use buttplug::core::message::{ActuatorType as ButtplugActuatorType, ButtplugDeviceMessageType};
+use chrono::{SecondsFormat, Utc};
+use futures::stream::SplitSink;
+use futures::{SinkExt as _, StreamExt as _};
+use percent_encoding::percent_decode_str;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::{task, time};
+use tracing::{Instrument as _, debug, debug_span, error, info, warn};
 use warp::Filter;
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
 
-use crate::app::structs::{ApplicationStateDb, MotorSettings};
+use crate::app::structs::{set_scale, ApplicationState, ApplicationStateDb, BatteryHistoryDb, DeviceScaleDb, DeviceServerRetryDb, GroupScaleDb, HapticErrorDb, HapticSessionDb, MotorHoldDb, MotorRampDb, MotorSettings, PendingHaltDb, PendingHaltKey, PlayingPatternDb, SentCommandDbs, TagClaimDb, TagValueDb};
+#[cfg(target_os = "linux")]
+use crate::app::webserver::abstract_socket;
+use crate::app::webserver::error::WebserverError;
+use crate::app::webserver::midi;
 use crate::app::webserver::shutdown_message::ShutdownMessage;
-use crate::app::webserver::structs::DeviceId;
+use crate::app::webserver::structs::{CoalescedCommands, DeliveryReport, DeviceId, MonitoredMessage, MotorCapabilities, PipelineState};
 use crate::buttplug as app_buttplug;
-use crate::config::v3::{ConfigurationV3, MotorTypeV3};
+use crate::config;
+use crate::config::ConfigError;
+use crate::config::CONFIG_DIR_FILE_PATH;
+use crate::gui::subscription::ApplicationStatusEvent;
+use crate::config::v3::{ActuatorType, CommandConflictPolicy, CommManager, CompositeTagMemberV3, ConfigurationV3, DuplicateTagPolicy, MotorConfigurationV3, MotorTypeV3, MAX_WATCHDOG_TIMEOUT_MS};
 use crate::util::extensions::FloatExtensions as _;
 use crate::util::watchdog;
 use crate::util::watchdog::WatchdogTimeoutDb;
 
 static LOG_PREFIX_HAPTIC_ENDPOINT: &str = "/haptic";
+static LOG_PREFIX_MONITOR_ENDPOINT: &str = "/monitor";
+
+/// Source of ids used to tell connections apart in `/haptic` logs and spans
+static NEXT_HAPTIC_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+// far larger than any reasonable list of communication manager changes
+const MAX_COMM_MANAGER_BODY_BYTES: u64 = 1024;
+
+// far larger than any reasonable configuration
+const MAX_CONFIG_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Longest `/haptic` session token accepted
+const MAX_SESSION_TOKEN_LENGTH: usize = 128;
+
+/// How often a motor that is ramping up or fading out is sent a new value
+const RAMP_STEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Longest fade-out accepted by `POST /fadeout`
+const MAX_FADEOUT_MS: u64 = 60_000;
+
+/// How many processed messages are buffered for each `/monitor` connection. Slower observers miss messages.
+const MONITOR_CHANNEL_CAPACITY: usize = 256;
+
+/// The channels the web server is started, restarted and shut down through
+pub struct WebserverChannels {
+    pub application_status_sender: mpsc::UnboundedSender<ApplicationStatusEvent>,
+    /// the web server waits on this for the configuration to be loaded, as that has the port to listen on
+    pub initial_config_loaded_rx: oneshot::Receiver<()>,
+    /// whether a device has connected, for the `wait_for_device` option
+    pub device_connected_rx: watch::Receiver<bool>,
+    /// sent once the web server is first listening
+    pub gui_start_tx: oneshot::Sender<()>,
+    pub warp_shutdown_initiate_rx: mpsc::UnboundedReceiver<ShutdownMessage>,
+    pub warp_shutdown_complete_tx: oneshot::Sender<Result<(), WebserverError>>,
+}
 
 pub fn start_webserver(
-    application_state_db: ApplicationStateDb,
+    haptic_state: HapticState,
     watchdog_timeout_db: WatchdogTimeoutDb,
-    initial_config_loaded_rx: oneshot::Receiver<()>,
-    gui_start_tx: oneshot::Sender<()>,
-    mut warp_shutdown_initiate_rx: mpsc::UnboundedReceiver<ShutdownMessage>,
-    warp_shutdown_complete_tx: oneshot::Sender<()>,
-
+    battery_history_db: BatteryHistoryDb,
+    device_server_retry_db: DeviceServerRetryDb,
+    channels: WebserverChannels,
 ) {
-    // GET / => 200 OK with body application name and version
+    let WebserverChannels {
+        application_status_sender,
+        initial_config_loaded_rx,
+        mut device_connected_rx,
+        gui_start_tx,
+        mut warp_shutdown_initiate_rx,
+        warp_shutdown_complete_tx,
+    } = channels;
+    let application_state_db = haptic_state.application_state_db.clone();
+    let device_scale_db = haptic_state.device_scale_db.clone();
+    let group_scale_db = haptic_state.group_scale_db.clone();
+
+    // GET / => 200 OK with body application name and version, followed by the instance name if one is configured
     let info = warp::path::end()
         .and(warp::get())
-        .map(|| format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+        .and(with_db(application_state_db.clone()))
+        .and_then(info_handler);
 
     // GET /hapticstatus => 200 OK with body containing haptic status
     let hapticstatus = warp::path("hapticstatus")
@@ -49,35 +115,230 @@ pub fn start_webserver(
     let batterystatus = warp::path("batterystatus")
         .and(warp::get())
         .and(with_db(application_state_db.clone()))
+        .and(with_db(battery_history_db.clone()))
         .and_then(battery_status_handler);
 
+    // GET /batteryhistory => JSON object of each device's recent battery readings, oldest first
+    let batteryhistory = warp::path("batteryhistory")
+        .and(warp::get())
+        .and(with_db(application_state_db.clone()))
+        .and(with_db(battery_history_db))
+        .and_then(battery_history_handler);
+
     // GET /batterystatus => list of battery levels, spaced with newlines
     let deviceconfig = warp::path("deviceconfig")
         .and(warp::get())
         .and(with_db(application_state_db.clone()))
         .and_then(device_config_handler);
 
-    // WEBSOCKET /haptic
+    // GET /motors => JSON list of configured motors with the ranges and step counts their devices report
+    let motors = warp::path("motors")
+        .and(warp::get())
+        .and(with_db(application_state_db.clone()))
+        .and(with_db(device_scale_db.clone()))
+        .and_then(motors_handler);
+
+    // GET /tags/{tag} => 200 OK with the tag's motor configuration, or 404 if the tag is not configured
+    let tag = warp::path!("tags" / String)
+        .and(warp::get())
+        .and(with_db(application_state_db.clone()))
+        .and_then(tag_handler);
+
+    // POST /device/{identifier}/scale?value=0.5 => set a live intensity multiplier for all of a device's motors
+    let devicescale = warp::path!("device" / String / "scale")
+        .and(warp::post())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db(application_state_db.clone()))
+        .and(with_db(device_scale_db.clone()))
+        .and_then(device_scale_handler);
+
+    // POST /group/{name}/scale?value=0.5 => set the live master scale of a motor group
+    let groupscale = warp::path!("group" / String / "scale")
+        .and(warp::post())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db(application_state_db.clone()))
+        .and(with_db(group_scale_db.clone()))
+        .and_then(group_scale_handler);
+
+    // GET /commmanagers => list of device communication managers and whether they are enabled
+    let commmanagers = warp::path("commmanagers")
+        .and(warp::get())
+        .and(with_db(application_state_db.clone()))
+        .and_then(comm_managers_handler);
+
+    // POST /commmanagers => enable or disable device communication managers, then restart the device server
+    let commmanagers_update = warp::path("commmanagers")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_COMM_MANAGER_BODY_BYTES))
+        .and(warp::body::bytes())
+        .and(with_db(application_state_db.clone()))
+        .and_then(comm_managers_update_handler);
+
+    // GET /example => a ready-to-send example message commanding every enabled tag in the live configuration
+    let example = warp::path("example")
+        .and(warp::get())
+        .and(with_db(application_state_db.clone()))
+        .and_then(example_handler);
+
+    // POST /config/validate => 200 OK if the TOML or JSON configuration in the body is valid, or 400 with one problem per line. Nothing is applied.
+    let config_validate = warp::path!("config" / "validate")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_CONFIG_BODY_BYTES))
+        .and(warp::body::bytes())
+        .and_then(config_validate_handler);
+
+    // POST /watchdog/timeout?ms=15000 => change the watchdog timeout for this run, or also save it with &persist=true
+    let watchdog_timeout = warp::path!("watchdog" / "timeout")
+        .and(warp::post())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db(application_state_db.clone()))
+        .and(with_db(watchdog_timeout_db.clone()))
+        .and(with_db(application_status_sender))
+        .and_then(watchdog_timeout_handler);
+
+    // POST /scanning?enabled=false => stop scanning for devices, or start again with enabled=true
+    let scanning = warp::path("scanning")
+        .and(warp::post())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db(application_state_db.clone()))
+        .and_then(scanning_handler);
+
+    // GET /healthz => 200 OK if the device server is running, or 503 explaining why not
+    let healthz = warp::path("healthz")
+        .and(warp::get())
+        .and(with_db(application_state_db.clone()))
+        .and(with_db(device_server_retry_db.clone()))
+        .and_then(healthz_handler);
+
+    // POST /reconnect => start trying to start the device server again, after too many failed attempts made it give up
+    let reconnect = warp::path("reconnect")
+        .and(warp::post())
+        .and(with_db(device_server_retry_db))
+        .and_then(reconnect_handler);
+
+    // GET /errors => JSON list of the most recently rejected /haptic messages, oldest first
+    let errors = warp::path("errors")
+        .and(warp::get())
+        .and(with_db(haptic_state.haptic_error_db.clone()))
+        .and_then(errors_handler);
+
+    // DELETE /errors => forget the recently rejected /haptic messages
+    let errors_clear = warp::path("errors")
+        .and(warp::delete())
+        .and(with_db(haptic_state.haptic_error_db.clone()))
+        .and_then(errors_clear_handler);
+
+    // WEBSOCKET /monitor => read-only stream of every processed /haptic message as JSON, if enabled
+    let monitor = warp::path("monitor")
+        .and(monitor_enabled(application_state_db.clone()))
+        .and(warp::ws())
+        .and(with_db(haptic_state.monitor_tx.clone()))
+        .map(|ws: warp::ws::Ws, monitor_tx: broadcast::Sender<MonitoredMessage>| {
+            let monitor_rx = monitor_tx.subscribe();
+            ws.on_upgrade(|ws| monitor_handler(ws, monitor_rx))
+        });
+
+    // POST /pattern/{name} => start playing a pattern from the configuration, or 404 if there is no such pattern
+    let pattern = warp::path!("pattern" / String)
+        .and(warp::post())
+        .and(with_db(haptic_state.clone()))
+        .and(with_db(watchdog_timeout_db.clone()))
+        .and_then(pattern_handler);
+
+    // POST /fadeout?ms=3000 => ramp every running scalar and rotation motor down to zero over the given time
+    let fadeout = warp::path("fadeout")
+        .and(warp::post())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db(haptic_state.clone()))
+        .and(with_db(watchdog_timeout_db.clone()))
+        .and_then(fadeout_handler);
+
+    // WEBSOCKET /haptic, or /haptic?session={token} to resume a session, and/or /haptic?claim={tag};{tag} to claim tags
     let haptic = warp::path("haptic")
         .and(warp::ws())
-        .and(with_db(application_state_db.clone()))
+        .and(max_websocket_message_bytes(application_state_db.clone()))
+        .map(|ws: warp::ws::Ws, max_message_bytes: Option<usize>| match max_message_bytes {
+            Some(max_message_bytes) => ws.max_message_size(max_message_bytes).max_frame_size(max_message_bytes),
+            None => ws,
+        })
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db(haptic_state.clone()))
         .and(with_db(watchdog_timeout_db.clone()))
-        .map(|ws: warp::ws::Ws, application_state_db: ApplicationStateDb, haptic_watchdog_db: WatchdogTimeoutDb| {
-            ws.on_upgrade(|ws| haptic_handler(ws, application_state_db, haptic_watchdog_db))
+        .map(|ws: warp::ws::Ws, mut query: HashMap<String, String>, haptic_state: HapticState, haptic_watchdog_db: WatchdogTimeoutDb| {
+            let session = query.remove("session").filter(|session| !session.is_empty());
+            let claim = query.remove("claim").filter(|claim| !claim.is_empty());
+            ws.on_upgrade(|ws| haptic_handler(ws, session, claim, haptic_state, haptic_watchdog_db))
         });
 
-    let routes = info
-        .or(hapticstatus)
+    // status responses are compressed if the client supports it, as they can get quite large with many devices
+    let status = hapticstatus
         .or(batterystatus)
+        .or(batteryhistory)
         .or(deviceconfig)
+        .or(motors);
+    let gzip_status = accepts_encoding("gzip")
+        .and(status.clone())
+        .with(warp::filters::compression::gzip());
+    let deflate_status = accepts_encoding("deflate")
+        .and(status.clone())
+        .with(warp::filters::compression::deflate());
+
+    let routes = info
+        .or(healthz)
+        .or(reconnect)
+        .or(gzip_status)
+        .or(deflate_status)
+        .or(status)
+        .or(tag)
+        .or(devicescale)
+        .or(groupscale)
+        .or(commmanagers)
+        .or(commmanagers_update)
+        .or(config_validate)
+        .or(example)
+        .or(watchdog_timeout)
+        .or(scanning)
+        .or(errors)
+        .or(errors_clear)
+        .or(pattern)
+        .or(fadeout)
+        .or(monitor)
         .or(haptic);
 
     // moved into the following task
     let reconnect_task_application_state_db_clone = application_state_db.clone();
+    let midi_haptic_state = haptic_state;
     task::spawn(async move {
         initial_config_loaded_rx.await.expect("failed to load initial configuration");
 
+        // MIDI input is only set up once, but its mappings are read from the current configuration
+        let midi_port = reconnect_task_application_state_db_clone.read().await.as_ref()
+            .and_then(|application_state| application_state.configuration.midi_input.as_ref())
+            .map(|midi_input| midi_input.port.clone());
+        if let Some(midi_port) = midi_port {
+            task::spawn(midi::listen(midi_port, midi_haptic_state, watchdog_timeout_db));
+        }
+
         let mut gui_start_oneshot_tx = Some(gui_start_tx); // will get None'd after the first loop
+        let mut result = Ok(());
+
+        let wait_for_device = reconnect_task_application_state_db_clone.read().await.as_ref()
+            .is_some_and(|application_state| application_state.configuration.wait_for_device);
+        if wait_for_device && !*device_connected_rx.borrow() {
+            // the GUI still waits for the web server, as the startup command is run once the GUI starts and expects the
+            // web server to be listening
+            info!("waiting for a device to connect before starting web server");
+            loop {
+                tokio::select! {
+                    _ = device_connected_rx.wait_for(|device_connected| *device_connected) => break,
+                    // restarts pick up the new port once the server starts, so only a shutdown needs handling here
+                    signal = warp_shutdown_initiate_rx.recv() => if let None | Some(ShutdownMessage::Shutdown) = signal {
+                        warp_shutdown_complete_tx.send(result).expect("warp shut down started, but could not transmit callback signal");
+                        return;
+                    },
+                }
+            }
+        }
 
         // loop handles restarting the warp server if needed
         loop {
@@ -85,7 +346,11 @@ pub fn start_webserver(
             // this is needed because we cannot move the mpsc consumer
             let (warp_shutdown_oneshot_tx, warp_shutdown_oneshot_rx) = oneshot::channel::<()>();
 
-            let port = reconnect_task_application_state_db_clone.read().await.as_ref().expect("failed to read initial configuration").configuration.port;
+            let (port, abstract_socket_name) = {
+                let application_state_mutex = reconnect_task_application_state_db_clone.read().await;
+                let configuration = &application_state_mutex.as_ref().expect("failed to read initial configuration").configuration;
+                (configuration.port, configuration.abstract_socket_name.clone())
+            };
             let proxy_server_address: SocketAddr = ([127, 0, 0, 1], port).into();
 
             let server = warp::serve(routes.clone())
@@ -108,16 +373,34 @@ pub fn start_webserver(
                     task::spawn(async move {
                         warp_future.await;
                     });
+                    let abstract_socket_shutdown_tx = abstract_socket_name.and_then(|name| serve_abstract_socket(routes.clone(), &name));
 
                     // sacrifice this thread to shutdown trigger bullshit
                     let signal = warp_shutdown_initiate_rx.recv().await.unwrap_or(ShutdownMessage::Shutdown);
                     warp_shutdown_oneshot_tx.send(()).expect("error transmitting warp shutdown signal");
+                    if let Some(abstract_socket_shutdown_tx) = abstract_socket_shutdown_tx {
+                        let _ = abstract_socket_shutdown_tx.send(());
+                    }
                     signal
                 }
                 Err(e) => {
-                    //TODO: what happens if the default port is used? The user needs some way to change it.
-                    error!("Failed to start web server: {e:?}");
-                    ShutdownMessage::Shutdown
+                    let e = WebserverError::from_bind_error(port, e);
+                    if gui_start_oneshot_tx.is_some() {
+                        // the GUI never started, so the config file is the only way to change the port
+                        match &e {
+                            WebserverError::PortInUse(port) => error!("Failed to start web server: port {port} is already in use. Close the other application using it, or change the port in {:?}.", *CONFIG_DIR_FILE_PATH),
+                            e => error!("{e}"),
+                        }
+                        result = Err(e);
+                        ShutdownMessage::Shutdown
+                    } else {
+                        // the GUI is still running, so wait for the user to try another port
+                        match &e {
+                            WebserverError::PortInUse(port) => error!("Failed to restart web server: port {port} is already in use. Change the port in settings and save again."),
+                            e => error!("Failed to restart web server: {e}"),
+                        }
+                        warp_shutdown_initiate_rx.recv().await.unwrap_or(ShutdownMessage::Shutdown)
+                    }
                 }
             };
 
@@ -126,23 +409,153 @@ pub fn start_webserver(
             }
             // otherwise we go again
         }
-        warp_shutdown_complete_tx.send(()).expect("warp shut down started, but could not transmit callback signal");
+        warp_shutdown_complete_tx.send(result).expect("warp shut down started, but could not transmit callback signal");
+    });
+}
+
+/// Also serve `routes` on the abstract socket `name`, until the returned sender is used. Clients connected this way go
+/// through exactly the same handlers as over TCP. Returns `None` if the socket couldn't be bound.
+#[cfg(target_os = "linux")]
+fn serve_abstract_socket<F>(routes: F, name: &str) -> Option<oneshot::Sender<()>>
+where
+    F: Filter<Error=warp::Rejection> + Clone + Send + Sync + 'static,
+    F::Extract: warp::Reply,
+{
+    let incoming = match abstract_socket::bind(name) {
+        Ok(incoming) => incoming,
+        Err(e) => {
+            error!("Failed to start web server on abstract socket @{name}: {e}");
+            return None;
+        }
+    };
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let server = warp::serve(routes).serve_incoming_with_graceful_shutdown(incoming, async move {
+        // a dropped sender also means it's time to stop
+        let _ = shutdown_rx.await;
     });
+    info!("starting web server on abstract socket @{name}");
+    task::spawn(server);
+    Some(shutdown_tx)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn serve_abstract_socket<F>(_routes: F, name: &str) -> Option<oneshot::Sender<()>> {
+    warn!("not starting web server on abstract socket @{name}, as abstract sockets are only supported on Linux");
+    None
 }
 
 fn with_db<T: Clone + Send>(db: T) -> impl Filter<Extract=(T, ), Error=convert::Infallible> + Clone {
     warp::any().map(move || db.clone())
 }
 
+/// only passes requests whose `Accept-Encoding` header allows the given encoding
+fn accepts_encoding(encoding: &'static str) -> impl Filter<Extract=(), Error=warp::Rejection> + Clone {
+    warp::header::<String>("accept-encoding")
+        .and_then(move |accept_encoding: String| async move {
+            if is_encoding_accepted(&accept_encoding, encoding) {
+                Ok(())
+            } else {
+                Err(warp::reject())
+            }
+        })
+        .untuple_one()
+}
+
+/// only passes requests if the `/monitor` endpoint is enabled in the configuration
+fn monitor_enabled(application_state_db: ApplicationStateDb) -> impl Filter<Extract=(), Error=warp::Rejection> + Clone {
+    with_db(application_state_db)
+        .and_then(|application_state_db: ApplicationStateDb| async move {
+            let enabled = application_state_db.read().await.as_ref()
+                .is_some_and(|application_state| application_state.configuration.monitor_endpoint);
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+/// extracts the configured websocket message size limit, if there is one
+fn max_websocket_message_bytes(application_state_db: ApplicationStateDb) -> impl Filter<Extract=(Option<usize>,), Error=convert::Infallible> + Clone {
+    with_db(application_state_db)
+        .then(|application_state_db: ApplicationStateDb| async move {
+            application_state_db.read().await.as_ref()
+                .and_then(|application_state| application_state.configuration.max_websocket_message_bytes)
+        })
+}
+
+/// check an `Accept-Encoding` header value such as `gzip, deflate;q=0.5` for an acceptable encoding
+fn is_encoding_accepted(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|entry| {
+        let mut parameters = entry.split(';').map(str::trim);
+        let name_matches = parameters.next().is_some_and(|name| name.eq_ignore_ascii_case(encoding));
+        // a quality of zero means the encoding is explicitly NOT acceptable
+        let refused = parameters.any(|parameter| {
+            parameter.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0)
+        });
+        name_matches && !refused
+    })
+}
+
+// return application name and version
+async fn info_handler(application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut string = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if let Some(instance_name) = application_state_db.read().await.as_ref().and_then(|application_state| application_state.configuration.instance_name.as_ref()) {
+        string.push('\n');
+        string.push_str(instance_name);
+    }
+    Ok(string)
+}
+
+// report whether the device server is running
+async fn healthz_handler(application_state_db: ApplicationStateDb, device_server_retry_db: DeviceServerRetryDb) -> Result<impl warp::Reply, warp::Rejection> {
+    if application_state_db.read().await.is_some() {
+        Ok(warp::reply::with_status("ok\n".to_string(), StatusCode::OK))
+    } else if device_server_retry_db.has_given_up() {
+        Ok(warp::reply::with_status("device server unavailable\n".to_string(), StatusCode::SERVICE_UNAVAILABLE))
+    } else {
+        Ok(warp::reply::with_status("device server is not running\n".to_string(), StatusCode::SERVICE_UNAVAILABLE))
+    }
+}
+
+// start trying to start the device server again, if it was given up on
+async fn reconnect_handler(device_server_retry_db: DeviceServerRetryDb) -> Result<impl warp::Reply, warp::Rejection> {
+    if device_server_retry_db.retry() {
+        Ok(warp::reply::with_status(String::new(), StatusCode::OK))
+    } else {
+        Ok(warp::reply::with_status("device server startup has not been given up on\n".to_string(), StatusCode::CONFLICT))
+    }
+}
+
+/// a one-line count of used devices, their controllable motors, and how many of those motors are tagged
+fn status_summary(application_state: &ApplicationState, devices: &[Arc<ButtplugClientDevice>]) -> String {
+    let motor_count: usize = devices.iter().map(|device| app_buttplug::motor_count(device)).sum();
+    // a motor with several tags only counts once
+    let tagged_motor_count = application_state.configuration.tags().values()
+        .filter(|motor| {
+            app_buttplug::device_for_motor(application_state, motor)
+                .is_some_and(|device| app_buttplug::feature_attributes(&device, &motor.feature_type, motor.feature_index).is_some())
+        })
+        .map(|motor| (&motor.device_name, &motor.device_identifier, &motor.feature_type, motor.feature_index))
+        .collect::<BTreeSet<_>>()
+        .len();
+    let devices_word = if devices.len() == 1 { "device" } else { "devices" };
+    let motors_word = if motor_count == 1 { "motor" } else { "motors" };
+    format!("{} {devices_word}, {motor_count} controllable {motors_word}, {tagged_motor_count} tagged", devices.len())
+}
+
 // return a device status summary
 async fn haptic_status_handler(application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
     let application_state_mutex = application_state_db.read().await;
     match application_state_mutex.as_ref() {
         Some(application_state) => {
             let connected = application_state.client.connected();
-            let mut string = format!("device server running={connected}");
-            for device in application_state.client.devices() {
-                string.push_str(format!("\n  {}", device.name()).as_str());
+            let devices = application_state.devices();
+            let mut string = status_summary(application_state, &devices);
+            string.push_str(format!("\ndevice server running={connected}").as_str());
+            for device in devices {
+                string.push_str(format!("\n  {}", app_buttplug::display_name_from_device(&device, application_state)).as_str());
                 if let Some(display_name) = device.display_name() {
                     string.push_str(format!(" [{display_name}]").as_str());
                 }
@@ -172,18 +585,24 @@ async fn haptic_status_handler(application_state_db: ApplicationStateDb) -> Resu
 }
 
 // return battery status
-async fn battery_status_handler(application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
+async fn battery_status_handler(application_state_db: ApplicationStateDb, battery_history_db: BatteryHistoryDb) -> Result<impl warp::Reply, warp::Rejection> {
     let application_state_mutex = application_state_db.read().await;
     match application_state_mutex.as_ref() {
         Some(application_state) => {
             let mut string = String::new();
-            for device in application_state.client.devices() {
-                let battery_level = if device.message_attributes().message_allowed(&ButtplugDeviceMessageType::BatteryLevelCmd) {
-                    device.battery_level().await.ok()
-                } else {
-                    None
-                };
-                string.push_str(format!("{}:{}\n", device.name(), battery_level.unwrap_or(-1.0)).as_str());
+            let query_timeout = application_state.configuration.device_query_timeout();
+            let unknown_battery_level = application_state.configuration.unknown_battery_level.text();
+            for device in application_state.devices() {
+                let battery_level = app_buttplug::battery_level(&device, query_timeout, application_state.configuration.battery_scale).await;
+                let device_name = app_buttplug::display_name_from_device(&device, application_state);
+                match battery_level {
+                    Some(battery_level) => string.push_str(format!("{device_name}:{battery_level}\n").as_str()),
+                    None => string.push_str(format!("{device_name}:{unknown_battery_level}\n").as_str()),
+                }
+                let identifier = app_buttplug::id_from_device(&device, &application_state.device_manager);
+                if let (Some(battery_level), Some(identifier), Some(battery_history)) = (battery_level, identifier, &application_state.configuration.battery_history) {
+                    battery_history_db.write().await.record(identifier, device_name, battery_level, battery_history, Utc::now());
+                }
             }
             Ok(string)
         }
@@ -191,13 +610,24 @@ async fn battery_status_handler(application_state_db: ApplicationStateDb) -> Res
     }
 }
 
+// return recorded battery readings, or an empty object if battery history is disabled
+async fn battery_history_handler(application_state_db: ApplicationStateDb, battery_history_db: BatteryHistoryDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let battery_history = application_state_db.read().await.as_ref()
+        .and_then(|application_state| application_state.configuration.battery_history.clone());
+    let readings = match battery_history {
+        Some(battery_history) => battery_history_db.read().await.readings(&battery_history, Utc::now()),
+        None => Default::default(),
+    };
+    Ok(warp::reply::json(&readings))
+}
+
 // return device config
 async fn device_config_handler(application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
     let application_state_mutex = application_state_db.read().await;
     match application_state_mutex.as_ref() {
         Some(application_state) => {
             let mut string = String::new();
-            for (tag, motor) in application_state.configuration.tags.iter() {
+            for (tag, motor) in application_state.configuration.tags_in_order() {
                 string.push_str(format!("{};{};{}\n", tag, motor.device_name, motor.feature_type).as_str());
             }
             Ok(string)
@@ -206,15 +636,428 @@ async fn device_config_handler(application_state_db: ApplicationStateDb) -> Resu
     }
 }
 
-// haptic websocket handler
-async fn haptic_handler(
-    websocket: warp::ws::WebSocket,
+// return each configured motor along with what its device reports about it, in the same order as the device config
+async fn motors_handler(application_state_db: ApplicationStateDb, device_scale_db: DeviceScaleDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let application_state_mutex = application_state_db.read().await;
+    let Some(application_state) = application_state_mutex.as_ref() else {
+        return Ok(warp::reply::json(&Vec::<MotorCapabilities>::new()));
+    };
+    let device_scales = device_scale_db.read().await;
+    let motors: Vec<MotorCapabilities> = application_state.configuration.tags_in_order().into_iter()
+        .map(|(tag, motor)| {
+            let device = app_buttplug::device_for_motor(application_state, motor);
+            let attributes = device.as_deref().and_then(|device| app_buttplug::feature_attributes(device, &motor.feature_type, motor.feature_index));
+            let (feature_type, actuator_type, min) = match &motor.feature_type {
+                MotorTypeV3::Scalar { actuator_type } => ("scalar", Some(actuator_type.to_string()), 0.0),
+                MotorTypeV3::Rotation => ("rotation", None, -1.0),
+                MotorTypeV3::Linear => ("linear", None, 0.0),
+            };
+            MotorCapabilities {
+                tag: tag.to_string(),
+                device_name: motor.device_name.clone(),
+                feature_type,
+                actuator_type,
+                feature_index: motor.feature_index,
+                enabled: motor.enabled,
+                min,
+                max: 1.0,
+                step_count: attributes.map(|attributes| *attributes.step_count()),
+                feature_descriptor: attributes.map(|attributes| attributes.feature_descriptor().clone()),
+                scale: motor.device_identifier.as_ref().and_then(|identifier| device_scales.get(identifier)).copied().unwrap_or(1.0),
+            }
+        })
+        .collect();
+    Ok(warp::reply::json(&motors))
+}
+
+// return an example message for the configured tags
+async fn example_handler(application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
+    match application_state_db.read().await.as_ref() {
+        Some(application_state) => Ok(warp::reply::with_status(application_state.configuration.example_message(), StatusCode::OK)),
+        None => Ok(warp::reply::with_status("device server is not running".to_string(), StatusCode::SERVICE_UNAVAILABLE)),
+    }
+}
+
+// return a single tag's motor config
+async fn tag_handler(tag: String, application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let tag = percent_decode_str(&tag).decode_utf8_lossy();
+    let application_state_mutex = application_state_db.read().await;
+    let motor = application_state_mutex.as_ref()
+        .and_then(|application_state| application_state.configuration.motor_from_tag(&tag));
+    match motor {
+        Some(motor) => Ok(warp::reply::with_status(format!("{};{};{}\n", tag, motor.device_name, motor.feature_type), StatusCode::OK)),
+        None => Ok(warp::reply::with_status(String::new(), StatusCode::NOT_FOUND)),
+    }
+}
+
+// play a pattern from the configuration in the background, replacing any pattern that is already playing
+async fn pattern_handler(name: String, haptic_state: HapticState, watchdog_time: WatchdogTimeoutDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let name = percent_decode_str(&name).decode_utf8_lossy().into_owned();
+    let steps: Option<Vec<(String, Duration)>> = haptic_state.application_state_db.read().await.as_ref()
+        .and_then(|application_state| {
+            let configuration = &application_state.configuration;
+            configuration.patterns.get(&name).map(|steps| {
+                steps.iter()
+                    .filter_map(|step| match configuration.resolve_tag(&step.tag) {
+                        Some((_, motor)) => Some((step.command(&motor.feature_type), step.duration())),
+                        None => {
+                            warn!("skipping unknown motor tag {} in pattern {name}", step.tag);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+        });
+    let Some(steps) = steps else {
+        return Ok(warp::reply::with_status(String::new(), StatusCode::NOT_FOUND));
+    };
+
+    let mut playing_pattern = haptic_state.playing_pattern_db.write().await;
+    if let Some(previous_pattern) = playing_pattern.take() {
+        previous_pattern.abort();
+    }
+    *playing_pattern = Some(task::spawn(play_pattern(name, steps, haptic_state.clone(), watchdog_time)).abort_handle());
+    Ok(warp::reply::with_status(String::new(), StatusCode::OK))
+}
+
+// fade every running tag out to 0 over the requested duration
+async fn fadeout_handler(query: HashMap<String, String>, haptic_state: HapticState, watchdog_time: WatchdogTimeoutDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let duration_ms = match query.get("ms").map(|ms| ms.parse::<u64>()) {
+        Some(Ok(duration_ms)) if (1..=MAX_FADEOUT_MS).contains(&duration_ms) => duration_ms,
+        Some(_) => return Ok(warp::reply::with_status(format!("ms must be a whole number from 1 to {MAX_FADEOUT_MS}\n"), StatusCode::BAD_REQUEST)),
+        None => return Ok(warp::reply::with_status("missing ms parameter\n".to_string(), StatusCode::BAD_REQUEST)),
+    };
+    let tag_values: Vec<(String, f64)> = haptic_state.tag_value_db.read().await.iter()
+        .filter(|(_, value)| **value != 0.0)
+        .map(|(tag, value)| (tag.clone(), *value))
+        .collect();
+
+    // a fade-out plays like a pattern, so new commands and patterns take over from it
+    let mut playing_pattern = haptic_state.playing_pattern_db.write().await;
+    if let Some(previous_pattern) = playing_pattern.take() {
+        previous_pattern.abort();
+    }
+    *playing_pattern = Some(task::spawn(fade_out(tag_values, Duration::from_millis(duration_ms), haptic_state.clone(), watchdog_time)).abort_handle());
+    Ok(warp::reply::with_status(String::new(), StatusCode::OK))
+}
+
+// set or clear a device's live intensity multiplier
+async fn device_scale_handler(identifier: String, query: HashMap<String, String>, application_state_db: ApplicationStateDb, device_scale_db: DeviceScaleDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let identifier = percent_decode_str(&identifier).decode_utf8_lossy().into_owned();
+    let scale = match scale_from_query(&query) {
+        Ok(scale) => scale,
+        Err(e) => return Ok(warp::reply::with_status(e, StatusCode::BAD_REQUEST)),
+    };
+
+    let device_connected = application_state_db.read().await.as_ref().is_some_and(|application_state| {
+        application_state.devices().iter()
+            .any(|device| app_buttplug::id_from_device(device, &application_state.device_manager).as_ref() == Some(&identifier))
+    });
+    if !device_connected {
+        return Ok(warp::reply::with_status(String::new(), StatusCode::NOT_FOUND));
+    }
+
+    set_scale(&device_scale_db, identifier, scale).await;
+    Ok(warp::reply::with_status(String::new(), StatusCode::OK))
+}
+
+// set or clear a configured motor group's master scale
+async fn group_scale_handler(group: String, query: HashMap<String, String>, application_state_db: ApplicationStateDb, group_scale_db: GroupScaleDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let group = percent_decode_str(&group).decode_utf8_lossy().into_owned();
+    let scale = match scale_from_query(&query) {
+        Ok(scale) => scale,
+        Err(e) => return Ok(warp::reply::with_status(e, StatusCode::BAD_REQUEST)),
+    };
+
+    let group_configured = application_state_db.read().await.as_ref()
+        .is_some_and(|application_state| application_state.configuration.motor_groups.contains_key(&group));
+    if !group_configured {
+        return Ok(warp::reply::with_status(String::new(), StatusCode::NOT_FOUND));
+    }
+
+    set_scale(&group_scale_db, group, scale).await;
+    Ok(warp::reply::with_status(String::new(), StatusCode::OK))
+}
+
+/// Read a scale from the `value` query parameter of a scale endpoint, or explain why it can't be used
+fn scale_from_query(query: &HashMap<String, String>) -> Result<f64, String> {
+    match query.get("value").map(|value| value.parse::<f64>()) {
+        Some(Ok(scale)) if scale.is_finite() && scale >= 0.0 => Ok(scale),
+        Some(_) => Err("value must be a non-negative number\n".to_string()),
+        None => Err("missing value parameter\n".to_string()),
+    }
+}
+
+// start or stop scanning for devices
+async fn scanning_handler(query: HashMap<String, String>, application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let scanning = match query.get("enabled").map(String::as_str) {
+        Some("true") => true,
+        Some("false") => false,
+        Some(_) => return Ok(warp::reply::with_status("enabled must be true or false\n".to_string(), StatusCode::BAD_REQUEST)),
+        None => return Ok(warp::reply::with_status("missing enabled parameter\n".to_string(), StatusCode::BAD_REQUEST)),
+    };
+    if application_state_db.read().await.is_none() {
+        return Ok(warp::reply::with_status("device server is not running\n".to_string(), StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    let verb = if scanning { "started" } else { "stopped" };
+    match app_buttplug::set_scanning(&application_state_db, scanning).await {
+        Ok(true) => Ok(warp::reply::with_status(format!("scanning {verb}\n"), StatusCode::OK)),
+        Ok(false) => Ok(warp::reply::with_status(format!("scanning already {verb}\n"), StatusCode::OK)),
+        Err(e) => Ok(warp::reply::with_status(format!("{e}\n"), StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+// return each communication manager and whether it's enabled
+async fn comm_managers_handler(application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let application_state_mutex = application_state_db.read().await;
+    match application_state_mutex.as_ref() {
+        Some(application_state) => {
+            let mut string = String::new();
+            for comm_manager in CommManager::available() {
+                string.push_str(format!("{};{}\n", comm_manager.name(), application_state.configuration.is_comm_manager_enabled(comm_manager)).as_str());
+            }
+            Ok(string)
+        }
+        None => Ok(String::new())
+    }
+}
+
+// apply communication manager changes, then restart the device server so they take effect
+async fn comm_managers_update_handler(body: Bytes, application_state_db: ApplicationStateDb) -> Result<impl warp::Reply, warp::Rejection> {
+    let changes = match parse_comm_manager_changes(&String::from_utf8_lossy(&body)) {
+        Ok(changes) => changes,
+        Err(e) => return Ok(warp::reply::with_status(format!("{e}\n"), StatusCode::BAD_REQUEST)),
+    };
+
+    let configuration = application_state_db.read().await.as_ref()
+        .map(|application_state| application_state.configuration.clone());
+    let Some(previous_configuration) = configuration else {
+        return Ok(warp::reply::with_status("device server is not running\n".to_string(), StatusCode::SERVICE_UNAVAILABLE));
+    };
+
+    let mut configuration = previous_configuration.clone();
+    for (comm_manager, enabled) in changes {
+        configuration.disabled_comm_managers.retain(|disabled| *disabled != comm_manager);
+        if !enabled {
+            configuration.disabled_comm_managers.push(comm_manager);
+        }
+    }
+    if configuration == previous_configuration {
+        return Ok(warp::reply::with_status(String::new(), StatusCode::OK));
+    }
+
+    // the device server reloads the configuration from disk when it reconnects, so it must be saved first.
+    // The application state isn't locked while saving, so commands aren't held up by disk I/O.
+    if let Err(e) = config::save_configuration(&configuration).await {
+        warn!("failed to save communication manager changes: {e}");
+        return Ok(warp::reply::with_status(format!("{e}\n"), save_error_status(&e)));
+    }
+    let disconnect = match application_state_db.write().await.as_mut() {
+        // only the communication managers are changed, in case anything else changed while saving
+        Some(application_state) => {
+            application_state.configuration.disabled_comm_managers = configuration.disabled_comm_managers;
+            application_state.client.disconnect()
+        }
+        None => return Ok(warp::reply::with_status("device server is not running\n".to_string(), StatusCode::SERVICE_UNAVAILABLE)),
+    };
+
+    // the reconnect loop will bring the device server back up with the new communication managers
+    info!("communication managers changed, restarting device server");
+    if let Err(e) = disconnect.await {
+        warn!("failed to disconnect from device server: {e}");
+    }
+    Ok(warp::reply::with_status(String::new(), StatusCode::OK))
+}
+
+// return the most recently rejected /haptic messages
+async fn errors_handler(haptic_error_db: HapticErrorDb) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&haptic_error_db.read().await.errors()))
+}
+
+async fn errors_clear_handler(haptic_error_db: HapticErrorDb) -> Result<impl warp::Reply, warp::Rejection> {
+    haptic_error_db.write().await.clear();
+    Ok(warp::reply::with_status(String::new(), StatusCode::OK))
+}
+
+// check a configuration without applying it
+async fn config_validate_handler(body: Bytes) -> Result<impl warp::Reply, warp::Rejection> {
+    let configuration = match config::deserialize_configuration::<ConfigurationV3>(&String::from_utf8_lossy(&body)) {
+        Ok(configuration) => configuration,
+        Err(e) => return Ok(warp::reply::with_status(format!("{e}\n"), StatusCode::BAD_REQUEST)),
+    };
+    match configuration.validate() {
+        Ok(()) => Ok(warp::reply::with_status(String::new(), StatusCode::OK)),
+        Err(errors) => Ok(warp::reply::with_status(errors.iter().map(|e| format!("{e}\n")).collect(), StatusCode::BAD_REQUEST)),
+    }
+}
+
+// change the watchdog timeout, saving it to the configuration file only if asked to
+async fn watchdog_timeout_handler(
+    query: HashMap<String, String>,
     application_state_db: ApplicationStateDb,
-    watchdog_time: WatchdogTimeoutDb,
-) {
-    info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client connected");
-    let (_, mut rx) = websocket.split();
-    while let Some(result) = rx.next().await {
+    watchdog_timeout_db: WatchdogTimeoutDb,
+    application_status_sender: mpsc::UnboundedSender<ApplicationStatusEvent>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let timeout_ms = match query.get("ms").map(|ms| ms.parse::<u64>()) {
+        Some(Ok(timeout_ms)) if (1..=MAX_WATCHDOG_TIMEOUT_MS).contains(&timeout_ms) => timeout_ms,
+        Some(_) => return Ok(warp::reply::with_status(format!("ms must be a whole number from 1 to {MAX_WATCHDOG_TIMEOUT_MS}\n"), StatusCode::BAD_REQUEST)),
+        None => return Ok(warp::reply::with_status("missing ms parameter\n".to_string(), StatusCode::BAD_REQUEST)),
+    };
+    let persist = match query.get("persist").map(String::as_str) {
+        None | Some("false") => false,
+        Some("true") => true,
+        Some(_) => return Ok(warp::reply::with_status("persist must be true or false\n".to_string(), StatusCode::BAD_REQUEST)),
+    };
+
+    let configuration = application_state_db.read().await.as_ref()
+        .map(|application_state| application_state.configuration.clone());
+    let Some(mut configuration) = configuration else {
+        return Ok(warp::reply::with_status("device server is not running\n".to_string(), StatusCode::SERVICE_UNAVAILABLE));
+    };
+
+    // the application state isn't locked while saving, so commands aren't held up by disk I/O
+    configuration.watchdog_timeout_ms = timeout_ms;
+    if persist {
+        if let Err(e) = config::save_configuration(&configuration).await {
+            warn!("failed to save watchdog timeout: {e}");
+            return Ok(warp::reply::with_status(format!("{e}\n"), save_error_status(&e)));
+        }
+    }
+    match application_state_db.write().await.as_mut() {
+        // only the timeout is changed, in case anything else changed while saving
+        Some(application_state) => application_state.configuration.watchdog_timeout_ms = timeout_ms,
+        None => return Ok(warp::reply::with_status("device server is not running\n".to_string(), StatusCode::SERVICE_UNAVAILABLE)),
+    }
+    watchdog::restart(&watchdog_timeout_db, configuration.watchdog_timeout());
+    // the GUI saves its own copy of the configuration, which would otherwise put the old timeout back. It may already be gone.
+    let _ = application_status_sender.send(ApplicationStatusEvent::WatchdogTimeoutChanged { timeout_ms, persisted: persist });
+    info!("watchdog timeout changed to {timeout_ms}ms{}", if persist { " and saved" } else { " until restart" });
+    Ok(warp::reply::with_status(String::new(), StatusCode::OK))
+}
+
+/// The status to respond with when saving the configuration fails. Configurations that can't be saved at all are a
+/// conflict rather than a server error.
+fn save_error_status(e: &ConfigError) -> StatusCode {
+    match e {
+        ConfigError::ConfiguredByEnvironment | ConfigError::LayerOverride => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// parse a list of communication manager changes such as `bluetooth:false;serial:true`
+fn parse_comm_manager_changes(body: &str) -> Result<Vec<(CommManager, bool)>, String> {
+    body.trim()
+        .split_terminator(';')
+        .map(|line| {
+            let (name, enabled) = line.split_once(':')
+                .ok_or_else(|| format!("could not extract communication manager state from {line}"))?;
+            let comm_manager = CommManager::from_name(name)
+                .ok_or_else(|| format!("unknown communication manager {name}"))?;
+            let enabled = enabled.parse::<bool>()
+                .map_err(|e| format!("could not parse communication manager state from {enabled}: {e:?}"))?;
+            Ok((comm_manager, enabled))
+        })
+        .collect()
+}
+
+/// Shared state needed to turn haptic commands into device commands.
+/// The rest of buttplug-lite uses the public parts, such as the watchdog to halt devices and the GUI to drive motors.
+#[derive(Clone)]
+pub struct HapticState {
+    pub application_state_db: ApplicationStateDb,
+    pub tag_value_db: TagValueDb,
+    pub sent_command_dbs: SentCommandDbs,
+    pub device_scale_db: DeviceScaleDb,
+    pub group_scale_db: GroupScaleDb,
+    pub motor_hold_db: MotorHoldDb,
+    pub motor_ramp_db: MotorRampDb,
+    playing_pattern_db: PlayingPatternDb,
+    haptic_session_db: HapticSessionDb,
+    pending_halt_db: PendingHaltDb,
+    haptic_error_db: HapticErrorDb,
+    tag_claim_db: TagClaimDb,
+    /// processed messages for `/monitor` connections
+    monitor_tx: broadcast::Sender<MonitoredMessage>,
+}
+
+impl HapticState {
+    pub fn new(
+        application_state_db: ApplicationStateDb,
+        tag_value_db: TagValueDb,
+        sent_command_dbs: SentCommandDbs,
+        device_scale_db: DeviceScaleDb,
+        group_scale_db: GroupScaleDb,
+        motor_hold_db: MotorHoldDb,
+        motor_ramp_db: MotorRampDb,
+    ) -> Self {
+        HapticState {
+            application_state_db,
+            tag_value_db,
+            sent_command_dbs,
+            device_scale_db,
+            group_scale_db,
+            motor_hold_db,
+            motor_ramp_db,
+            playing_pattern_db: Default::default(), // only the web server plays patterns
+            haptic_session_db: Default::default(), // only the web server uses sessions
+            pending_halt_db: Default::default(), // only the web server has connections to close
+            haptic_error_db: Default::default(), // only the web server records rejected messages
+            tag_claim_db: Default::default(), // only the web server has connections to claim tags
+            monitor_tx: broadcast::channel(MONITOR_CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+// haptic websocket handler
+async fn haptic_handler(websocket: warp::ws::WebSocket, session: Option<String>, claim: Option<String>, haptic_state: HapticState, watchdog_time: WatchdogTimeoutDb) {
+    let connection_id = NEXT_HAPTIC_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+    info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} connected");
+
+    let session = session.filter(|session| {
+        let valid = session.len() <= MAX_SESSION_TOKEN_LENGTH;
+        if !valid {
+            warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: ignoring {} byte session token from client {connection_id}, as session tokens may be at most {MAX_SESSION_TOKEN_LENGTH} bytes long", session.len());
+        }
+        valid
+    });
+    // what this client has commanded, to be halted when it disconnects
+    let mut stoppable_tags: HashSet<String> = HashSet::new();
+    if let Some(session) = &session {
+        if let Some(pending_halt) = haptic_state.pending_halt_db.write().await.remove(&PendingHaltKey::Session(session.clone())) {
+            pending_halt.abort();
+            info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} reconnected before its motors were halted");
+        }
+        let span = debug_span!("haptic_session_resume", connection_id);
+        if let Some(resumed) = resume_session(session, &haptic_state).instrument(span).await {
+            info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} resumed its session");
+            watchdog::feed(&watchdog_time, &haptic_state.application_state_db).await;
+            record_stoppable_tags(&resumed, &mut stoppable_tags, &haptic_state).await;
+        }
+    }
+    if let Some(claim) = &claim {
+        claim_tags(connection_id, claim, &haptic_state).await;
+    }
+
+    let (mut tx, mut rx) = websocket.split();
+    // highest sequence number this client has sent, if it uses them
+    let mut last_sequence_number: Option<u64> = None;
+    let mut coalesced = CoalescedCommands::default();
+    loop {
+        // wait for the next message, unless coalesced commands are due to be sent first
+        let result = match coalesced.flush_at() {
+            Some(flush_at) => match time::timeout_at(flush_at.into(), rx.next()).await {
+                Ok(result) => result,
+                Err(_) => {
+                    flush_coalesced_commands(&mut coalesced, &haptic_state)
+                        .instrument(debug_span!("haptic_coalesced", connection_id))
+                        .await;
+                    continue;
+                }
+            },
+            None => rx.next().await,
+        };
+        let Some(result) = result else { break };
         let message = match result {
             Ok(message) => message,
             Err(e) => {
@@ -228,7 +1071,12 @@ async fn haptic_handler(
                 if message.is_binary() {
                     warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: received unexpected binary message: {message:?}");
                 } else if message.is_close() {
-                    info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client closed connection");
+                    info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} closed connection");
+                    flush_coalesced_commands(&mut coalesced, &haptic_state)
+                        .instrument(debug_span!("haptic_coalesced", connection_id))
+                        .await;
+                    haptic_state.tag_claim_db.write().await.release(connection_id);
+                    schedule_close_halt(connection_id, session, stoppable_tags, &haptic_state).await;
                     return; // stop reading input from the client if they close the connection
                 } else if message.is_ping() || message.is_pong() {
                     // do nothing, as there is no need to log ping or pong messages
@@ -245,67 +1093,635 @@ async fn haptic_handler(
             }
         };
 
-        let application_state_mutex = application_state_db.read().await;
-        if let Some(application_state) = application_state_mutex.as_ref() {
-            let device_map = build_vibration_map(&application_state.configuration, message);
+        let (sequence_number, message) = split_sequence_number(message);
+        if let Some(sequence_number) = sequence_number {
+            if let Some(last_sequence_number) = last_sequence_number.filter(|last_sequence_number| sequence_number <= *last_sequence_number) {
+                debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: dropping stale message {sequence_number} from client {connection_id}, as it already sent {last_sequence_number}");
+                let result: Result<DeliveryReport, String> = Err(format!("dropped stale message {sequence_number}, as message {last_sequence_number} was already received"));
+                send_haptic_reply(&mut tx, message, &result, &haptic_state).await;
+                continue;
+            }
+            last_sequence_number = Some(sequence_number);
+        }
+
+        let message = &*drop_claimed_commands(connection_id, message, &haptic_state).await;
+        log_debug_tags(connection_id, message, &haptic_state).await;
+        let span = debug_span!("haptic_message", connection_id, message_length = message.len());
+        let processed = process_haptic_message(message, &haptic_state, &mut coalesced)
+            .instrument(span)
+            .await;
+        if processed.is_ok() {
+            watchdog::feed(&watchdog_time, &haptic_state.application_state_db).await;
+            record_stoppable_tags(message, &mut stoppable_tags, &haptic_state).await;
+            if let Some(session) = &session {
+                record_session_values(session, message, &haptic_state).await;
+            }
+        }
+        if let Err(e) = &processed {
+            record_haptic_error(connection_id, message, e, &haptic_state).await;
+        }
+        publish_monitored_message(Some(connection_id), message, processed.as_ref().err().cloned(), &haptic_state);
+        send_haptic_reply(&mut tx, message, &processed, &haptic_state).await;
+    }
+    flush_coalesced_commands(&mut coalesced, &haptic_state)
+        .instrument(debug_span!("haptic_coalesced", connection_id))
+        .await;
+    haptic_state.tag_claim_db.write().await.release(connection_id);
+    info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} connection lost");
+    schedule_close_halt(connection_id, session, stoppable_tags, &haptic_state).await;
+}
 
-            let mut device_map = match device_map {
-                Ok(map) => map,
-                Err(e) => {
-                    debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: error parsing command: {e}");
-                    continue;
-                }
-            };
+/// Remember the scalar and rotation tags a message commanded, so they can be stopped when the client disconnects.
+/// Linear motors can't be stopped with a command, so they are left to the watchdog.
+async fn record_stoppable_tags(message: &str, stoppable_tags: &mut HashSet<String>, haptic_state: &HapticState) {
+    let application_state_mutex = haptic_state.application_state_db.read().await;
+    let Some(application_state) = application_state_mutex.as_ref() else { return };
+    for tag in stoppable_commanded_tags(&application_state.configuration, message) {
+        if !stoppable_tags.contains(&tag) {
+            stoppable_tags.insert(tag);
+        }
+    }
+}
 
-            for device in application_state.client.devices() {
-                let device_identifier = app_buttplug::id_from_device(&device, &application_state.device_manager);
-                let key = DeviceId {
-                    name: device.name().to_owned(),
-                    identifier: device_identifier,
-                };
+/// The scalar and rotation tags a message commanded. Composite tags are expanded into the motor tags they fan out to,
+/// as those are what a halt has to stop.
+fn stoppable_commanded_tags(configuration: &ConfigurationV3, message: &str) -> Vec<String> {
+    let expanded = expand_composite_tags(configuration, message);
+    commanded_tags(configuration, &expanded)
+        .filter(|tag| configuration.tags().get(*tag).is_some_and(|motor| motor.feature_type != MotorTypeV3::Linear))
+        .map(str::to_string)
+        .collect()
+}
 
-                let motor_settings = device_map.remove(&key)
-                    .or_else(|| device_map.remove(&key.without_identifier())); // fall back to no-id check in case of old configs
+/// If configured, stop the motors a client commanded once it has been disconnected for the configured delay.
+/// Motors other clients are driving keep running. Connecting again with the same session token cancels the halt.
+async fn schedule_close_halt(connection_id: u64, session: Option<String>, stoppable_tags: HashSet<String>, haptic_state: &HapticState) {
+    let halt_delay = haptic_state.application_state_db.read().await.as_ref()
+        .and_then(|application_state| application_state.configuration.halt_on_close_delay());
+    let Some(halt_delay) = halt_delay else { return };
+    if stoppable_tags.is_empty() {
+        return;
+    }
+    let halt_command: String = stoppable_tags.iter()
+        .map(|tag| format!("{tag}:0;"))
+        .collect();
+    if halt_delay.is_zero() {
+        info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: halting the motors of client {connection_id}, as it disconnected");
+        send_haptic_command(&halt_command, haptic_state).await;
+        return;
+    }
 
-                if let Some(motor_settings) = motor_settings {
-                    let MotorSettings {
-                        scalar_map,
-                        rotate_map,
-                        linear_map,
-                    } = motor_settings;
+    debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: halting the motors of client {connection_id} in {halt_delay:?} unless it reconnects");
+    let key = PendingHaltKey::new(connection_id, session.as_deref());
+    let mut pending_halts = haptic_state.pending_halt_db.write().await;
+    let pending_key = key.clone();
+    let haptic_state = haptic_state.clone();
+    let halt = task::spawn(async move {
+        time::sleep(halt_delay).await;
+        haptic_state.pending_halt_db.write().await.remove(&pending_key);
+        info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: halting the motors of client {connection_id}, as it did not reconnect within {halt_delay:?}");
+        send_haptic_command(&halt_command, &haptic_state).await;
+    });
+    // a later disconnect of the same session restarts the delay
+    if let Some(previous_halt) = pending_halts.insert(key, halt.abort_handle()) {
+        previous_halt.abort();
+    }
+}
 
-                    if !scalar_map.is_empty() {
-                        match device.scalar(&ScalarCommand::ScalarMap(scalar_map)).await {
-                            Ok(()) => (),
-                            Err(e) => warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: error sending command {e:?}",)
-                        }
-                    }
-                    if !rotate_map.is_empty() {
-                        match device.rotate(&RotateCommand::RotateMap(rotate_map)).await {
-                            Ok(()) => (),
-                            Err(e) => warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: error sending command {e:?}")
+// monitor websocket handler. Observers can't send commands, so they are disconnected if they send anything but a close, ping, or pong.
+async fn monitor_handler(websocket: warp::ws::WebSocket, mut monitor_rx: broadcast::Receiver<MonitoredMessage>) {
+    info!("{LOG_PREFIX_MONITOR_ENDPOINT}: observer connected");
+    let (mut tx, mut rx) = websocket.split();
+    loop {
+        tokio::select! {
+            monitored = monitor_rx.recv() => match monitored {
+                Ok(monitored) => {
+                    let json = match serde_json::to_string(&monitored) {
+                        Ok(json) => json,
+                        Err(e) => {
+                            warn!("{LOG_PREFIX_MONITOR_ENDPOINT}: failed to serialize message: {e}");
+                            continue;
                         }
+                    };
+                    if let Err(e) = tx.send(warp::ws::Message::text(json)).await {
+                        debug!("{LOG_PREFIX_MONITOR_ENDPOINT}: failed to send message: {e}");
+                        break;
                     }
-                    if !linear_map.is_empty() {
-                        match device.linear(&LinearCommand::LinearMap(linear_map)).await {
-                            Ok(()) => (),
-                            Err(e) => warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: error sending command {e:?}")
-                        }
-                    }
-                }; // else, ignore this device
-            }
-            drop(application_state_mutex); // prevent this section from requiring two locks
-            watchdog::feed(&watchdog_time).await;
-        } // else, no server connected, so send no commands
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => debug!("{LOG_PREFIX_MONITOR_ENDPOINT}: observer fell behind and missed {missed} messages"),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = rx.next() => match message {
+                Some(Ok(message)) if message.is_ping() || message.is_pong() => (),
+                Some(Ok(message)) if message.is_close() => break,
+                Some(Ok(_)) => {
+                    warn!("{LOG_PREFIX_MONITOR_ENDPOINT}: disconnecting observer, as the monitor endpoint is read-only");
+                    let _ = tx.send(warp::ws::Message::close()).await;
+                    break;
+                }
+                Some(Err(e)) => {
+                    warn!("{LOG_PREFIX_MONITOR_ENDPOINT}: message read error: {e:?}");
+                    break;
+                }
+                None => break,
+            },
+        }
     }
-    info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client connection lost");
+    info!("{LOG_PREFIX_MONITOR_ENDPOINT}: observer disconnected");
 }
 
-/* convert a command into a tree structure more usable by the Buttplug api
+/// Claim the `;`-separated tags in `claim` for a connection, so other connections can't command them until it disconnects
+async fn claim_tags(connection_id: u64, claim: &str, haptic_state: &HapticState) {
+    let application_state_mutex = haptic_state.application_state_db.read().await;
+    let Some(application_state) = application_state_mutex.as_ref() else { return };
+    let mut tags = Vec::new();
+    for tag in claim.split_terminator(';') {
+        match application_state.configuration.resolve_tag(tag) {
+            Some((tag, _)) => tags.push(tag),
+            None => warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} can't claim unknown tag {tag}"),
+        }
+    }
+    let taken_tags = haptic_state.tag_claim_db.write().await.claim(connection_id, tags.iter().copied());
+    if !taken_tags.is_empty() {
+        warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} can't claim tags already claimed by another client: {}", taken_tags.join(" "));
+    }
+    tags.retain(|tag| !taken_tags.contains(tag));
+    if !tags.is_empty() {
+        info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} claimed tags {}", tags.join(" "));
+    }
+}
+
+/// Remove the commands in a message for tags claimed by another connection. Messages with no such commands are returned unchanged.
+/// Composite tags are expanded first, so that claims on the tags they fan out to are respected.
+async fn drop_claimed_commands<'a>(connection_id: u64, message: &'a str, haptic_state: &HapticState) -> Cow<'a, str> {
+    let application_state_mutex = haptic_state.application_state_db.read().await;
+    let Some(application_state) = application_state_mutex.as_ref() else { return Cow::Borrowed(message) };
+    let configuration = &application_state.configuration;
+    let message = expand_composite_tags(configuration, message);
+    let tag_claims = haptic_state.tag_claim_db.read().await;
+    let claimed_tag = |line| commanded_tag(configuration, line)
+        .filter(|tag| !tag_claims.allows(connection_id, tag));
+    let claimed_tags: Vec<&str> = message.split_terminator(';').filter_map(claimed_tag).collect();
+    if claimed_tags.is_empty() {
+        return message;
+    }
+    debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: ignoring tags claimed by another client: {}", claimed_tags.join(" "));
+    Cow::Owned(
+        message.split_terminator(';')
+            .filter(|line| claimed_tag(line).is_none())
+            .map(|line| format!("{line};"))
+            .collect()
+    )
+}
+
+/// Remember a rejected message for `GET /errors`
+async fn record_haptic_error(connection_id: u64, message: &str, error: &str, haptic_state: &HapticState) {
+    let capacity = haptic_state.application_state_db.read().await.as_ref()
+        .map_or_else(|| ConfigurationV3::default().max_recorded_errors, |application_state| application_state.configuration.max_recorded_errors);
+    haptic_state.haptic_error_db.write().await.record(connection_id, message, error.to_string(), capacity);
+}
+
+/// If haptic replies are enabled, tell the client whether its message was accepted, and if not, why.
+/// Accepted messages also note anything that was accepted but had no effect, including devices that failed to receive their commands.
+async fn send_haptic_reply(tx: &mut SplitSink<warp::ws::WebSocket, warp::ws::Message>, message: &str, result: &Result<DeliveryReport, String>, haptic_state: &HapticState) {
+    let reply = match haptic_state.application_state_db.read().await.as_ref() {
+        Some(application_state) if !application_state.configuration.haptic_replies => return,
+        Some(application_state) => match result {
+            Ok(delivery_report) => {
+                let mut notes = Vec::new();
+                if application_state.devices().is_empty() {
+                    notes.push("no devices connected".to_string());
+                }
+                if !delivery_report.failed_devices.is_empty() {
+                    notes.push(format!(
+                        "failed to send to {} of {} devices: {}",
+                        delivery_report.failed_devices.len(),
+                        delivery_report.device_count,
+                        delivery_report.failed_devices.join(", "),
+                    ));
+                }
+                let unknown_tags: Vec<&str> = unknown_tags(&application_state.configuration, message).collect();
+                if !unknown_tags.is_empty() {
+                    notes.push(format!("ignored unknown tags {}", unknown_tags.join(" ")));
+                }
+                if notes.is_empty() {
+                    "ok".to_string()
+                } else {
+                    format!("ok: {}", notes.join(", "))
+                }
+            }
+            Err(e) => format!("error: {e}"),
+        },
+        None => return, // with no device server there is no configuration to say if replies are wanted
+    };
+    if let Err(e) = tx.send(warp::ws::Message::text(reply)).await {
+        debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: failed to send reply: {e}");
+    }
+}
+
+/// Send any commands waiting for a connection's coalescing window to end
+async fn flush_coalesced_commands(coalesced: &mut CoalescedCommands, haptic_state: &HapticState) {
+    let device_map = coalesced.take();
+    if !device_map.is_empty() {
+        send_device_map(device_map, haptic_state).await;
+    }
+}
+
+/// Re-send the values a session last sent to its tags, returning the message that was sent if there were any and they reached the device server
+async fn resume_session(session: &str, haptic_state: &HapticState) -> Option<String> {
+    let tag_values = haptic_state.haptic_session_db.write().await.resume(session, Instant::now());
+    if tag_values.is_empty() {
+        return None;
+    }
+    let message: String = tag_values.iter()
+        .map(|(tag, value)| format!("{tag}:{value};"))
+        .collect();
+    apply_haptic_message(&message, haptic_state, None).await.is_ok().then_some(message)
+}
+
+/// Log the commands in a message that address a debug tag, along with the client that sent them
+async fn log_debug_tags(connection_id: u64, message: &str, haptic_state: &HapticState) {
+    let application_state_mutex = haptic_state.application_state_db.read().await;
+    let Some(configuration) = application_state_mutex.as_ref().map(|application_state| &application_state.configuration) else { return };
+    if configuration.debug_tags.is_empty() {
+        return;
+    }
+    for line in message.split_terminator(';') {
+        if let Some(tag) = commanded_tag(configuration, line).filter(|tag| configuration.is_debug_tag(tag)) {
+            info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: client {connection_id} sent debug tag {tag} the command {line:?}");
+        }
+    }
+}
+
+/// Remember the values a session just sent to its scalar and rotation tags, so they can be restored if it reconnects
+async fn record_session_values(session: &str, message: &str, haptic_state: &HapticState) {
+    let application_state_mutex = haptic_state.application_state_db.read().await;
+    let Some(application_state) = application_state_mutex.as_ref() else { return };
+    let tag_values = haptic_state.tag_value_db.read().await;
+    let sent_values: Vec<(String, f64)> = commanded_tags(&application_state.configuration, message)
+        .filter_map(|tag| Some((tag.to_string(), *tag_values.get(tag)?)))
+        .collect();
+    haptic_state.haptic_session_db.write().await.record(session, sent_values, Instant::now());
+}
+
+/// Split the optional `@sequence;` prefix off a haptic message, returning the sequence number and the remaining commands.
+/// Messages without a valid prefix are returned unchanged.
+fn split_sequence_number(message: &str) -> (Option<u64>, &str) {
+    if let Some(rest) = message.strip_prefix('@') {
+        let (sequence_number, commands) = rest.split_once(';').unwrap_or((rest, ""));
+        if !sequence_number.is_empty() && sequence_number.bytes().all(|byte| byte.is_ascii_digit()) {
+            if let Ok(sequence_number) = sequence_number.parse() {
+                return (Some(sequence_number), commands);
+            }
+        }
+    }
+    (None, message)
+}
+
+/// Process a single text message from a haptic client. Succeeds if it was sent to the device server or added to `coalesced`, and otherwise explains why not.
+async fn process_haptic_message(message: &str, haptic_state: &HapticState, coalesced: &mut CoalescedCommands) -> Result<DeliveryReport, String> {
+    let (max_command_length, coalesce_window) = match haptic_state.application_state_db.read().await.as_ref() {
+        Some(application_state) => (Some(application_state.configuration.max_command_length), application_state.configuration.coalesce_window()),
+        None => (None, None),
+    };
+    if let Some(max_command_length) = max_command_length.filter(|max_command_length| message.len() > *max_command_length) {
+        warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: rejecting {} byte message, as messages may be at most {max_command_length} bytes long", message.len());
+        return Err(format!("message is {} bytes long, but may be at most {max_command_length} bytes long", message.len()));
+    }
+
+    apply_haptic_message(message, haptic_state, coalesce_window.map(|coalesce_window| (coalesced, coalesce_window))).await
+}
+
+/// Send a haptic message to the device server without checking its length, or explain why it could not be sent.
+/// If `coalesce` is given, the device commands are added to it to be sent when its window ends instead.
+pub(super) async fn apply_haptic_message(message: &str, haptic_state: &HapticState, coalesce: Option<(&mut CoalescedCommands, Duration)>) -> Result<DeliveryReport, String> {
+    // a message with no commands, such as a keepalive, still counts as input but shouldn't interrupt anything
+    if is_blank_message(message) {
+        return Ok(DeliveryReport::default());
+    }
+
+    // commands from a client take over from any pattern that is playing
+    if let Some(playing_pattern) = haptic_state.playing_pattern_db.write().await.take() {
+        debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: cancelling pattern");
+        playing_pattern.abort();
+    }
+
+    let processed = match build_device_map(message, haptic_state).await {
+        Ok(device_map) => match coalesce {
+            Some((coalesced, coalesce_window)) => {
+                coalesced.add(device_map, coalesce_window, Instant::now());
+                Ok(DeliveryReport::default())
+            }
+            None => Ok(send_device_map(device_map, haptic_state).await),
+        },
+        Err(e) => Err(e),
+    };
+
+    processed
+}
+
+/// if a message has no commands in it, only whitespace and `;` separators
+fn is_blank_message(message: &str) -> bool {
+    message.split_terminator(';').all(|line| line.trim().is_empty())
+}
+
+/// Stop a held motor once its minimum on-time has passed, unless it has been turned back on since
+async fn release_motor_hold(tag: String, release_at: Instant, haptic_state: HapticState) {
+    time::sleep_until(release_at.into()).await;
+    if haptic_state.motor_hold_db.read().await.is_release_pending(&tag) {
+        debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: minimum on-time elapsed for motor tag {tag}");
+        send_haptic_command(&format!("{tag}:0"), &haptic_state).await;
+    }
+}
+
+/// Keep re-sending a ramping motor's commanded value, which the ramp scales down, until the ramp is done or the motor is stopped
+async fn step_motor_ramp(tag: String, haptic_state: HapticState) {
+    let mut interval = time::interval(RAMP_STEP_INTERVAL);
+    interval.tick().await; // the first tick completes immediately
+    loop {
+        interval.tick().await;
+        if !haptic_state.motor_ramp_db.read().await.is_ramping(&tag) {
+            break;
+        }
+        let Some(value) = haptic_state.tag_value_db.read().await.get(&tag).copied() else { break };
+        if !send_haptic_command(&format!("{tag}:{value}"), &haptic_state).await {
+            break;
+        }
+    }
+    debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: finished ramping motor tag {tag}");
+}
+
+/// Play each step of a pattern in order. Stops early if the device server goes away.
+async fn play_pattern(name: String, steps: Vec<(String, Duration)>, haptic_state: HapticState, watchdog_time: WatchdogTimeoutDb) {
+    info!("playing pattern {name}");
+    for (command, duration) in steps {
+        if !send_monitored_command(&command, &haptic_state).await {
+            warn!("stopping pattern {name}, as its command {command} could not be sent");
+            return;
+        }
+        watchdog::feed(&watchdog_time, &haptic_state.application_state_db).await;
+        time::sleep(duration).await;
+    }
+    debug!("finished playing pattern {name}");
+}
+
+/// Step each tag down from its value to zero over `duration`. Stops early if the device server goes away.
+async fn fade_out(tag_values: Vec<(String, f64)>, duration: Duration, haptic_state: HapticState, watchdog_time: WatchdogTimeoutDb) {
+    if tag_values.is_empty() {
+        return;
+    }
+    info!("fading out {} motors over {duration:?}", tag_values.len());
+    let steps = u32::try_from(duration.as_millis() / RAMP_STEP_INTERVAL.as_millis()).unwrap_or(u32::MAX).max(1);
+    let mut interval = time::interval(duration / steps);
+    interval.tick().await; // the first tick completes immediately
+    for step in 1..=steps {
+        interval.tick().await;
+        let remaining = f64::from(steps - step) / f64::from(steps);
+        let command: String = tag_values.iter()
+            .map(|(tag, value)| format!("{tag}:{};", value * remaining))
+            .collect();
+        if !send_monitored_command(&command, &haptic_state).await {
+            warn!("stopping fade-out, as its command {command} could not be sent");
+            return;
+        }
+        watchdog::feed(&watchdog_time, &haptic_state.application_state_db).await;
+    }
+    debug!("finished fading out");
+}
+
+/// Send a command played by `/pattern` or `/fadeout` like [`send_haptic_command`], and echo it to `/monitor`
+async fn send_monitored_command(command: &str, haptic_state: &HapticState) -> bool {
+    let result = build_device_map(command, haptic_state).await;
+    let error = result.as_ref().err().cloned();
+    if let Ok(device_map) = result {
+        send_device_map(device_map, haptic_state).await;
+    }
+    let sent = error.is_none();
+    publish_monitored_message(None, command, error, haptic_state);
+    sent
+}
+
+/// Echo a processed command to `/monitor` observers
+fn publish_monitored_message(connection: Option<u64>, message: &str, error: Option<String>, haptic_state: &HapticState) {
+    // this only fails if nobody is monitoring
+    let _ = haptic_state.monitor_tx.send(MonitoredMessage {
+        time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        connection,
+        message: message.to_string(),
+        error,
+    });
+}
+
+/// Parse a haptic command and send it to the matching devices, returning `true` if the device server was running and the command was valid.
+/// This is boxed because holds and ramps started by [`build_device_map`] spawn tasks that call back into it, and the compiler can't
+/// work out that such a cycle of futures is `Send` on its own.
+fn send_haptic_command<'a>(message: &'a str, haptic_state: &'a HapticState) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+    Box::pin(async move {
+        match build_device_map(message, haptic_state).await {
+            Ok(device_map) => {
+                send_device_map(device_map, haptic_state).await;
+                true
+            }
+            Err(_) => false,
+        }
+    })
+}
+
+/// Parse a haptic command into the settings for each device, or explain why the device server is not running or the command is invalid.
+/// Every command goes through here, including those from patterns, fade-outs, holds and ramps, so any holds and ramps it starts are followed up on.
+async fn build_device_map(message: &str, haptic_state: &HapticState) -> Result<HashMap<DeviceId, MotorSettings>, String> {
+    let device_map = parse_device_map(message, haptic_state).await;
+
+    // motors that are now being held on need to be stopped once their minimum on-time is up
+    for (tag, release_at) in haptic_state.motor_hold_db.write().await.take_new_releases() {
+        task::spawn(release_motor_hold(tag, release_at, haptic_state.clone()).in_current_span());
+    }
+    // motors that started ramping up need to keep being stepped up until they reach their commanded value
+    for tag in haptic_state.motor_ramp_db.write().await.take_new_ramps() {
+        task::spawn(step_motor_ramp(tag, haptic_state.clone()).in_current_span());
+    }
+
+    device_map
+}
+
+async fn parse_device_map(message: &str, haptic_state: &HapticState) -> Result<HashMap<DeviceId, MotorSettings>, String> {
+    let application_state_mutex = haptic_state.application_state_db.read().await;
+    let application_state = application_state_mutex.as_ref()
+        .ok_or_else(|| "device server is not running".to_string())?; // no server connected, so send no commands
+    let device_map = build_vibration_map(
+        &application_state.configuration,
+        PipelineState {
+            tag_values: haptic_state.tag_value_db.write().await.deref_mut(),
+            device_scales: haptic_state.device_scale_db.read().await.deref(),
+            group_scales: haptic_state.group_scale_db.read().await.deref(),
+            motor_holds: haptic_state.motor_hold_db.write().await.deref_mut(),
+            motor_ramps: haptic_state.motor_ramp_db.write().await.deref_mut(),
+        },
+        Instant::now(),
+        message,
+    );
+    device_map.map_err(|e| {
+        debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: error parsing command: {e}");
+        e.to_string()
+    })
+}
+
+/// Send settings to each connected device they are meant for, reporting which devices they were sent to
+async fn send_device_map(mut device_map: HashMap<DeviceId, MotorSettings>, haptic_state: &HapticState) -> DeliveryReport {
+    let mut delivery_report = DeliveryReport::default();
+    let application_state_mutex = haptic_state.application_state_db.read().await;
+    if let Some(application_state) = application_state_mutex.as_ref() {
+        let quantize_scalars = application_state.configuration.quantize_scalar_commands;
+        let quantize_rotations = application_state.configuration.quantize_rotate_commands;
+        let resume_after_reconnect = application_state.configuration.resume_after_reconnect;
+        let command_conflict_policy = application_state.configuration.command_conflict_policy;
+        let sent_command_dbs = &haptic_state.sent_command_dbs;
+        let devices = application_state.devices();
+
+        // forget about devices that have gone away, as they won't remember what we last sent them if they come back
+        sent_command_dbs.scalars.write().await.retain(|device_index, _| devices.iter().any(|device| device.index() == *device_index));
+        sent_command_dbs.rotations.write().await.retain(|device_index, _| devices.iter().any(|device| device.index() == *device_index));
+
+        for device in devices {
+            let device_identifier = app_buttplug::id_from_device(&device, &application_state.device_manager);
+            let key = DeviceId {
+                name: app_buttplug::display_name_from_device(&device, application_state),
+                identifier: device_identifier,
+            };
+
+            let motor_settings = device_map.remove(&key)
+                .or_else(|| device_map.remove(&key.without_identifier())); // fall back to no-id check in case of old configs
+
+            if let Some(motor_settings) = motor_settings {
+                let span = debug_span!("haptic_device", device_index = device.index(), device_name = device.name());
+                // the caches are only locked briefly, so slow devices don't hold up commands for other devices.
+                // Sends to this device wait for each other instead, so the keepalive can't restore an outdated value.
+                let _send_guard = sent_command_dbs.lock_device(device.index()).await;
+                let sent_scalars = sent_command_dbs.scalars.read().await.get(&device.index()).cloned().unwrap_or_default();
+                let sent_rotations = sent_command_dbs.rotations.read().await.get(&device.index()).cloned().unwrap_or_default();
+                let delivered = send_motor_settings(&device, motor_settings, command_conflict_policy, quantize_scalars, quantize_rotations, &sent_scalars, &sent_rotations)
+                    .instrument(span)
+                    .await;
+
+                let mut running_motors = sent_command_dbs.running_motors.write().await;
+                running_motors.record_scalars(device.index(), &delivered.scalar_map);
+                running_motors.record_rotations(device.index(), &delivered.rotate_map);
+                drop(running_motors);
+                if quantize_scalars && !delivered.scalar_map.is_empty() {
+                    sent_command_dbs.scalars.write().await.entry(device.index()).or_default().extend(delivered.scalar_map.clone());
+                }
+                if quantize_rotations && !delivered.rotate_map.is_empty() {
+                    sent_command_dbs.rotations.write().await.entry(device.index()).or_default().extend(delivered.rotate_map.clone());
+                }
+                if let Some(identifier) = key.identifier.as_deref().filter(|_| resume_after_reconnect) {
+                    let mut last_commands = sent_command_dbs.last_commands.write().await;
+                    let last_command = last_commands.entry(identifier);
+                    last_command.record_scalars(&delivered.scalar_map);
+                    last_command.record_rotations(&delivered.rotate_map);
+                }
+
+                delivery_report.device_count += 1;
+                if !delivered.sent {
+                    delivery_report.failed_devices.push(key.name);
+                }
+            } // else, ignore this device
+        }
+    }
+    delivery_report
+}
+
+/// What [`send_motor_settings`] managed to send to a device
+struct DeliveredSettings {
+    scalar_map: HashMap<u32, (f64, ButtplugActuatorType)>,
+    rotate_map: HashMap<u32, (f64, bool)>,
+    /// `false` if any command failed
+    sent: bool,
+}
+
+/// Send the commands for a single device, after resolving conflicting commands according to `command_conflict_policy`.
+/// If quantization is enabled, commands matching what `sent_scalars` and `sent_rotations` say the device already has are skipped.
+async fn send_motor_settings(
+    device: &ButtplugClientDevice,
+    mut motor_settings: MotorSettings,
+    command_conflict_policy: CommandConflictPolicy,
+    quantize_scalars: bool,
+    quantize_rotations: bool,
+    sent_scalars: &HashMap<u32, (f64, ButtplugActuatorType)>,
+    sent_rotations: &HashMap<u32, (f64, bool)>,
+) -> DeliveredSettings {
+    for (feature_index, (_, actuator_type)) in motor_settings.scalar_map.iter_mut() {
+        *actuator_type = app_buttplug::reported_actuator_type(device, *feature_index, *actuator_type);
+    }
+    for scalar_index in motor_settings.resolve_conflicts(&app_buttplug::shared_features(device), command_conflict_policy) {
+        warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: {} scalar feature {scalar_index} was sent both a scalar and a rotate or linear command in one message. Resolving with {command_conflict_policy:?}.", device.name());
+    }
+
+    if quantize_scalars {
+        let step_counts: Vec<u32> = device.message_attributes().scalar_cmd().as_ref()
+            .map(|attributes| attributes.iter().map(|attribute| *attribute.step_count()).collect())
+            .unwrap_or_default();
+        motor_settings.quantize_scalars(&step_counts);
+        motor_settings.scalar_map.retain(|feature_index, command| sent_scalars.get(feature_index) != Some(command));
+    }
+
+    // secondary rotation speeds address the next rotate feature, which not every device has
+    let rotate_feature_count = device.message_attributes().rotate_cmd().as_ref().map_or(0, |attributes| attributes.len());
+    motor_settings.rotate_map.retain(|feature_index, _| (*feature_index as usize) < rotate_feature_count);
+
+    if quantize_rotations {
+        let step_counts: Vec<u32> = device.message_attributes().rotate_cmd().as_ref()
+            .map(|attributes| attributes.iter().map(|attribute| *attribute.step_count()).collect())
+            .unwrap_or_default();
+        motor_settings.quantize_rotations(&step_counts);
+        motor_settings.rotate_map.retain(|feature_index, command| sent_rotations.get(feature_index) != Some(command));
+    }
+
+    let MotorSettings {
+        mut scalar_map,
+        mut rotate_map,
+        linear_map,
+    } = motor_settings;
+
+    let mut sent = true;
+    if !scalar_map.is_empty() {
+        if let Err(e) = device.scalar(&ScalarCommand::ScalarMap(scalar_map.clone())).await {
+            warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: error sending command {e:?}");
+            scalar_map.clear();
+            sent = false;
+        }
+    }
+    if !rotate_map.is_empty() {
+        if let Err(e) = device.rotate(&RotateCommand::RotateMap(rotate_map.clone())).await {
+            warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: error sending command {e:?}");
+            rotate_map.clear();
+            sent = false;
+        }
+    }
+    if !linear_map.is_empty() {
+        match device.linear(&LinearCommand::LinearMap(linear_map)).await {
+            Ok(()) => (),
+            Err(e) => {
+                warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: error sending command {e:?}");
+                sent = false;
+            }
+        }
+    }
+    DeliveredSettings {
+        scalar_map,
+        rotate_map,
+        sent,
+    }
+}
+
+/* convert a command into a tree structure more usable by the Buttplug api
  * The input looks something like this, where 'i' and 'o' are motor tags:
  *
  * "i:0.6;o:0.0"
  *
+ * Scalar and rotation motors may also be adjusted relative to their last commanded value, like so:
+ *
+ * "i+0.1;o-0.25"
+ *
  * The output looks something like this:
  *
  * Device1:
@@ -314,33 +1730,89 @@ async fn haptic_handler(
  * Device2:
  *    Motor1Index: Motor1Strength
  *    Motor2Index: Motor2Strength
+ *
+ * Commands for composite tags are first rewritten into commands for each tag they fan out to.
+ *
+ * The tag values, scales, holds and ramps a command is applied to are described on `PipelineState`.
  */
-fn build_vibration_map(configuration: &ConfigurationV3, command: &str) -> Result<HashMap<DeviceId, MotorSettings>, String> {
+pub(super) fn build_vibration_map(configuration: &ConfigurationV3, state: PipelineState, now: Instant, command: &str) -> Result<HashMap<DeviceId, MotorSettings>, String> {
+    let PipelineState { tag_values, device_scales, group_scales, motor_holds, motor_ramps } = state;
+    let command = expand_composite_tags(configuration, command);
     let mut devices: HashMap<DeviceId, MotorSettings> = HashMap::new();
+    let mut updated_tag_values: HashMap<&str, f64> = HashMap::new();
+    let mut absolute_tags: HashSet<&str> = HashSet::new();
 
     for line in command.split_terminator(';') {
+        // blank commands, such as from a trailing `; ` or a keepalive, do nothing
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some((tag, motor, delta)) = split_relative_command(configuration, line) {
+            if !motor.enabled {
+                debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: ignoring disabled motor tag {tag}");
+                continue;
+            }
+            if !configuration.is_motor_type_allowed(&motor.feature_type) {
+                debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: ignoring motor tag {tag}, as its actuator type is not allowed");
+                continue;
+            }
+            let delta = match parse_value(configuration, delta) {
+                Ok(f) => f,
+                Err(e) => return Err(format!("could not parse relative adjustment from {delta}: {e}"))
+            };
+            let current_value = updated_tag_values.get(tag).or_else(|| tag_values.get(tag)).copied().unwrap_or(0.0);
+            match &motor.feature_type {
+                MotorTypeV3::Scalar { actuator_type } => {
+                    let intensity = (current_value + delta).clamp(0.0, 1.0);
+                    insert_scalar(&mut devices, motor, actuator_type, intensity);
+                    updated_tag_values.insert(tag, intensity);
+                }
+                MotorTypeV3::Rotation => {
+                    let speed = (current_value + delta).clamp(-1.0, 1.0);
+                    insert_rotation(&mut devices, motor, speed);
+                    updated_tag_values.insert(tag, speed);
+                }
+                MotorTypeV3::Linear => return Err(format!("relative adjustments are not supported for linear motor tag {tag}"))
+            }
+            continue;
+        }
+
         let mut split_line = line.split(':');
         let tag = match split_line.next() {
             Some(tag) => tag,
             None => return Err(format!("could not extract motor tag from {line}"))
         };
-        match configuration.motor_from_tag(tag) {
-            Some(motor) => {
+        match configuration.resolve_tag(tag) {
+            Some((tag, motor)) if !motor.enabled => debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: ignoring disabled motor tag {tag}"),
+            Some((tag, motor)) if !configuration.is_motor_type_allowed(&motor.feature_type) => debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: ignoring motor tag {tag}, as its actuator type is not allowed"),
+            Some((tag, motor)) => {
+                let duplicate = !absolute_tags.insert(tag);
+                if duplicate {
+                    match configuration.duplicate_tag_policy {
+                        DuplicateTagPolicy::Reject => return Err(format!("motor tag {tag} was commanded more than once")),
+                        policy => warn!("{LOG_PREFIX_HAPTIC_ENDPOINT}: motor tag {tag} was commanded more than once in one message. Resolving with {policy:?}."),
+                    }
+                }
+                // with the Max policy, a repeated command only replaces the earlier one if it is larger
+                let keeps_earlier = |earlier: Option<f64>, value: f64| {
+                    duplicate && configuration.duplicate_tag_policy == DuplicateTagPolicy::Max && earlier.is_some_and(|earlier| value.abs() <= earlier.abs())
+                };
                 match &motor.feature_type {
                     MotorTypeV3::Scalar { actuator_type } => {
                         let intensity = match split_line.next() {
                             Some(tag) => tag,
                             None => return Err(format!("could not extract motor intensity from {line}"))
                         };
-                        let intensity = match intensity.parse::<f64>() {
-                            Ok(f) => f.filter_nan().clamp(0.0, 1.0),
-                            Err(e) => return Err(format!("could not parse motor intensity from {intensity}: {e:?}"))
+                        let intensity = match parse_value(configuration, intensity) {
+                            Ok(f) => f.clamp(0.0, 1.0),
+                            Err(e) => return Err(format!("could not parse motor intensity from {intensity}: {e}"))
                         };
+                        if keeps_earlier(updated_tag_values.get(tag).copied(), intensity) {
+                            continue;
+                        }
 
-                        devices.entry(motor.into())
-                            .or_default()
-                            .scalar_map
-                            .insert(motor.feature_index, (intensity, actuator_type.to_buttplug()));
+                        insert_scalar(&mut devices, motor, actuator_type, intensity);
+                        updated_tag_values.insert(tag, intensity);
                     }
                     MotorTypeV3::Linear => {
                         let duration = match split_line.next() {
@@ -356,10 +1828,19 @@ fn build_vibration_map(configuration: &ConfigurationV3, command: &str) -> Result
                             Some(tag) => tag,
                             None => return Err(format!("could not extract motor position from {line}"))
                         };
-                        let position = match position.parse::<f64>() {
-                            Ok(f) => f.filter_nan().clamp(0.0, 1.0),
-                            Err(e) => return Err(format!("could not parse motor position from {position}: {e:?}"))
+                        let position = match parse_value(configuration, position) {
+                            Ok(f) => f.clamp(0.0, 1.0),
+                            Err(e) => return Err(format!("could not parse motor position from {position}: {e}"))
                         };
+                        let earlier_position = devices.get(&DeviceId::from(motor))
+                            .and_then(|motor_settings| motor_settings.linear_map.get(&motor.feature_index))
+                            .map(|(_, position)| *position);
+                        if keeps_earlier(earlier_position, position) {
+                            continue;
+                        }
+                        if configuration.is_debug_tag(tag) {
+                            info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: debug tag {tag} was commanded to position {position} over {duration}ms");
+                        }
 
                         devices.entry(motor.into())
                             .or_default()
@@ -371,20 +1852,26 @@ fn build_vibration_map(configuration: &ConfigurationV3, command: &str) -> Result
                             Some(tag) => tag,
                             None => return Err(format!("could not extract motor speed from {line}"))
                         };
-                        let mut speed = match speed.parse::<f64>() {
-                            Ok(f) => f.filter_nan().clamp(-1.0, 1.0),
-                            Err(e) => return Err(format!("could not parse motor speed from {speed}: {e:?}"))
+                        let speed = match parse_value(configuration, speed) {
+                            Ok(f) => f.clamp(-1.0, 1.0),
+                            Err(e) => return Err(format!("could not parse motor speed from {speed}: {e}"))
                         };
 
-                        let direction = speed >= 0.0;
-                        if !direction {
-                            speed = -speed;
+                        // an optional second speed controls the device's next rotate feature, for devices with more than one
+                        let secondary_speed = match split_line.next().map(|speed| (speed, parse_value(configuration, speed))) {
+                            Some((_, Ok(f))) => Some(f.clamp(-1.0, 1.0)),
+                            Some((speed, Err(e))) => return Err(format!("could not parse secondary motor speed from {speed}: {e}")),
+                            None => None,
+                        };
+                        if keeps_earlier(updated_tag_values.get(tag).copied(), speed) {
+                            continue;
                         }
 
-                        devices.entry(motor.into())
-                            .or_default()
-                            .rotate_map
-                            .insert(motor.feature_index, (speed, direction));
+                        insert_rotation(&mut devices, motor, speed);
+                        updated_tag_values.insert(tag, speed);
+                        if let Some(secondary_speed) = secondary_speed {
+                            insert_secondary_rotation(&mut devices, motor, secondary_speed);
+                        }
                     }
                 }
             }
@@ -392,6 +1879,617 @@ fn build_vibration_map(configuration: &ConfigurationV3, command: &str) -> Result
         };
     };
 
-    // Ok(&mut devices)
+    // motors with a response curve have their commanded value mapped along it first.
+    // motors with a minimum on-time may ignore a stop command for now, and be stopped later once it has passed.
+    // motors turned on after sitting idle may start lower, and be stepped up to their commanded value later.
+    // motors in groups are then scaled by their groups' master scales.
+    for (tag, value) in &updated_tag_values {
+        let Some(motor) = configuration.motor_from_tag(tag) else { continue };
+        let mut sent_value = *value;
+        if let Some(response_curve) = &motor.response_curve {
+            sent_value = response_curve.apply(sent_value);
+        }
+        if let Some(min_on_time) = motor.min_on_time() {
+            sent_value = motor_holds.apply(tag, min_on_time, sent_value, now);
+        }
+        if let Some(ramp_in) = &configuration.ramp_in {
+            sent_value = motor_ramps.apply(tag, ramp_in.idle(), ramp_in.duration(), sent_value, now);
+        }
+        if !group_scales.is_empty() {
+            sent_value = (sent_value * configuration.group_scale(tag, group_scales)).clamp(-1.0, 1.0);
+        }
+        if configuration.is_debug_tag(tag) {
+            info!("{LOG_PREFIX_HAPTIC_ENDPOINT}: debug tag {tag} was commanded {value}, and is being sent {sent_value}");
+        }
+        if sent_value != *value {
+            match &motor.feature_type {
+                MotorTypeV3::Scalar { actuator_type } => insert_scalar(&mut devices, motor, actuator_type, sent_value),
+                MotorTypeV3::Rotation => insert_rotation(&mut devices, motor, sent_value),
+                MotorTypeV3::Linear => (),
+            }
+        }
+    }
+
+    for (device, motor_settings) in devices.iter_mut() {
+        if let Some(scale) = device.identifier.as_ref().and_then(|identifier| device_scales.get(identifier)) {
+            motor_settings.scale(*scale);
+        }
+    }
+
+    for (tag, value) in updated_tag_values {
+        tag_values.insert(tag.to_string(), value);
+    }
+
     Ok(devices)
 }
+
+/// The values in a command for a composite tag, which are scaled by each tag it fans out to
+#[derive(Clone, Copy)]
+enum CompositeValues<'a> {
+    /// everything after the tag's `:`, such as `0.5` or `500:0.5`
+    Absolute(&'a str),
+    /// a signed adjustment, such as `+0.1`
+    Relative(&'a str),
+}
+
+/// Rewrite the commands in a message for composite tags into commands for each tag they fan out to, scaled by each tag's weight.
+/// Messages without composite tags are returned unchanged.
+fn expand_composite_tags<'a>(configuration: &ConfigurationV3, command: &'a str) -> Cow<'a, str> {
+    if configuration.composite_tags.is_empty()
+        || !command.split_terminator(';').any(|line| split_composite_command(configuration, line).is_some()) {
+        return Cow::Borrowed(command);
+    }
+    let mut expanded = String::new();
+    for line in command.split_terminator(';') {
+        match split_composite_command(configuration, line) {
+            Some((tag, members, values)) => expand_composite_command(configuration, &mut vec![tag], members, values, 1.0, &mut expanded),
+            None => {
+                expanded.push_str(line);
+                expanded.push(';');
+            }
+        }
+    }
+    Cow::Owned(expanded)
+}
+
+/// Append a command for each tag in `members` to `expanded`, continuing into nested composite tags.
+/// `path` holds the composite tags being expanded, which are skipped if they are reached again so recursive definitions can't loop forever.
+fn expand_composite_command<'a>(
+    configuration: &'a ConfigurationV3,
+    path: &mut Vec<&'a str>,
+    members: &'a [CompositeTagMemberV3],
+    values: CompositeValues,
+    weight: f64,
+    expanded: &mut String,
+) {
+    for member in members {
+        let weight = weight * member.weight;
+        if let Some((tag, nested_members)) = configuration.composite_tag(&member.tag) {
+            if path.contains(&tag) {
+                debug!("{LOG_PREFIX_HAPTIC_ENDPOINT}: ignoring composite tag {tag}, as it fans out to itself");
+                continue;
+            }
+            path.push(tag);
+            expand_composite_command(configuration, path, nested_members, values, weight, expanded);
+            path.pop();
+            continue;
+        }
+
+        let tag = &member.tag;
+        let scale = |value: &str| match value.parse::<f64>() {
+            // unparseable values are passed on unchanged, to be rejected with the usual error
+            Ok(f) if weight != 1.0 => (f * weight).to_string(),
+            _ => value.to_string(),
+        };
+        let command = match values {
+            CompositeValues::Relative(delta) => match delta.parse::<f64>() {
+                Ok(f) => format!("{tag}{:+}", f * weight),
+                Err(_) => format!("{tag}{delta}"),
+            },
+            CompositeValues::Absolute(values) => {
+                let is_linear = configuration.resolve_tag(tag).is_some_and(|(_, motor)| motor.feature_type == MotorTypeV3::Linear);
+                match values.split_once(':') {
+                    // linear commands are a duration then a position, and only the position is scaled
+                    Some((duration, position)) if is_linear => format!("{tag}:{duration}:{}", scale(position)),
+                    _ => format!("{tag}:{}", values.split(':').map(scale).collect::<Vec<_>>().join(":")),
+                }
+            }
+        };
+        expanded.push_str(&command);
+        expanded.push(';');
+    }
+}
+
+/// Attempt to split a command for a composite tag into its configured name, the tags it fans out to, and its values.
+/// Like motor tags, composite tags may contain `+` or `-`, so each operator position of a relative command is tried.
+fn split_composite_command<'a, 'b>(configuration: &'a ConfigurationV3, line: &'b str) -> Option<(&'a str, &'a [CompositeTagMemberV3], CompositeValues<'b>)> {
+    if let Some((tag, values)) = line.split_once(':') {
+        let (tag, members) = configuration.composite_tag(tag)?;
+        return Some((tag, members, CompositeValues::Absolute(values)));
+    }
+    line.char_indices()
+        .filter(|(_, c)| *c == '+' || *c == '-')
+        .find_map(|(index, _)| {
+            let (tag, members) = configuration.composite_tag(&line[..index])?;
+            Some((tag, members, CompositeValues::Relative(&line[index..])))
+        })
+}
+
+/// The configured tags addressed by each command in a message, as they are configured rather than as they were written
+fn commanded_tags<'a>(configuration: &'a ConfigurationV3, command: &'a str) -> impl Iterator<Item = &'a str> {
+    command.split_terminator(';')
+        .filter_map(|line| commanded_tag(configuration, line))
+}
+
+/// The configured tag addressed by a single command, if it is configured
+fn commanded_tag<'a>(configuration: &'a ConfigurationV3, line: &'a str) -> Option<&'a str> {
+    match split_relative_command(configuration, line) {
+        Some((tag, _, _)) => Some(tag),
+        None => configuration.resolve_tag(line.split(':').next()?).map(|(tag, _)| tag),
+    }
+}
+
+/// The tags in a message that are not configured, which [`build_vibration_map`] ignores
+fn unknown_tags<'a>(configuration: &'a ConfigurationV3, command: &'a str) -> impl Iterator<Item = &'a str> {
+    command.split_terminator(';')
+        .filter(|line| split_relative_command(configuration, line).is_none())
+        .filter_map(|line| line.split(':').next())
+        .filter(|tag| !tag.is_empty() && configuration.resolve_tag(tag).is_none())
+}
+
+/// Attempt to split a relative command such as `tag+0.1` into its tag, motor, and signed adjustment.
+/// Tags may themselves contain `+` or `-`, so each operator position is tried until the preceding text is a known tag.
+fn split_relative_command<'a>(configuration: &'a ConfigurationV3, line: &'a str) -> Option<(&'a str, &'a MotorConfigurationV3, &'a str)> {
+    if line.contains(':') {
+        return None;
+    }
+    line.char_indices()
+        .filter(|(_, c)| *c == '+' || *c == '-')
+        .find_map(|(index, _)| {
+            let (tag, delta) = line.split_at(index);
+            configuration.resolve_tag(tag).map(|(tag, motor)| (tag, motor, delta))
+        })
+}
+
+/// Parse a number from a command. NaN becomes zero, unless `reject_non_finite_values` is set, in which case NaN and
+/// infinite values are errors.
+fn parse_value(configuration: &ConfigurationV3, value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(f) if configuration.reject_non_finite_values && !f.is_finite() => Err("value is not a finite number".to_string()),
+        Ok(f) => Ok(f.filter_nan()),
+        Err(e) => Err(format!("{e:?}")),
+    }
+}
+
+fn insert_scalar(devices: &mut HashMap<DeviceId, MotorSettings>, motor: &MotorConfigurationV3, actuator_type: &ActuatorType, intensity: f64) {
+    devices.entry(motor.into())
+        .or_default()
+        .scalar_map
+        .insert(motor.feature_index, (intensity, actuator_type.to_buttplug()));
+}
+
+fn insert_rotation(devices: &mut HashMap<DeviceId, MotorSettings>, motor: &MotorConfigurationV3, speed: f64) {
+    let direction = speed >= 0.0;
+    devices.entry(motor.into())
+        .or_default()
+        .rotate_map
+        .insert(motor.feature_index, (speed.abs(), direction));
+}
+
+/// Set the rotate feature after `motor`'s, unless that feature was already commanded directly in the same message
+fn insert_secondary_rotation(devices: &mut HashMap<DeviceId, MotorSettings>, motor: &MotorConfigurationV3, speed: f64) {
+    let direction = speed >= 0.0;
+    devices.entry(motor.into())
+        .or_default()
+        .rotate_map
+        .entry(motor.feature_index + 1)
+        .or_insert((speed.abs(), direction));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::structs::SharedFeatures;
+    use crate::app::webserver::fixtures;
+    use crate::app::webserver::fixtures::{device_id, motor, Pipeline};
+    use crate::config::v3::{RampInV3, ResponseCurveV3};
+
+    use super::*;
+
+    fn configuration() -> ConfigurationV3 {
+        fixtures::configuration(&[
+            ("v", MotorTypeV3::Scalar { actuator_type: ActuatorType::Vibrate }, 0),
+            ("left-v", MotorTypeV3::Scalar { actuator_type: ActuatorType::Vibrate }, 1),
+            ("r", MotorTypeV3::Rotation, 0),
+            ("l", MotorTypeV3::Linear, 0),
+            ("sr", MotorTypeV3::Scalar { actuator_type: ActuatorType::Rotate }, 2),
+        ])
+    }
+
+    #[test]
+    fn sequence_number_prefix() {
+        assert_eq!(split_sequence_number("@42;v:0.5;r:1"), (Some(42), "v:0.5;r:1"));
+        assert_eq!(split_sequence_number("@7"), (Some(7), ""));
+        assert_eq!(split_sequence_number("v:0.5"), (None, "v:0.5"));
+        assert_eq!(split_sequence_number("@tag:0.5"), (None, "@tag:0.5"));
+        assert_eq!(split_sequence_number("@;v:0.5"), (None, "@;v:0.5"));
+        assert_eq!(split_sequence_number("@-1;v:0.5"), (None, "@-1;v:0.5"));
+    }
+
+    #[test]
+    fn encoding_negotiation() {
+        assert!(is_encoding_accepted("gzip", "gzip"));
+        assert!(is_encoding_accepted("deflate, GZIP;q=0.5", "gzip"));
+        assert!(!is_encoding_accepted("gzip;q=0", "gzip"));
+        assert!(!is_encoding_accepted("br", "gzip"));
+    }
+
+    #[test]
+    fn relative_scalar_accumulates_and_clamps() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        pipeline.send("v:0.5").unwrap();
+        let map = pipeline.send("v+0.25").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.75);
+
+        let map = pipeline.send("v+0.5").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 1.0);
+        assert_eq!(pipeline.tag_values["v"], 1.0);
+    }
+
+    #[test]
+    fn relative_rotation_can_change_direction() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        let map = pipeline.send("r-0.5").unwrap();
+        assert_eq!(map[&device_id()].rotate_map[&0], (0.5, false));
+        assert_eq!(pipeline.tag_values["r"], -0.5);
+    }
+
+    #[test]
+    fn relative_tag_containing_operator() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        let map = pipeline.send("left-v+0.5").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&1].0, 0.5);
+    }
+
+    #[test]
+    fn failed_command_does_not_update_tag_values() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        assert!(pipeline.send("v:0.5;l+0.1").is_err());
+        assert!(pipeline.tag_values.is_empty());
+    }
+
+    /// The features of the device in `configuration` that take more than one kind of command. The device has two vibrators
+    /// and a rotator as scalar features, and the rotator also takes rotate commands.
+    fn shared_features() -> SharedFeatures {
+        SharedFeatures::new(&[ButtplugActuatorType::Vibrate, ButtplugActuatorType::Vibrate, ButtplugActuatorType::Rotate], 1, 1)
+    }
+
+    #[test]
+    fn conflicting_commands_follow_policy() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        // "sr" and "r" are the same rotator, sent a scalar and a rotate command
+        let mut motor_settings = pipeline.send("sr:0.5;r:0.25;v:1").unwrap().remove(&device_id()).unwrap();
+        assert_eq!(motor_settings.resolve_conflicts(&shared_features(), CommandConflictPolicy::PreferScalar), vec![2]);
+        assert_eq!(motor_settings.scalar_map.keys().collect::<BTreeSet<_>>(), BTreeSet::from([&0, &2]));
+        assert!(motor_settings.rotate_map.is_empty());
+
+        // "v" shares its index with "r", but it is a different feature
+        let mut motor_settings = pipeline.send("v:0.5;r:0.25").unwrap().remove(&device_id()).unwrap();
+        assert!(motor_settings.resolve_conflicts(&shared_features(), CommandConflictPolicy::PreferScalar).is_empty());
+        assert!(motor_settings.scalar_map.contains_key(&0));
+        assert!(motor_settings.rotate_map.contains_key(&0));
+    }
+
+    #[test]
+    fn duplicate_tags_follow_policy() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        let map = pipeline.send("v:0.7;v:0.3").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.3);
+
+        pipeline.configuration.duplicate_tag_policy = DuplicateTagPolicy::Max;
+        let map = pipeline.send("v:0.7;v:0.3").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.7);
+        assert_eq!(pipeline.tag_values["v"], 0.7);
+        let map = pipeline.send("r:-0.5;r:0.25").unwrap();
+        assert_eq!(map[&device_id()].rotate_map[&0], (0.5, false));
+
+        pipeline.configuration.duplicate_tag_policy = DuplicateTagPolicy::Reject;
+        assert!(pipeline.send("v:0.3;v:0.7").is_err());
+        assert_eq!(pipeline.tag_values["v"], 0.7);
+        // relative adjustments add up rather than counting as duplicates
+        assert!(pipeline.send("v:0.25;v+0.25").is_ok());
+        assert_eq!(pipeline.tag_values["v"], 0.5);
+    }
+
+    #[test]
+    fn comm_manager_changes() {
+        assert_eq!(parse_comm_manager_changes("bluetooth:false;serial:true\n"), Ok(vec![(CommManager::Bluetooth, false), (CommManager::Serial, true)]));
+        assert!(parse_comm_manager_changes("bluetooth").is_err());
+        assert!(parse_comm_manager_changes("bluetooth:maybe").is_err());
+        assert!(parse_comm_manager_changes("carrier-pigeon:true").is_err());
+    }
+
+    #[test]
+    fn device_scale_applies_after_tag_values() {
+        let mut pipeline = Pipeline::new(configuration());
+        pipeline.device_scales = HashMap::from([(fixtures::DEVICE_IDENTIFIER.to_string(), 0.5)]);
+
+        let map = pipeline.send("v:0.8;r:-0.5;l:20:0.5").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.4);
+        assert_eq!(map[&device_id()].rotate_map[&0], (0.25, false));
+        assert_eq!(map[&device_id()].linear_map[&0], (20, 0.5));
+        assert_eq!(pipeline.tag_values["v"], 0.8);
+    }
+
+    #[test]
+    fn blank_commands_are_skipped() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        let map = pipeline.send(";v:0.5;; ;").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.5);
+        assert!(is_blank_message(""));
+        assert!(is_blank_message(" ;;\t; "));
+        assert!(!is_blank_message(";v:0.5"));
+    }
+
+    #[test]
+    fn scale_is_read_from_value_parameter() {
+        let query = |value: &str| HashMap::from([("value".to_string(), value.to_string())]);
+        assert_eq!(scale_from_query(&query("0.5")), Ok(0.5));
+        assert_eq!(scale_from_query(&query("0")), Ok(0.0));
+        assert!(scale_from_query(&query("-1")).is_err());
+        assert!(scale_from_query(&query("NaN")).is_err());
+        assert!(scale_from_query(&query("loud")).is_err());
+        assert!(scale_from_query(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn group_scale_applies_to_members_after_tag_values() {
+        let mut configuration = configuration();
+        configuration.motor_groups = HashMap::from([("left".to_string(), vec!["v".to_string(), "r".to_string()])]);
+        let mut pipeline = Pipeline::new(configuration);
+        pipeline.group_scales = HashMap::from([("left".to_string(), 0.5)]);
+
+        let map = pipeline.send("v:0.8;r:-0.5;l:20:0.5").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.4);
+        assert_eq!(map[&device_id()].rotate_map[&0], (0.25, false));
+        assert_eq!(map[&device_id()].linear_map[&0], (20, 0.5));
+        assert_eq!(pipeline.tag_values["v"], 0.8);
+    }
+
+    #[test]
+    fn response_curve_maps_values_before_sending() {
+        let mut configuration = configuration();
+        let response_curve = ResponseCurveV3(vec![[0.0, 0.0], [0.5, 0.2], [1.0, 1.0]]);
+        assert_eq!(response_curve.problem(), None);
+        assert!(ResponseCurveV3(vec![[0.5, 0.0], [0.2, 1.0]]).problem().is_some());
+        configuration.tags_mut().get_mut("v").unwrap().response_curve = Some(response_curve.clone());
+        configuration.tags_mut().get_mut("r").unwrap().response_curve = Some(response_curve);
+        let mut pipeline = Pipeline::new(configuration);
+
+        let map = pipeline.send("v:0.25;r:-1").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.1);
+        assert_eq!(map[&device_id()].rotate_map[&0], (1.0, false));
+        assert_eq!(pipeline.tag_values["v"], 0.25);
+    }
+
+    #[test]
+    fn response_curve_passes_stops_through() {
+        let mut configuration = configuration();
+        configuration.tags_mut().get_mut("v").unwrap().response_curve = Some(ResponseCurveV3(vec![[0.0, 0.2], [1.0, 1.0]]));
+        configuration.tags_mut().get_mut("r").unwrap().response_curve = Some(ResponseCurveV3(vec![[0.2, 0.3], [1.0, 1.0]]));
+        let mut pipeline = Pipeline::new(configuration);
+
+        let map = pipeline.send("v:0.25;r:0.1").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.4);
+        assert_eq!(map[&device_id()].rotate_map[&0], (0.3, true));
+
+        let map = pipeline.send("v:0;r:0").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.0);
+        assert_eq!(map[&device_id()].rotate_map[&0].0, 0.0);
+    }
+
+    #[test]
+    fn min_on_time_holds_stop_until_elapsed() {
+        let mut configuration = configuration();
+        configuration.tags_mut().get_mut("v").unwrap().min_on_time_ms = Some(500);
+        let mut pipeline = Pipeline::new(configuration);
+        let start = Instant::now();
+
+        pipeline.send_at(start, "v:0.6").unwrap();
+        let map = pipeline.send_at(start + Duration::from_millis(100), "v:0").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.6);
+        assert_eq!(pipeline.tag_values["v"], 0.0);
+        assert_eq!(pipeline.motor_holds.take_new_releases(), vec![("v".to_string(), start + Duration::from_millis(500))]);
+
+        let map = pipeline.send_at(start + Duration::from_millis(500), "v:0").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.0);
+        assert!(!pipeline.motor_holds.is_release_pending("v"));
+    }
+
+    #[test]
+    fn ramp_in_after_idle() {
+        let mut configuration = configuration();
+        configuration.ramp_in = Some(RampInV3 { duration_ms: 1000, idle_ms: 500 });
+        let mut pipeline = Pipeline::new(configuration);
+        let start = Instant::now();
+
+        let map = pipeline.send_at(start, "v:0.8").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.0);
+        assert_eq!(pipeline.tag_values["v"], 0.8);
+        assert_eq!(pipeline.motor_ramps.take_new_ramps(), vec!["v".to_string()]);
+
+        let map = pipeline.send_at(start + Duration::from_millis(250), "v:0.8").unwrap();
+        assert!((map[&device_id()].scalar_map[&0].0 - 0.2).abs() < 1e-9);
+        let map = pipeline.send_at(start + Duration::from_millis(1000), "v:0.8").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.8);
+        assert!(!pipeline.motor_ramps.is_ramping("v"));
+
+        // a short stop doesn't count as idle
+        pipeline.send_at(start + Duration::from_millis(1100), "v:0").unwrap();
+        let map = pipeline.send_at(start + Duration::from_millis(1200), "v:0.5").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.5);
+        assert!(pipeline.motor_ramps.take_new_ramps().is_empty());
+    }
+
+    #[test]
+    fn motor_index_addressing() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        let map = pipeline.send("#2:0.5").unwrap();
+        assert!(map.is_empty());
+
+        pipeline.configuration.motor_index_addressing = true;
+        let tags: Vec<&str> = pipeline.configuration.tags_in_order().into_iter().map(|(tag, _)| tag).collect();
+        assert_eq!(tags, vec!["l", "r", "v", "left-v", "sr"]);
+
+        let map = pipeline.send("#2:0.5;#2+0.25").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.75);
+        assert_eq!(pipeline.tag_values["v"], 0.75);
+    }
+
+    #[test]
+    fn secondary_rotation_speed() {
+        let mut configuration = configuration();
+        configuration.tags_mut().insert("r1".to_string(), motor(MotorTypeV3::Rotation, 1));
+        let mut pipeline = Pipeline::new(configuration);
+
+        let map = pipeline.send("r:0.5:-0.25").unwrap();
+        assert_eq!(map[&device_id()].rotate_map[&0], (0.5, true));
+        assert_eq!(map[&device_id()].rotate_map[&1], (0.25, false));
+        assert_eq!(pipeline.tag_values["r"], 0.5);
+
+        // commanding the second feature directly takes priority
+        let map = pipeline.send("r:0.5:-0.25;r1:1").unwrap();
+        assert_eq!(map[&device_id()].rotate_map[&1], (1.0, true));
+
+        assert!(pipeline.send("r:0.5:fast").is_err());
+    }
+
+    #[test]
+    fn multiple_actuators_on_one_device() {
+        let mut configuration = configuration();
+        configuration.tags_mut().insert("l1".to_string(), motor(MotorTypeV3::Linear, 1));
+        configuration.tags_mut().insert("r1".to_string(), motor(MotorTypeV3::Rotation, 1));
+        let mut pipeline = Pipeline::new(configuration);
+
+        let map = pipeline.send("l:20:0.5;l1:40:1;r:0.5;r1:-0.25").unwrap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map[&device_id()].linear_map, HashMap::from([(0, (20, 0.5)), (1, (40, 1.0))]));
+        assert_eq!(map[&device_id()].rotate_map, HashMap::from([(0, (0.5, true)), (1, (0.25, false))]));
+    }
+
+    #[test]
+    fn actuator_type_allowlist() {
+        let mut configuration = configuration();
+        configuration.actuator_type_allowlist = vec![ActuatorType::Rotate];
+        let mut pipeline = Pipeline::new(configuration);
+
+        let map = pipeline.send("left-v:0.5;left-v+0.1;sr:0.5;r:0.5").unwrap();
+        assert_eq!(map[&device_id()].scalar_map.keys().collect::<Vec<_>>(), [&2]);
+        assert!(map[&device_id()].rotate_map.contains_key(&0));
+        assert!(!pipeline.tag_values.contains_key("left-v"));
+    }
+
+    #[test]
+    fn case_insensitive_tags() {
+        let mut configuration = configuration();
+        configuration.tags_mut().insert("V".to_string(), motor(MotorTypeV3::Scalar { actuator_type: ActuatorType::Vibrate }, 1));
+        let mut pipeline = Pipeline::new(configuration);
+
+        let map = pipeline.send("Left-V:0.5").unwrap();
+        assert!(map.is_empty());
+
+        pipeline.configuration.case_insensitive_tags = true;
+        let map = pipeline.send("Left-V:0.5;R+0.25").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&1].0, 0.5);
+        assert_eq!(pipeline.tag_values["left-v"], 0.5);
+        assert_eq!(pipeline.tag_values["r"], 0.25);
+        // an exact match wins over a match ignoring case
+        assert_eq!(pipeline.configuration.resolve_tag("V").map(|(tag, _)| tag), Some("V"));
+        assert_eq!(pipeline.configuration.resolve_tag("v").map(|(tag, _)| tag), Some("v"));
+    }
+
+    #[test]
+    fn non_finite_values() {
+        let mut pipeline = Pipeline::new(configuration());
+
+        let map = pipeline.send("v:NaN;r:inf").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.0);
+        assert_eq!(map[&device_id()].rotate_map[&0], (1.0, true));
+
+        pipeline.configuration.reject_non_finite_values = true;
+        assert!(pipeline.send("v:NaN").is_err());
+        assert!(pipeline.send("v+-inf").is_err());
+        assert!(pipeline.send("v:0.5").is_ok());
+    }
+
+    #[test]
+    fn composite_tags_fan_out() {
+        let member = |tag: &str, weight: f64| CompositeTagMemberV3 { tag: tag.to_string(), weight };
+        let mut configuration = configuration();
+        configuration.composite_tags.insert("vibes".to_string(), vec![member("v", 1.0), member("left-v", 0.5)]);
+        configuration.composite_tags.insert("all".to_string(), vec![member("vibes", 1.0), member("all", 1.0)]);
+        configuration.composite_tags.insert("strokers".to_string(), vec![member("l", 0.5)]);
+        configuration.composite_tags.insert("v".to_string(), vec![member("r", 1.0)]);
+
+        assert_eq!(expand_composite_tags(&configuration, "v:0.5;r:1"), "v:0.5;r:1");
+        assert_eq!(expand_composite_tags(&configuration, "vibes:0.5;r:1"), "v:0.5;left-v:0.25;r:1;");
+        assert_eq!(expand_composite_tags(&configuration, "vibes-0.5"), "v-0.5;left-v-0.25;");
+        assert_eq!(expand_composite_tags(&configuration, "strokers:100:1"), "l:100:0.5;");
+        assert_eq!(expand_composite_tags(&configuration, "all:1"), "v:1;left-v:0.5;");
+        assert_eq!(expand_composite_tags(&configuration, "vibes:x"), "v:x;left-v:x;");
+
+        let mut pipeline = Pipeline::new(configuration);
+        let map = pipeline.send("vibes:0.5").unwrap();
+        assert_eq!(map[&device_id()].scalar_map[&0].0, 0.5);
+        assert_eq!(map[&device_id()].scalar_map[&1].0, 0.25);
+
+        pipeline.configuration.remove_recursive_composite_tags();
+        assert!(pipeline.configuration.composite_tags.contains_key("vibes"));
+        assert!(!pipeline.configuration.composite_tags.contains_key("all"));
+    }
+
+    #[test]
+    fn closing_connection_stops_composite_tag_members() {
+        let member = |tag: &str, weight: f64| CompositeTagMemberV3 { tag: tag.to_string(), weight };
+        let mut configuration = configuration();
+        configuration.composite_tags.insert("vibes".to_string(), vec![member("v", 1.0), member("left-v", 0.5)]);
+        configuration.composite_tags.insert("strokers".to_string(), vec![member("l", 0.5)]);
+
+        let tags = stoppable_commanded_tags(&configuration, "vibes:0.5;strokers:100:1;r:1");
+        assert_eq!(tags, vec!["v", "left-v", "r"]);
+    }
+
+    #[test]
+    fn commanded_tags_are_resolved() {
+        let mut configuration = configuration();
+        configuration.motor_index_addressing = true;
+        let tags: Vec<&str> = commanded_tags(&configuration, "v:0.5;left-v+0.1;#1:-1;l:20:0.5;unknown:1").collect();
+        assert_eq!(tags, vec!["v", "left-v", "r", "l"]);
+    }
+
+    #[test]
+    fn unknown_tags_are_found() {
+        let configuration = configuration();
+        let tags: Vec<&str> = unknown_tags(&configuration, "v:0.5;left-v+0.1;unknown:1;other+0.1;;v").collect();
+        assert_eq!(tags, vec!["unknown", "other+0.1"]);
+    }
+
+    #[tokio::test]
+    async fn pattern_commands_are_monitored() {
+        let haptic_state = HapticState::new(Default::default(), Default::default(), Default::default(), Default::default(), Default::default(), Default::default(), Default::default());
+        let mut monitor_rx = haptic_state.monitor_tx.subscribe();
+        // the device server isn't running, so the command can't be sent
+        assert!(!send_monitored_command("v:0.5", &haptic_state).await);
+        let monitored = monitor_rx.try_recv().unwrap();
+        assert_eq!(monitored.connection, None);
+        assert_eq!(monitored.message, "v:0.5");
+        assert!(monitored.error.is_some());
+    }
+}
@@ -4,6 +4,13 @@
 
 //! Simple structs used locally by the route code
 
+use std::collections::HashMap;
+use std::mem;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::app::structs::{MotorHolds, MotorRamps, MotorSettings};
 use crate::config::v3::MotorConfigurationV3;
 
 #[derive(Eq, PartialEq, Hash)]
@@ -29,3 +36,139 @@ impl From<&MotorConfigurationV3> for DeviceId {
         }
     }
 }
+
+/// Which devices a haptic message's commands were sent to. Commands waiting for a coalescing window to end haven't been sent to any yet.
+#[derive(Debug, Default)]
+pub struct DeliveryReport {
+    /// how many devices commands were sent to
+    pub device_count: usize,
+    /// names of the devices that rejected at least one command
+    pub failed_devices: Vec<String>,
+}
+
+/// What a haptic command is applied to besides the configuration, borrowed from the webserver's state for one command
+pub struct PipelineState<'a> {
+    /// the last value commanded for each tag. Only updated if the entire command parses successfully.
+    pub tag_values: &'a mut HashMap<String, f64>,
+    /// live multipliers for whole devices, keyed by device identifier. Applied after `tag_values` is updated, so relative
+    /// adjustments are unaffected by them.
+    pub device_scales: &'a HashMap<String, f64>,
+    /// master scales of motor groups, applied the same way as `device_scales`
+    pub group_scales: &'a HashMap<String, f64>,
+    pub motor_holds: &'a mut MotorHolds,
+    pub motor_ramps: &'a mut MotorRamps,
+}
+
+/// A processed haptic command as sent to `GET /monitor` observers
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitoredMessage {
+    /// when the message was processed, as an RFC 3339 UTC timestamp
+    pub time: String,
+    /// which `/haptic` connection sent the message, matching the connection ids in the logs. Unset for commands played by
+    /// `/pattern` and `/fadeout`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection: Option<u64>,
+    pub message: String,
+    /// why the message was rejected, if it was
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What a client needs to drive one configured motor, as listed by `GET /motors`
+#[derive(Serialize)]
+pub struct MotorCapabilities {
+    pub tag: String,
+    pub device_name: String,
+    /// `scalar`, `rotation`, or `linear`
+    pub feature_type: &'static str,
+    /// for scalar motors, what the motor does, such as `vibrate`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actuator_type: Option<String>,
+    pub feature_index: u32,
+    pub enabled: bool,
+    /// lowest value accepted in commands. For linear motors this is a position.
+    pub min: f64,
+    /// highest value accepted in commands
+    pub max: f64,
+    /// how many distinct levels the device supports, or `None` if it isn't connected
+    pub step_count: Option<u32>,
+    /// the device's description of the feature, or `None` if it isn't connected
+    pub feature_descriptor: Option<String>,
+    /// the device's live multiplier, set with `POST /device/{identifier}/scale`
+    pub scale: f64,
+}
+
+/// Device commands from one `/haptic` connection that are waiting for its coalescing window to end, so they can be sent together
+#[derive(Default)]
+pub struct CoalescedCommands {
+    device_map: HashMap<DeviceId, MotorSettings>,
+    flush_at: Option<Instant>,
+}
+
+impl CoalescedCommands {
+    /// Add commands to be sent later, opening a window that ends `window` from `now` if one isn't already open.
+    /// Commands replace any earlier ones waiting for the same features.
+    pub fn add(&mut self, device_map: HashMap<DeviceId, MotorSettings>, window: Duration, now: Instant) {
+        self.flush_at.get_or_insert(now + window);
+        for (device, motor_settings) in device_map {
+            self.device_map.entry(device).or_default().merge(motor_settings);
+        }
+    }
+
+    /// when the waiting commands should be sent, if there are any
+    pub fn flush_at(&self) -> Option<Instant> {
+        self.flush_at
+    }
+
+    /// Take all the waiting commands, closing the window
+    pub fn take(&mut self) -> HashMap<DeviceId, MotorSettings> {
+        self.flush_at = None;
+        mem::take(&mut self.device_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buttplug::core::message::ActuatorType;
+
+    use super::*;
+
+    fn device(name: &str) -> DeviceId {
+        DeviceId {
+            name: name.to_string(),
+            identifier: Some(format!("test://{name}")),
+        }
+    }
+
+    fn scalars(scalars: &[(u32, f64)]) -> MotorSettings {
+        MotorSettings {
+            scalar_map: scalars.iter().map(|(feature_index, intensity)| (*feature_index, (*intensity, ActuatorType::Vibrate))).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn coalesced_commands_last_write_wins() {
+        let mut coalesced_commands = CoalescedCommands::default();
+        let start = Instant::now();
+        let window = Duration::from_millis(50);
+        assert_eq!(coalesced_commands.flush_at(), None);
+
+        coalesced_commands.add(HashMap::from([(device("a"), scalars(&[(0, 0.25), (1, 0.5)]))]), window, start);
+        coalesced_commands.add(HashMap::from([(device("a"), scalars(&[(0, 1.0)])), (device("b"), scalars(&[(0, 0.75)]))]), window, start + Duration::from_millis(20));
+        coalesced_commands.add(HashMap::from([(device("a"), scalars(&[(0, 0.0)]))]), window, start + Duration::from_millis(40));
+        // later commands don't extend the window
+        assert_eq!(coalesced_commands.flush_at(), Some(start + window));
+
+        let device_map = coalesced_commands.take();
+        assert_eq!(device_map.len(), 2);
+        assert_eq!(device_map[&device("a")].scalar_map, HashMap::from([(0, (0.0, ActuatorType::Vibrate)), (1, (0.5, ActuatorType::Vibrate))]));
+        assert_eq!(device_map[&device("b")].scalar_map, HashMap::from([(0, (0.75, ActuatorType::Vibrate))]));
+
+        // taking the commands closes the window, and the next command opens a new one
+        assert_eq!(coalesced_commands.flush_at(), None);
+        assert!(coalesced_commands.take().is_empty());
+        coalesced_commands.add(HashMap::from([(device("a"), scalars(&[(0, 0.5)]))]), window, start + Duration::from_millis(60));
+        assert_eq!(coalesced_commands.flush_at(), Some(start + Duration::from_millis(110)));
+    }
+}
@@ -14,12 +14,20 @@ fn default_version() -> i32 {
 pub struct ConfigurationMinimal {
     #[serde(default = "default_version")]
     pub version: i32,
+    /// read here as well as in the full configuration, so that it still applies when the rest of the file fails to load
+    #[serde(default)]
+    pub config_backups: Option<usize>,
+    /// read here as well, as the renderer has to be chosen before the full configuration is loaded
+    #[serde(default)]
+    pub prefer_opengl: bool,
 }
 
 impl Default for ConfigurationMinimal {
     fn default() -> Self {
         ConfigurationMinimal {
             version: CONFIG_VERSION,
+            config_backups: None,
+            prefer_opengl: false,
         }
     }
 }
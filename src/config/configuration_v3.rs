@@ -2,53 +2,618 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use buttplug::core::message::ActuatorType as ButtplugActuatorType;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
+use crate::config::ValidationError;
 use crate::config::v2::{ConfigurationV2, MotorConfigurationV2, MotorTypeV2};
 
 use super::CONFIG_VERSION;
 
 const DEFAULT_PORT: u16 = 3031;
+const DEFAULT_DEVICE_QUERY_TIMEOUT_MS: u64 = 3000;
+const DEFAULT_MAX_COMMAND_LENGTH: usize = 4096;
+const DEFAULT_WATCHDOG_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_MAX_RECORDED_ERRORS: usize = 50;
+const DEFAULT_UPDATE_CHECK_CONNECT_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_UPDATE_CHECK_TIMEOUT_MS: u64 = 3000;
+const DEFAULT_SERVER_CONNECT_TIMEOUT_MS: u64 = 30_000;
+
+/// longest watchdog timeout, in milliseconds, that may be configured
+pub const MAX_WATCHDOG_TIMEOUT_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// longest tag, in bytes, that may be configured
+pub const MAX_TAG_LENGTH: usize = 128;
 
 fn default_version() -> i32 {
     1
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+fn default_device_query_timeout_ms() -> u64 {
+    DEFAULT_DEVICE_QUERY_TIMEOUT_MS
+}
+
+fn is_default_device_query_timeout_ms(value: &u64) -> bool {
+    *value == DEFAULT_DEVICE_QUERY_TIMEOUT_MS
+}
+
+fn default_server_connect_timeout_ms() -> u64 {
+    DEFAULT_SERVER_CONNECT_TIMEOUT_MS
+}
+
+fn is_default_server_connect_timeout_ms(value: &u64) -> bool {
+    *value == DEFAULT_SERVER_CONNECT_TIMEOUT_MS
+}
+
+fn default_max_command_length() -> usize {
+    DEFAULT_MAX_COMMAND_LENGTH
+}
+
+fn is_default_max_command_length(value: &usize) -> bool {
+    *value == DEFAULT_MAX_COMMAND_LENGTH
+}
+
+fn default_watchdog_timeout_ms() -> u64 {
+    DEFAULT_WATCHDOG_TIMEOUT_MS
+}
+
+fn is_default_watchdog_timeout_ms(value: &u64) -> bool {
+    *value == DEFAULT_WATCHDOG_TIMEOUT_MS
+}
+
+fn default_max_recorded_errors() -> usize {
+    DEFAULT_MAX_RECORDED_ERRORS
+}
+
+fn is_default_max_recorded_errors(value: &usize) -> bool {
+    *value == DEFAULT_MAX_RECORDED_ERRORS
+}
+
+fn default_update_check_connect_timeout_ms() -> u64 {
+    DEFAULT_UPDATE_CHECK_CONNECT_TIMEOUT_MS
+}
+
+fn default_update_check_timeout_ms() -> u64 {
+    DEFAULT_UPDATE_CHECK_TIMEOUT_MS
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn is_default_weight(value: &f64) -> bool {
+    *value == 1.0
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct ConfigurationV3 {
     #[serde(default = "default_version")]
     pub version: i32,
     pub port: u16,
-    /// map of tag name to motor struct
-    pub tags: HashMap<String, MotorConfigurationV3>,
+    /// map of tag name to motor struct. Private so every change goes through [`ConfigurationV3::tags_mut`], which keeps
+    /// `tag_order` up to date.
+    tags: HashMap<String, MotorConfigurationV3>,
+    /// command (program followed by arguments) to run once the web server has started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_command: Option<Vec<String>>,
+    /// command (program followed by arguments) to run during application shutdown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutdown_command: Option<Vec<String>>,
+    /// if set, external edits to the configuration file are applied without an application restart
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub watch_config_file: bool,
+    /// how to resolve a message that addresses one device feature with both scalar and rotate/linear commands
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub command_conflict_policy: CommandConflictPolicy,
+    /// periodically warn about connected devices that have no tagged motors
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub warn_untagged_devices: bool,
+    /// how to resolve a message that commands the same tag more than once
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub duplicate_tag_policy: DuplicateTagPolicy,
+    /// what `GET /batterystatus` reports for devices without a battery reading
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub unknown_battery_level: UnknownBatteryLevel,
+    /// how battery readings from devices are scaled to a 0-1 fraction
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub battery_scale: BatteryScale,
+    /// keep the GUI window above other windows
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub always_on_top: bool,
+    /// smooth the edges of shapes drawn in the GUI
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub antialiasing: bool,
+    /// render the GUI with OpenGL, for graphics drivers and VMs where the default renderer shows nothing
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub prefer_opengl: bool,
+    /// snap scalar intensities to the device's step count, and skip commands that would not change the resulting step
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub quantize_scalar_commands: bool,
+    /// snap rotation speeds to the device's step count, and skip commands that would not change the resulting step
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub quantize_rotate_commands: bool,
+    /// device communication managers that should not be started
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_comm_managers: Vec<CommManager>,
+    /// hex color string, such as `#7289DA`, used in place of the GUI theme's primary color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accent_color: Option<String>,
+    /// how long to wait for a device to report its battery or signal strength before giving up on it
+    #[serde(default = "default_device_query_timeout_ms", skip_serializing_if = "is_default_device_query_timeout_ms")]
+    pub device_query_timeout_ms: u64,
+    /// name used for both the buttplug client and server, to tell multiple buttplug-lite instances apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_name: Option<String>,
+    /// if set, motors may be addressed as `#N`, where `N` is the zero-based index of the tag in [`ConfigurationV3::tags_in_order`]
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub motor_index_addressing: bool,
+    /// if set, tags in commands match configured tags regardless of case, such as `Buzz` matching `buzz`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub case_insensitive_tags: bool,
+    /// named sequences of motor commands, played with `POST /pattern/{name}`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub patterns: HashMap<String, Vec<PatternStepV3>>,
+    /// longest message, in bytes, accepted from a haptic client
+    #[serde(default = "default_max_command_length", skip_serializing_if = "is_default_max_command_length")]
+    pub max_command_length: usize,
+    /// if set, `/haptic` websocket messages and frames larger than this many bytes close the connection as they arrive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_websocket_message_bytes: Option<usize>,
+    /// if not empty, only devices matching one of these rules are used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device_allowlist: Vec<DeviceRule>,
+    /// devices matching any of these rules are never used, even if they are also allowlisted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device_blocklist: Vec<DeviceRule>,
+    /// if not empty, only scalar motors with one of these actuator types are controlled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actuator_type_allowlist: Vec<ActuatorType>,
+    /// regex find-and-replace rules applied in order to device names reported by buttplug
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device_renames: Vec<DeviceRenameV3>,
+    /// most allowed devices used at once. Devices that connect after the limit is reached are ignored until a used device disconnects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_devices: Option<usize>,
+    /// if nonzero, device commands from each `/haptic` connection are collected for this many milliseconds and then sent together
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub coalesce_window_ms: u64,
+    /// how the GUI orders connected devices
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub device_order: DeviceOrder,
+    /// device names listed first, in this order, when [`ConfigurationV3::device_order`] is [`DeviceOrder::Pinned`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_devices: Vec<String>,
+    /// devices are halted after this many milliseconds with no command received
+    #[serde(default = "default_watchdog_timeout_ms", skip_serializing_if = "is_default_watchdog_timeout_ms")]
+    pub watchdog_timeout_ms: u64,
+    /// how long to wait after the device server starts before scanning for devices, for Bluetooth adapters that miss devices when scanned too early
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub scan_delay_ms: u64,
+    /// reply to each `/haptic` message with whether it was accepted, so integrations can be tested without a device
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub haptic_replies: bool,
+    /// accept `GET /monitor` websockets, which are sent every processed `/haptic` message
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub monitor_endpoint: bool,
+    /// if set, scalar and rotation motors turned on after sitting idle ramp up to their commanded value instead of jumping to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ramp_in: Option<RampInV3>,
+    /// if set, battery readings are kept for `GET /batteryhistory`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battery_history: Option<BatteryHistoryV3>,
+    /// how many rejected `/haptic` messages are kept for `GET /errors`
+    #[serde(default = "default_max_recorded_errors", skip_serializing_if = "is_default_max_recorded_errors")]
+    pub max_recorded_errors: usize,
+    /// what newly discovered scalar motors reporting an unknown actuator type are treated as. If unset they stay unknown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unknown_actuator_type: Option<ActuatorType>,
+    /// halt devices when an applied configuration changes the tags, so retagged motors don't keep running from the old tags
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub stop_devices_on_remap: bool,
+    /// show each connected device's protocol, address, and motor count in the GUI
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub show_device_details: bool,
+    /// show an example command next to each tagged motor in the GUI
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub show_motor_examples: bool,
+    /// show the step count the device reports for each connected motor in the GUI
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub show_step_counts: bool,
+    /// if set, warnings and errors are POSTed to this URL in batches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_shipping_url: Option<String>,
+    /// reject commands containing NaN or infinite values instead of treating NaN as zero
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub reject_non_finite_values: bool,
+    /// named groups of tags that can be commanded together as if they were one tag
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub composite_tags: HashMap<String, Vec<CompositeTagMemberV3>>,
+    /// named groups of tags, each with a live master scale for all of its members
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub motor_groups: HashMap<String, Vec<String>>,
+    /// how the startup update check connects to GitHub
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub update_check: UpdateCheckV3,
+    /// show a notice in the GUI while the device server is reconnecting, and once it is back
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub connection_notices: bool,
+    /// don't start the web server until a device has connected
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub wait_for_device: bool,
+    /// if nonzero, the running motors of each device are refreshed this often, for devices that fall asleep without commands
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub keepalive_interval_ms: u64,
+    /// if set, devices are halted this many milliseconds after a `/haptic` client closes its connection, unless it reconnects first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub halt_on_close_ms: Option<u64>,
+    /// re-send the last commands to devices that reconnect, so a brief disconnect doesn't stop them
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub resume_after_reconnect: bool,
+    /// if set, stop trying to start the device server after this many consecutive failures, until a retry is asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_reconnect_attempts: Option<u32>,
+    /// how long the device server may take to start before the attempt is abandoned and retried. 0 waits forever.
+    #[serde(default = "default_server_connect_timeout_ms", skip_serializing_if = "is_default_server_connect_timeout_ms")]
+    pub server_connect_timeout_ms: u64,
+    /// tell apart devices that share a display name by numbering them. Each device keeps its number while buttplug-lite runs.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub number_duplicate_devices: bool,
+    /// if set, the web server is also served on the Linux abstract socket with this name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abstract_socket_name: Option<String>,
+    /// how many backups of the configuration file to keep, if not the default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_backups: Option<usize>,
+    /// if set, MIDI control changes read from this device are sent to tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub midi_input: Option<MidiInputV3>,
+    /// tags whose commands are logged in detail, without turning on debug logging for everything
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub debug_tags: Vec<String>,
+    /// the order of `tags` for `#N` addressing, worked out once rather than on every lookup
+    #[serde(skip)]
+    tag_order: TagOrder,
 }
 
 impl ConfigurationV3 {
-    pub fn new(port: u16, tags: HashMap<String, MotorConfigurationV3>) -> ConfigurationV3 {
+    /// copy this configuration, replacing the settings that are editable in the GUI
+    pub fn with_gui_settings(&self, port: u16, tags: HashMap<String, MotorConfigurationV3>, always_on_top: bool) -> ConfigurationV3 {
         ConfigurationV3 {
             version: CONFIG_VERSION,
             port,
             tags,
+            always_on_top,
+            tag_order: TagOrder::default(),
+            ..self.clone()
         }
     }
 
     pub fn new_with_current_version(&self) -> ConfigurationV3 {
         ConfigurationV3 {
             version: CONFIG_VERSION,
-            port: self.port,
-            tags: self.tags.clone(),
+            ..self.clone()
+        }
+    }
+
+    pub fn device_query_timeout(&self) -> Duration {
+        Duration::from_millis(self.device_query_timeout_ms)
+    }
+
+    /// how long the watchdog waits for a command before halting devices
+    pub fn watchdog_timeout(&self) -> Duration {
+        Duration::from_millis(self.watchdog_timeout_ms.clamp(1, MAX_WATCHDOG_TIMEOUT_MS))
+    }
+
+    /// how long the device server may take to start, if there is a limit
+    pub fn server_connect_timeout(&self) -> Option<Duration> {
+        (self.server_connect_timeout_ms != 0).then(|| Duration::from_millis(self.server_connect_timeout_ms))
+    }
+
+    /// how long to wait before scanning for devices, if at all
+    pub fn scan_delay(&self) -> Option<Duration> {
+        (self.scan_delay_ms != 0).then(|| Duration::from_millis(self.scan_delay_ms))
+    }
+
+    /// how long to collect device commands before sending them together, if at all
+    pub fn coalesce_window(&self) -> Option<Duration> {
+        (self.coalesce_window_ms != 0).then(|| Duration::from_millis(self.coalesce_window_ms))
+    }
+
+    /// how long to wait after a `/haptic` client closes its connection before halting devices, if they are halted at all
+    pub fn halt_on_close_delay(&self) -> Option<Duration> {
+        self.halt_on_close_ms.map(Duration::from_millis)
+    }
+
+    /// how often to refresh running motors, if at all
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        (self.keepalive_interval_ms != 0).then(|| Duration::from_millis(self.keepalive_interval_ms))
+    }
+
+    pub fn is_comm_manager_enabled(&self, comm_manager: CommManager) -> bool {
+        !self.disabled_comm_managers.contains(&comm_manager)
+    }
+
+    /// Check a device's protocol and address against the allowlist and blocklist.
+    /// A device whose identity is unknown matches no rules.
+    pub fn is_device_allowed(&self, identity: Option<(&str, &str)>) -> bool {
+        let matches = |rule: &DeviceRule| identity.is_some_and(|(protocol, address)| rule.matches(protocol, address));
+        (self.device_allowlist.is_empty() || self.device_allowlist.iter().any(matches))
+            && !self.device_blocklist.iter().any(matches)
+    }
+
+    /// Check a motor's actuator type against the allowlist. Rotation and linear motors have no actuator type, so they are always allowed.
+    pub fn is_motor_type_allowed(&self, feature_type: &MotorTypeV3) -> bool {
+        match feature_type {
+            MotorTypeV3::Scalar { actuator_type } => self.actuator_type_allowlist.is_empty() || self.actuator_type_allowlist.contains(actuator_type),
+            MotorTypeV3::Rotation | MotorTypeV3::Linear => true,
         }
     }
 
+    /// Apply the device rename rules to a device name reported by buttplug
+    pub fn device_display_name(&self, device_name: &str) -> String {
+        self.device_renames.iter()
+            .filter_map(|rename| Some((rename.pattern.regex().ok()?, rename.replacement.as_str())))
+            .fold(device_name.to_string(), |name, (pattern, replacement)| pattern.replace_all(&name, replacement).into_owned())
+    }
+
     pub fn motor_from_tag(&self, tag: &str) -> Option<&MotorConfigurationV3> {
         self.tags.get(tag)
     }
 
+    /// an example message commanding every enabled tag, in the same order the GUI lists them
+    pub fn example_message(&self) -> String {
+        build_example_message(self.tags_in_order())
+    }
+
+    /// map of tag name to motor struct
+    pub fn tags(&self) -> &HashMap<String, MotorConfigurationV3> {
+        &self.tags
+    }
+
+    /// Change the tags. Their order is worked out again the next time it's needed.
+    pub fn tags_mut(&mut self) -> &mut HashMap<String, MotorConfigurationV3> {
+        self.tag_order = TagOrder::default();
+        &mut self.tags
+    }
+
+    /// all tags, sorted by [`MotorConfigurationV3::order_key`] and then by tag in the same order the GUI lists them
+    pub fn tags_in_order(&self) -> Vec<(&str, &MotorConfigurationV3)> {
+        self.tag_order.get(&self.tags).iter()
+            .filter_map(|tag| self.tags.get_key_value(tag))
+            .map(|(tag, motor)| (tag.as_str(), motor))
+            .collect()
+    }
+
+    /// Look up a motor from a tag as it appears in a command, returning the tag it is configured under.
+    /// If `motor_index_addressing` is enabled, `#N` refers to the Nth tag in [`ConfigurationV3::tags_in_order`]. A tag literally named `#N` takes precedence.
+    /// If `case_insensitive_tags` is enabled, a tag matching in case takes precedence over tags that only match ignoring case.
+    pub fn resolve_tag<'a>(&'a self, tag: &str) -> Option<(&'a str, &'a MotorConfigurationV3)> {
+        if let Some((tag, motor)) = self.tags.get_key_value(tag) {
+            return Some((tag.as_str(), motor));
+        }
+        if let Some((tag, motor)) = self.get_ignoring_case(&self.tags, tag) {
+            return Some((tag, motor));
+        }
+        if !self.motor_index_addressing {
+            return None;
+        }
+        let index = tag.strip_prefix('#')?.parse::<usize>().ok()?;
+        self.tag_order.get(&self.tags).get(index)
+            .and_then(|tag| self.tags.get_key_value(tag))
+            .map(|(tag, motor)| (tag.as_str(), motor))
+    }
+
+    /// The tags a composite tag fans out to, returning the name it is configured under.
+    /// Configured motor tags take precedence over composite tags with the same name.
+    pub fn composite_tag<'a>(&'a self, tag: &str) -> Option<(&'a str, &'a [CompositeTagMemberV3])> {
+        if self.resolve_tag(tag).is_some() {
+            return None;
+        }
+        self.composite_tags.get_key_value(tag)
+            .map(|(tag, members)| (tag.as_str(), members))
+            .or_else(|| self.get_ignoring_case(&self.composite_tags, tag))
+            .map(|(tag, members)| (tag, members.as_slice()))
+    }
+
+    /// The product of the master scales of every motor group `tag` belongs to. Groups without a scale are at 1.
+    pub fn group_scale(&self, tag: &str, group_scales: &HashMap<String, f64>) -> f64 {
+        self.motor_groups.iter()
+            .filter(|(_, tags)| tags.iter().any(|member| member == tag))
+            .filter_map(|(group, _)| group_scales.get(group))
+            .product()
+    }
+
+    /// If `case_insensitive_tags` is enabled, find a tag that matches ignoring case. If several do, the first in sort order wins.
+    fn get_ignoring_case<'a, V>(&self, tags: &'a HashMap<String, V>, tag: &str) -> Option<(&'a str, &'a V)> {
+        if !self.case_insensitive_tags {
+            return None;
+        }
+        let tag = tag.to_lowercase();
+        tags.iter()
+            .filter(|(name, _)| name.to_lowercase() == tag)
+            .map(|(name, value)| (name.as_str(), value))
+            .min_by_key(|(name, _)| *name)
+    }
+
+    /// Drop any composite tags that fan out to themselves, directly or through other composite tags, logging each one
+    pub fn remove_recursive_composite_tags(&mut self) {
+        let recursive_tags: Vec<String> = self.composite_tags.keys()
+            .filter(|tag| self.composite_tag_reaches(tag, tag, &mut HashSet::new()))
+            .cloned()
+            .collect();
+        for tag in recursive_tags {
+            warn!("ignoring composite tag {tag:?}, as it fans out to itself");
+            self.composite_tags.remove(&tag);
+        }
+    }
+
+    /// if the composite tag `composite` fans out to `tag`, directly or through other composite tags
+    fn composite_tag_reaches<'a>(&'a self, composite: &'a str, tag: &str, visited: &mut HashSet<&'a str>) -> bool {
+        if !visited.insert(composite) {
+            return false;
+        }
+        let Some((_, members)) = self.composite_tag(composite) else { return false };
+        members.iter().any(|member| member.tag == tag || self.composite_tag_reaches(&member.tag, tag, visited))
+    }
+
+    /// Drop any tags longer than [`MAX_TAG_LENGTH`], logging each one
+    pub fn remove_overlong_tags(&mut self) {
+        self.tags_mut().retain(|tag, _| {
+            let valid = tag.len() <= MAX_TAG_LENGTH;
+            if !valid {
+                warn!("ignoring {} byte tag starting with {:?}, as tags may be at most {MAX_TAG_LENGTH} bytes long", tag.len(), tag.chars().take(16).collect::<String>());
+            }
+            valid
+        });
+    }
+
+    /// Drop any device rename rules whose pattern is not a valid regex, logging each one
+    pub fn remove_invalid_device_renames(&mut self) {
+        self.device_renames.retain(|rename| {
+            match rename.pattern.regex() {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!("ignoring device rename with invalid pattern {:?}: {e}", rename.pattern.as_str());
+                    false
+                }
+            }
+        });
+    }
+
+    /// Drop any response curves that can't be used, logging each one
+    pub fn remove_invalid_response_curves(&mut self) {
+        for (tag, motor) in self.tags_mut() {
+            if let Some(problem) = motor.response_curve.as_ref().and_then(ResponseCurveV3::problem) {
+                warn!("ignoring response curve of tag {tag:?}, as {problem}");
+                motor.response_curve = None;
+            }
+        }
+    }
+
+    /// if commands for this configured tag are logged in detail. Listed tags are matched the same way as tags in commands.
+    pub fn is_debug_tag(&self, tag: &str) -> bool {
+        self.debug_tags.iter().any(|debug_tag| self.resolve_tag(debug_tag).is_some_and(|(debug_tag, _)| debug_tag == tag))
+    }
+
+    /// if a tag can be used in commands, which means it fits within [`MAX_TAG_LENGTH`] and has no `:` or `;` separators
+    pub fn is_tag_valid(tag: &str) -> bool {
+        !tag.contains(':') && !tag.contains(';') && tag.len() <= MAX_TAG_LENGTH
+    }
+
+    /// Check every rule a configuration must follow, returning all of the problems found.
+    /// Loading a configuration drops some of these problems with a warning instead, but a configuration being saved or
+    /// applied should have none.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        if self.version != CONFIG_VERSION {
+            errors.push(ValidationError::OutdatedVersion { version: self.version });
+        }
+        if self.port == 0 {
+            errors.push(ValidationError::Port);
+        }
+
+        let mut tags: Vec<&String> = self.tags.keys().collect();
+        tags.sort_unstable();
+        for tag in &tags {
+            if tag.is_empty() {
+                errors.push(ValidationError::EmptyTag);
+            } else if tag.len() > MAX_TAG_LENGTH {
+                errors.push(ValidationError::TagTooLong { length: tag.len(), start: tag.chars().take(16).collect() });
+            } else if !Self::is_tag_valid(tag) {
+                errors.push(ValidationError::TagSeparator { tag: tag.to_string() });
+            }
+        }
+        if self.case_insensitive_tags {
+            for (i, first) in tags.iter().enumerate() {
+                for second in tags.iter().skip(i + 1).filter(|second| second.to_lowercase() == first.to_lowercase()) {
+                    errors.push(ValidationError::AmbiguousTags { first: first.to_string(), second: second.to_string() });
+                }
+            }
+        }
+
+        for tag in &tags {
+            if let Some(problem) = self.tags[*tag].response_curve.as_ref().and_then(ResponseCurveV3::problem) {
+                errors.push(ValidationError::InvalidResponseCurve { tag: tag.to_string(), reason: problem });
+            }
+        }
+
+        let mut composite_tags: Vec<(&String, &Vec<CompositeTagMemberV3>)> = self.composite_tags.iter().collect();
+        composite_tags.sort_unstable_by_key(|(tag, _)| *tag);
+        for (tag, members) in composite_tags {
+            for member in members.iter().filter(|member| self.resolve_tag(&member.tag).is_none() && self.composite_tag(&member.tag).is_none()) {
+                errors.push(ValidationError::UnknownTag { referrer: format!("composite tag {tag:?}"), tag: member.tag.clone() });
+            }
+            if self.composite_tag_reaches(tag, tag, &mut HashSet::new()) {
+                errors.push(ValidationError::RecursiveCompositeTag { tag: tag.clone() });
+            }
+        }
+
+        let mut motor_groups: Vec<(&String, &Vec<String>)> = self.motor_groups.iter().collect();
+        motor_groups.sort_unstable_by_key(|(group, _)| *group);
+        for (group, members) in motor_groups {
+            // group scales match configured tags exactly
+            for member in members.iter().filter(|member| !self.tags.contains_key(*member)) {
+                errors.push(ValidationError::UnknownTag { referrer: format!("motor group {group:?}"), tag: member.clone() });
+            }
+        }
+
+        let mut patterns: Vec<(&String, &Vec<PatternStepV3>)> = self.patterns.iter().collect();
+        patterns.sort_unstable_by_key(|(name, _)| *name);
+        for (name, steps) in patterns {
+            for step in steps.iter().filter(|step| self.resolve_tag(&step.tag).is_none()) {
+                errors.push(ValidationError::UnknownTag { referrer: format!("pattern {name:?}"), tag: step.tag.clone() });
+            }
+        }
+
+        if let Some(midi_input) = &self.midi_input {
+            for mapping in &midi_input.mappings {
+                if !(1..=16).contains(&mapping.channel) || mapping.controller > 127 {
+                    errors.push(ValidationError::MidiMapping { channel: mapping.channel, controller: mapping.controller });
+                }
+                if self.resolve_tag(&mapping.tag).is_none() && self.composite_tag(&mapping.tag).is_none() {
+                    errors.push(ValidationError::UnknownTag { referrer: format!("MIDI mapping for channel {} controller {}", mapping.channel, mapping.controller), tag: mapping.tag.clone() });
+                }
+            }
+        }
+
+        for debug_tag in self.debug_tags.iter().filter(|debug_tag| self.resolve_tag(debug_tag).is_none()) {
+            errors.push(ValidationError::UnknownTag { referrer: "debug_tags".to_string(), tag: debug_tag.clone() });
+        }
+
+        for rename in &self.device_renames {
+            if let Err(e) = rename.pattern.regex() {
+                errors.push(ValidationError::InvalidDeviceRename { pattern: rename.pattern.as_str().to_string(), reason: e.to_string() });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     pub fn is_version_outdated(version: i32) -> bool {
         version < CONFIG_VERSION
     }
@@ -64,6 +629,66 @@ impl Default for ConfigurationV3 {
             version: CONFIG_VERSION,
             port: DEFAULT_PORT,
             tags: Default::default(),
+            startup_command: None,
+            shutdown_command: None,
+            watch_config_file: false,
+            command_conflict_policy: CommandConflictPolicy::default(),
+            duplicate_tag_policy: DuplicateTagPolicy::default(),
+            unknown_battery_level: UnknownBatteryLevel::default(),
+            battery_scale: BatteryScale::default(),
+            warn_untagged_devices: true,
+            always_on_top: false,
+            antialiasing: true,
+            prefer_opengl: false,
+            quantize_scalar_commands: true,
+            quantize_rotate_commands: false,
+            disabled_comm_managers: Vec::new(),
+            accent_color: None,
+            device_query_timeout_ms: DEFAULT_DEVICE_QUERY_TIMEOUT_MS,
+            instance_name: None,
+            motor_index_addressing: false,
+            case_insensitive_tags: false,
+            patterns: HashMap::new(),
+            max_command_length: DEFAULT_MAX_COMMAND_LENGTH,
+            max_websocket_message_bytes: None,
+            device_allowlist: Vec::new(),
+            device_blocklist: Vec::new(),
+            actuator_type_allowlist: Vec::new(),
+            device_renames: Vec::new(),
+            max_devices: None,
+            coalesce_window_ms: 0,
+            device_order: DeviceOrder::default(),
+            pinned_devices: Vec::new(),
+            watchdog_timeout_ms: DEFAULT_WATCHDOG_TIMEOUT_MS,
+            scan_delay_ms: 0,
+            haptic_replies: false,
+            monitor_endpoint: false,
+            ramp_in: None,
+            battery_history: None,
+            max_recorded_errors: DEFAULT_MAX_RECORDED_ERRORS,
+            unknown_actuator_type: None,
+            stop_devices_on_remap: false,
+            show_device_details: false,
+            show_motor_examples: false,
+            show_step_counts: false,
+            log_shipping_url: None,
+            reject_non_finite_values: false,
+            composite_tags: HashMap::new(),
+            motor_groups: HashMap::new(),
+            update_check: UpdateCheckV3::default(),
+            connection_notices: true,
+            wait_for_device: false,
+            keepalive_interval_ms: 0,
+            halt_on_close_ms: None,
+            resume_after_reconnect: false,
+            max_reconnect_attempts: None,
+            server_connect_timeout_ms: DEFAULT_SERVER_CONNECT_TIMEOUT_MS,
+            number_duplicate_devices: false,
+            abstract_socket_name: None,
+            config_backups: None,
+            midi_input: None,
+            debug_tags: Vec::new(),
+            tag_order: TagOrder::default(),
         }
     }
 }
@@ -83,8 +708,359 @@ impl From<ConfigurationV2> for ConfigurationV3 {
                 .filter(|(_key, value)| !bad_device_names.contains(&value.device_name))
                 .filter_map(|(key, value)| value.try_into().ok().map(|value| (key, value)))
                 .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// One step of a pattern: set a motor to a value, then wait before moving on to the next step
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PatternStepV3 {
+    pub tag: String,
+    /// scalar strength, rotation speed, or linear position, depending on the motor's type
+    pub value: f64,
+    /// how long this step lasts. Linear motors also take this long to move to their position.
+    pub duration_ms: u64,
+}
+
+impl PatternStepV3 {
+    /// the haptic command for this step, given the type of the motor its tag refers to
+    pub fn command(&self, feature_type: &MotorTypeV3) -> String {
+        match feature_type {
+            MotorTypeV3::Linear => format!("{}:{}:{}", self.tag, self.duration_ms, self.value),
+            MotorTypeV3::Rotation | MotorTypeV3::Scalar { .. } => format!("{}:{}", self.tag, self.value),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+}
+
+/// One of the tags a composite tag fans out to
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CompositeTagMemberV3 {
+    /// a motor tag, or another composite tag
+    pub tag: String,
+    /// multiplies every value sent to this tag through the composite tag
+    #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")]
+    pub weight: f64,
+}
+
+/// Matches devices using a particular buttplug protocol, such as `lovense`, and optionally only the device at one address
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct DeviceRule {
+    pub protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+impl DeviceRule {
+    pub fn matches(&self, protocol: &str, address: &str) -> bool {
+        self.protocol.eq_ignore_ascii_case(protocol)
+            && self.address.as_ref().is_none_or(|rule_address| rule_address.eq_ignore_ascii_case(address))
+    }
+}
+
+/// Softens the first command a motor receives after sitting idle
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct RampInV3 {
+    /// how long, in milliseconds, the motor takes to reach its commanded value
+    pub duration_ms: u64,
+    /// how long, in milliseconds, a motor must have been stopped before it ramps up again
+    #[serde(default)]
+    pub idle_ms: u64,
+}
+
+impl RampInV3 {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+
+    pub fn idle(&self) -> Duration {
+        Duration::from_millis(self.idle_ms)
+    }
+}
+
+/// Sends MIDI control changes from a MIDI input port to tags
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct MidiInputV3 {
+    /// Part of the name of the MIDI input port to read from. If unset, the first port is used. Only read at startup.
+    /// Older configurations named a raw MIDI device here, which is read as a port name.
+    #[serde(default, alias = "device", skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mappings: Vec<MidiMappingV3>,
+}
+
+/// Sends one MIDI controller's values to a tag, scaled from 0-127 to 0-1
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct MidiMappingV3 {
+    /// MIDI channel, from 1 to 16
+    pub channel: u8,
+    /// control change number, from 0 to 127
+    pub controller: u8,
+    pub tag: String,
+}
+
+impl MidiInputV3 {
+    /// the tags mapped to a controller on a channel, which is counted from 1
+    pub fn tags_for(&self, channel: u8, controller: u8) -> impl Iterator<Item=&str> {
+        self.mappings.iter()
+            .filter(move |mapping| mapping.channel == channel && mapping.controller == controller)
+            .map(|mapping| mapping.tag.as_str())
+    }
+}
+
+/// Keeps recent battery readings for `GET /batteryhistory`
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct BatteryHistoryV3 {
+    /// how long, in seconds, readings are kept
+    #[serde(default = "default_battery_history_retention_s")]
+    pub retention_s: u64,
+    /// most readings kept for each device. The oldest are forgotten first.
+    #[serde(default = "default_battery_history_max_samples")]
+    pub max_samples: usize,
+}
+
+fn default_battery_history_retention_s() -> u64 {
+    86400
+}
+
+fn default_battery_history_max_samples() -> usize {
+    1000
+}
+
+impl BatteryHistoryV3 {
+    pub fn retention(&self) -> chrono::TimeDelta {
+        chrono::TimeDelta::seconds(i64::try_from(self.retention_s).unwrap_or(i64::MAX))
+    }
+}
+
+/// Connection settings for the startup update check. The strict defaults suit most networks, but can fail behind TLS-intercepting proxies.
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct UpdateCheckV3 {
+    /// how long, in milliseconds, to wait for a connection
+    #[serde(default = "default_update_check_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+    /// how long, in milliseconds, the whole request may take
+    #[serde(default = "default_update_check_timeout_ms")]
+    pub timeout_ms: u64,
+    /// start with HTTP/2 without negotiating it first. If this fails, the check is retried with negotiation, which allows HTTP/1.1.
+    #[serde(default = "default_true")]
+    pub http2_prior_knowledge: bool,
+    /// refuse to connect over plain HTTP
+    #[serde(default = "default_true")]
+    pub https_only: bool,
+    /// If set, the result of each update check is saved and no new check is made until this many hours have passed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_hours: Option<u64>,
+}
+
+impl UpdateCheckV3 {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    pub fn cooldown(&self) -> Option<Duration> {
+        self.cooldown_hours.map(|hours| Duration::from_secs(hours.saturating_mul(60 * 60)))
+    }
+}
+
+impl Default for UpdateCheckV3 {
+    fn default() -> Self {
+        UpdateCheckV3 {
+            connect_timeout_ms: DEFAULT_UPDATE_CHECK_CONNECT_TIMEOUT_MS,
+            timeout_ms: DEFAULT_UPDATE_CHECK_TIMEOUT_MS,
+            http2_prior_knowledge: true,
+            https_only: true,
+            cooldown_hours: None,
+        }
+    }
+}
+
+/// Replaces every match of a regex `pattern` in a device name. The `replacement` may refer to capture groups, such as `$1`.
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct DeviceRenameV3 {
+    pub pattern: RenamePattern,
+    #[serde(default)]
+    pub replacement: String,
+}
+
+/// A device rename regex, compiled once when the configuration is read instead of each time a device is named
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(from = "String", into = "String")]
+pub struct RenamePattern {
+    source: String,
+    /// the compiled regex, or why `source` is not a valid regex
+    regex: Result<Regex, regex::Error>,
+}
+
+impl RenamePattern {
+    pub fn new(source: impl Into<String>) -> Self {
+        let source = source.into();
+        let regex = Regex::new(&source);
+        RenamePattern { source, regex }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// The compiled regex. Invalid patterns are reported by [`ConfigurationV3::validate`], and dropped when the
+    /// configuration is loaded.
+    pub fn regex(&self) -> Result<&Regex, &regex::Error> {
+        self.regex.as_ref()
+    }
+}
+
+impl From<String> for RenamePattern {
+    fn from(source: String) -> Self {
+        RenamePattern::new(source)
+    }
+}
+
+impl From<RenamePattern> for String {
+    fn from(pattern: RenamePattern) -> Self {
+        pattern.source
+    }
+}
+
+impl PartialEq for RenamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for RenamePattern {}
+
+/// Some devices expose a single feature through both a scalar actuator and a dedicated rotate or linear command.
+/// This decides which command is sent when a message addresses that feature both ways at once.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum CommandConflictPolicy {
+    /// send both commands and let the device sort it out
+    #[default]
+    SendBoth,
+    /// only send the scalar command
+    PreferScalar,
+    /// only send the rotate or linear command
+    PreferRotateOrLinear,
+}
+
+/// Decides what happens when one message sends more than one absolute command to the same tag, such as `v:0.3;v:0.7`.
+/// Relative adjustments are not affected, as several of them in one message simply add up.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum DuplicateTagPolicy {
+    /// the last command for the tag is used
+    #[default]
+    LastWins,
+    /// the command with the largest intensity, speed or position is used
+    Max,
+    /// the whole message is rejected
+    Reject,
+}
+
+/// What `GET /batterystatus` reports in place of a battery level for devices without a reading
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum UnknownBatteryLevel {
+    /// `-1`, which older clients expect
+    #[default]
+    NegativeOne,
+    /// `unknown`, which can't be mistaken for a real battery level
+    Unknown,
+}
+
+impl UnknownBatteryLevel {
+    pub fn text(self) -> &'static str {
+        match self {
+            UnknownBatteryLevel::NegativeOne => "-1",
+            UnknownBatteryLevel::Unknown => "unknown",
+        }
+    }
+}
+
+/// How battery readings are turned into a 0-1 fraction. Some device protocols report a 0-100 percentage instead.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum BatteryScale {
+    /// readings above 1 are percentages, and anything else is already a fraction
+    #[default]
+    Auto,
+    /// every reading is already a fraction
+    Fraction,
+    /// every reading is a percentage
+    Percent,
+}
+
+impl BatteryScale {
+    /// a battery reading as a fraction from 0 to 1
+    pub fn normalize(self, battery_level: f64) -> f64 {
+        let battery_level = match self {
+            BatteryScale::Auto if battery_level > 1.0 => battery_level / 100.0,
+            BatteryScale::Auto | BatteryScale::Fraction => battery_level,
+            BatteryScale::Percent => battery_level / 100.0,
+        };
+        battery_level.clamp(0.0, 1.0)
+    }
+}
+
+/// How the GUI orders connected devices
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum DeviceOrder {
+    /// alphabetically by device name
+    #[default]
+    Name,
+    /// in the order the devices connected, so that existing devices never move when a new one connects
+    ConnectionTime,
+    /// devices in [`ConfigurationV3::pinned_devices`] first, then the rest by name
+    Pinned,
+}
+
+/// The buttplug device communication managers buttplug-lite can run
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CommManager {
+    Bluetooth,
+    Serial,
+    LovenseHidDongle,
+    LovenseSerialDongle,
+    LovenseConnect,
+    XInput,
+}
+
+impl CommManager {
+    /// every communication manager supported on this platform
+    pub fn available() -> Vec<CommManager> {
+        let mut comm_managers = vec![
+            CommManager::Bluetooth,
+            CommManager::Serial,
+            CommManager::LovenseHidDongle,
+            CommManager::LovenseSerialDongle,
+            CommManager::LovenseConnect,
+        ];
+        if cfg!(target_os = "windows") {
+            comm_managers.push(CommManager::XInput);
+        }
+        comm_managers
+    }
+
+    /// name used to refer to this communication manager in the web API
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommManager::Bluetooth => "bluetooth",
+            CommManager::Serial => "serial",
+            CommManager::LovenseHidDongle => "lovense-hid-dongle",
+            CommManager::LovenseSerialDongle => "lovense-serial-dongle",
+            CommManager::LovenseConnect => "lovense-connect",
+            CommManager::XInput => "xinput",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<CommManager> {
+        CommManager::available().into_iter().find(|comm_manager| comm_manager.name() == name)
+    }
 }
 
 // encodes the "address" of a specific motor
@@ -94,6 +1070,47 @@ pub struct MotorConfigurationV3 {
     pub device_identifier: Option<String>,
     pub feature_index: u32,
     pub feature_type: MotorTypeV3,
+    /// disabled tags are retained in the configuration, but commands sent to them are ignored
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// once turned on, keep this motor on for at least this many milliseconds before letting it stop
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_on_time_ms: Option<u64>,
+    /// if set, the watchdog leaves this motor running when it halts devices
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub watchdog_exempt: bool,
+    /// if set, commanded values are mapped along this curve before being sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_curve: Option<ResponseCurveV3>,
+}
+
+impl MotorConfigurationV3 {
+    /// check if two configurations refer to the same physical motor, ignoring any per-tag settings
+    pub fn is_same_motor(&self, other: &MotorConfigurationV3) -> bool {
+        self.device_name == other.device_name
+            && self.device_identifier == other.device_identifier
+            && self.feature_index == other.feature_index
+            && self.feature_type == other.feature_type
+    }
+
+    /// What motors are sorted by: their device, then their feature index. Settings such as `enabled` are left out, so
+    /// changing them doesn't move a motor. Devices are told apart by identifier, or by name for legacy motors without one.
+    pub fn order_key(&self) -> (Option<&str>, &str, u32) {
+        (self.device_identifier.as_deref(), &self.device_name, self.feature_index)
+    }
+
+    pub fn min_on_time(&self) -> Option<Duration> {
+        self.min_on_time_ms.filter(|min_on_time_ms| *min_on_time_ms > 0).map(Duration::from_millis)
+    }
+
+    /// An example command for this motor tagged with `tag`, such as `tag:0.5`. Disabled motors have none, as commands sent to them would be ignored.
+    pub fn example_command(&self, tag: &str) -> Option<String> {
+        self.enabled.then(|| match self.feature_type {
+            MotorTypeV3::Linear => format!("{tag}:20:0.5"),
+            MotorTypeV3::Rotation => format!("{tag}:-0.5"),
+            MotorTypeV3::Scalar { .. } => format!("{tag}:0.5"),
+        })
+    }
 }
 
 impl Display for MotorConfigurationV3 {
@@ -115,10 +1132,109 @@ impl TryFrom<MotorConfigurationV2> for MotorConfigurationV3 {
             device_identifier: None,
             feature_type: type_v3,
             feature_index: config_v2.feature_index,
+            enabled: true,
+            min_on_time_ms: None,
+            watchdog_exempt: false,
+            response_curve: None,
+        })
+    }
+}
+
+/// The tags of a configuration in the order of [`ConfigurationV3::tags_in_order`], worked out the first time it's needed
+/// and forgotten whenever the tags change
+#[derive(Debug, Default, Clone)]
+struct TagOrder(OnceLock<Vec<String>>);
+
+impl TagOrder {
+    fn get(&self, tags: &HashMap<String, MotorConfigurationV3>) -> &[String] {
+        self.0.get_or_init(|| {
+            let mut tags: Vec<(&String, &MotorConfigurationV3)> = tags.iter().collect();
+            tags.sort_unstable_by(|(tag_a, motor_a), (tag_b, motor_b)| (motor_a.order_key(), tag_a).cmp(&(motor_b.order_key(), tag_b)));
+            tags.into_iter().map(|(tag, _)| tag.clone()).collect()
         })
     }
 }
 
+impl PartialEq for TagOrder {
+    /// the order follows from the tags, which are compared separately
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// An example message commanding each of these tagged motors, such as `a:0.5;b:-0.5`
+pub fn build_example_message<'a>(tagged_motors: impl IntoIterator<Item=(&'a str, &'a MotorConfigurationV3)>) -> String {
+    tagged_motors.into_iter()
+        .filter_map(|(tag, motor)| motor.example_command(tag))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// A piecewise-linear mapping from commanded values to sent values, as `[input, output]` points. Inputs must be strictly
+/// increasing, and every input and output must be from 0 to 1.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(transparent)]
+pub struct ResponseCurveV3(pub Vec<[f64; 2]>);
+
+impl ResponseCurveV3 {
+    /// why these points don't make a usable curve, if they don't
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.0.len() < 2 {
+            Some("it needs at least two points")
+        } else if self.0.iter().flatten().any(|value| !(0.0..=1.0).contains(value)) {
+            Some("every input and output must be from 0 to 1")
+        } else if self.0.windows(2).any(|points| points[0][0] >= points[1][0]) {
+            Some("its inputs must be strictly increasing")
+        } else {
+            None
+        }
+    }
+
+    /// Map a value's magnitude along the curve, interpolating between points and keeping its sign.
+    /// Magnitudes outside the curve's inputs take the output of the nearest point, except that 0 always stays 0 so
+    /// motors can still be stopped.
+    pub fn apply(&self, value: f64) -> f64 {
+        if value == 0.0 {
+            return value;
+        }
+        let input = value.abs();
+        let output = match self.0.iter().position(|[point_input, _]| *point_input >= input) {
+            Some(0) => self.0[0][1],
+            Some(index) => {
+                let [[input_0, output_0], [input_1, output_1]] = [self.0[index - 1], self.0[index]];
+                output_0 + (output_1 - output_0) * (input - input_0) / (input_1 - input_0)
+            }
+            None => self.0.last().map_or(input, |[_, output]| *output),
+        };
+        output.copysign(value)
+    }
+}
+
+// compared by each value's bits, so that motor configurations can still be sorted and deduplicated
+impl PartialEq for ResponseCurveV3 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ResponseCurveV3 {}
+
+impl Ord for ResponseCurveV3 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().flatten()
+            .zip(other.0.iter().flatten())
+            .map(|(value, other_value)| value.total_cmp(other_value))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| self.0.len().cmp(&other.0.len()))
+    }
+}
+
+impl PartialOrd for ResponseCurveV3 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
 #[serde(tag = "type")]
 pub enum MotorTypeV3 {
@@ -202,3 +1318,249 @@ impl Display for MotorTypeV3 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_renames_are_compiled_on_load() {
+        let configuration: ConfigurationV3 = toml::from_str(r#"
+            version = 3
+            port = 3031
+            [tags]
+            [[device_renames]]
+            pattern = "^Lovense (\\w+)$"
+            replacement = "$1"
+            [[device_renames]]
+            pattern = "("
+        "#).unwrap();
+        assert!(configuration.device_renames[0].pattern.regex().is_ok());
+        assert!(configuration.device_renames[1].pattern.regex().is_err());
+        // invalid patterns are skipped
+        assert_eq!(configuration.device_display_name("Lovense Hush"), "Hush");
+
+        let serialized = toml::to_string(&configuration).unwrap();
+        assert_eq!(toml::from_str::<ConfigurationV3>(&serialized).unwrap(), configuration);
+    }
+
+    fn motor(feature_index: u32) -> MotorConfigurationV3 {
+        MotorConfigurationV3 {
+            device_name: "device".to_string(),
+            device_identifier: Some("test://device".to_string()),
+            feature_index,
+            feature_type: MotorTypeV3::Scalar { actuator_type: ActuatorType::Vibrate },
+            enabled: true,
+            min_on_time_ms: None,
+            watchdog_exempt: false,
+            response_curve: None,
+        }
+    }
+
+    /// a valid configuration with a vibrator for each tag
+    fn configuration(tags: &[&str]) -> ConfigurationV3 {
+        let mut configuration = ConfigurationV3::default();
+        for (feature_index, tag) in (0..).zip(tags) {
+            configuration.tags_mut().insert(tag.to_string(), motor(feature_index));
+        }
+        configuration
+    }
+
+    /// the messages of the problems `validate` finds
+    fn problems(configuration: &ConfigurationV3) -> Vec<String> {
+        configuration.validate().err().unwrap_or_default().iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn validate_version() {
+        let mut configuration = configuration(&["v"]);
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.version = 2;
+        assert_eq!(problems(&configuration), vec!["configuration version 2 is not the current version 3"]);
+    }
+
+    #[test]
+    fn validate_port() {
+        let mut configuration = configuration(&["v"]);
+        configuration.port = 1;
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.port = 0;
+        assert_eq!(problems(&configuration), vec!["port 0 is not allowed"]);
+    }
+
+    #[test]
+    fn validate_empty_tag() {
+        assert_eq!(problems(&configuration(&["v"])), Vec::<String>::new());
+        assert_eq!(problems(&configuration(&[""])), vec!["tags may not be empty"]);
+    }
+
+    #[test]
+    fn validate_tag_length() {
+        let longest = "a".repeat(MAX_TAG_LENGTH);
+        assert_eq!(problems(&configuration(&[&longest])), Vec::<String>::new());
+
+        let too_long = "a".repeat(MAX_TAG_LENGTH + 1);
+        assert_eq!(problems(&configuration(&[&too_long])), vec![r#"129 byte tag starting with "aaaaaaaaaaaaaaaa" is longer than 128 bytes"#]);
+    }
+
+    #[test]
+    fn validate_tag_separators() {
+        assert_eq!(problems(&configuration(&["left-v", "v+"])), Vec::<String>::new());
+        assert_eq!(problems(&configuration(&["a:b", "c;d"])), vec![r#"tag "a:b" may not contain `:` or `;`"#, r#"tag "c;d" may not contain `:` or `;`"#]);
+    }
+
+    #[test]
+    fn validate_ambiguous_tags() {
+        let mut configuration = configuration(&["v", "V"]);
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.case_insensitive_tags = true;
+        assert_eq!(problems(&configuration), vec![r#"tags "V" and "v" only differ in case, so they are ambiguous with case_insensitive_tags"#]);
+    }
+
+    #[test]
+    fn validate_response_curves() {
+        let mut configuration = configuration(&["v"]);
+        configuration.tags_mut().get_mut("v").unwrap().response_curve = Some(ResponseCurveV3(vec![[0.0, 0.0], [1.0, 1.0]]));
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.tags_mut().get_mut("v").unwrap().response_curve = Some(ResponseCurveV3(vec![[0.0, 0.0]]));
+        assert_eq!(problems(&configuration), vec![r#"response curve of tag "v" can't be used, as it needs at least two points"#]);
+    }
+
+    #[test]
+    fn validate_composite_tag_members() {
+        let member = |tag: &str| CompositeTagMemberV3 { tag: tag.to_string(), weight: 1.0 };
+        let mut configuration = configuration(&["v"]);
+        configuration.composite_tags.insert("inner".to_string(), vec![member("v")]);
+        configuration.composite_tags.insert("outer".to_string(), vec![member("inner"), member("v")]);
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.composite_tags.insert("broken".to_string(), vec![member("missing")]);
+        assert_eq!(problems(&configuration), vec![r#"composite tag "broken" refers to unknown tag "missing""#]);
+    }
+
+    #[test]
+    fn validate_recursive_composite_tags() {
+        let member = |tag: &str| CompositeTagMemberV3 { tag: tag.to_string(), weight: 1.0 };
+        let mut configuration = configuration(&["v"]);
+        configuration.composite_tags.insert("a".to_string(), vec![member("v")]);
+        configuration.composite_tags.insert("b".to_string(), vec![member("a")]);
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.composite_tags.insert("a".to_string(), vec![member("b")]);
+        assert_eq!(problems(&configuration), vec![r#"composite tag "a" fans out to itself"#, r#"composite tag "b" fans out to itself"#]);
+    }
+
+    #[test]
+    fn validate_motor_group_members() {
+        let mut configuration = configuration(&["v"]);
+        configuration.motor_groups.insert("left".to_string(), vec!["v".to_string()]);
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        // group members must match a tag exactly, even with case_insensitive_tags
+        configuration.case_insensitive_tags = true;
+        configuration.motor_groups.insert("left".to_string(), vec!["V".to_string()]);
+        assert_eq!(problems(&configuration), vec![r#"motor group "left" refers to unknown tag "V""#]);
+    }
+
+    #[test]
+    fn validate_pattern_steps() {
+        let step = |tag: &str| PatternStepV3 { tag: tag.to_string(), value: 1.0, duration_ms: 100 };
+        let mut configuration = configuration(&["v"]);
+        configuration.patterns.insert("pulse".to_string(), vec![step("v")]);
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.patterns.insert("pulse".to_string(), vec![step("v"), step("missing")]);
+        assert_eq!(problems(&configuration), vec![r#"pattern "pulse" refers to unknown tag "missing""#]);
+    }
+
+    #[test]
+    fn validate_midi_mappings() {
+        let mapping = |channel: u8, controller: u8, tag: &str| MidiMappingV3 { channel, controller, tag: tag.to_string() };
+        let mut configuration = configuration(&["v"]);
+        configuration.midi_input = Some(MidiInputV3 {
+            port: None,
+            mappings: vec![mapping(1, 0, "v"), mapping(16, 127, "v")],
+        });
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.midi_input = Some(MidiInputV3 {
+            port: None,
+            mappings: vec![mapping(0, 1, "v"), mapping(1, 128, "v"), mapping(2, 3, "missing")],
+        });
+        assert_eq!(problems(&configuration), vec![
+            "MIDI mapping for channel 0 controller 1 can never match, as channels are from 1 to 16 and controllers from 0 to 127",
+            "MIDI mapping for channel 1 controller 128 can never match, as channels are from 1 to 16 and controllers from 0 to 127",
+            r#"MIDI mapping for channel 2 controller 3 refers to unknown tag "missing""#,
+        ]);
+    }
+
+    #[test]
+    fn validate_debug_tags() {
+        let mut configuration = configuration(&["v"]);
+        configuration.debug_tags = vec!["v".to_string()];
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.debug_tags = vec!["v".to_string(), "missing".to_string()];
+        assert_eq!(problems(&configuration), vec![r#"debug_tags refers to unknown tag "missing""#]);
+    }
+
+    #[test]
+    fn validate_device_renames() {
+        let mut configuration = configuration(&["v"]);
+        configuration.device_renames = vec![DeviceRenameV3 { pattern: RenamePattern::new("^Lovense (\\w+)$"), replacement: "$1".to_string() }];
+        assert_eq!(problems(&configuration), Vec::<String>::new());
+
+        configuration.device_renames = vec![DeviceRenameV3 { pattern: RenamePattern::new("("), replacement: String::new() }];
+        let problems = problems(&configuration);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with(r#"device rename pattern "(" is not a valid regex: "#), "{}", problems[0]);
+    }
+
+    #[test]
+    fn tag_order_ignores_motor_settings() {
+        let mut configuration = configuration(&["b", "a", "c"]);
+        configuration.tags_mut().get_mut("a").unwrap().device_identifier = Some("test://other".to_string());
+        configuration.tags_mut().get_mut("b").unwrap().enabled = false;
+        configuration.tags_mut().get_mut("c").unwrap().feature_index = 0;
+        configuration.tags_mut().get_mut("c").unwrap().feature_type = MotorTypeV3::Rotation;
+        let tags: Vec<&str> = configuration.tags_in_order().into_iter().map(|(tag, _)| tag).collect();
+        // sorted by identifier and feature index, with the tag breaking ties
+        assert_eq!(tags, vec!["b", "c", "a"]);
+
+        configuration.motor_index_addressing = true;
+        assert_eq!(configuration.resolve_tag("#2").map(|(tag, _)| tag), Some("a"));
+        assert_eq!(configuration.resolve_tag("#3"), None);
+
+        // changing the tags after the order was worked out changes the order
+        let mut changed = configuration.clone();
+        changed.tags_mut().insert("0".to_string(), motor(0));
+        changed.tags_mut().get_mut("a").unwrap().device_identifier = None;
+        assert_eq!(changed.resolve_tag("#0").map(|(tag, _)| tag), Some("a"));
+        assert_eq!(changed.resolve_tag("#1").map(|(tag, _)| tag), Some("0"));
+        assert_eq!(configuration.resolve_tag("#0").map(|(tag, _)| tag), Some("b"));
+
+        // as do the GUI's changes
+        let tags = HashMap::from([("z".to_string(), motor(0))]);
+        let from_gui = configuration.with_gui_settings(configuration.port, tags, false);
+        assert_eq!(from_gui.resolve_tag("#0").map(|(tag, _)| tag), Some("z"));
+    }
+
+    #[test]
+    fn response_curve_keeps_zero_at_zero() {
+        // a curve starting above zero still lets motors stop
+        let raised_floor = ResponseCurveV3(vec![[0.0, 0.2], [1.0, 1.0]]);
+        assert_eq!(raised_floor.apply(0.0), 0.0);
+        assert_eq!(raised_floor.apply(0.25), 0.4);
+
+        // as does a curve starting after zero
+        let late_start = ResponseCurveV3(vec![[0.2, 0.3], [1.0, 1.0]]);
+        assert_eq!(late_start.apply(0.0), 0.0);
+        assert_eq!(late_start.apply(-0.0), 0.0);
+        assert_eq!(late_start.apply(0.1), 0.3);
+        assert_eq!(late_start.apply(-0.1), -0.3);
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::io;
+
+use thiserror::Error;
+use tokio::task::JoinError;
+
+use crate::config::{CONFIG_ENV_VAR, CONFIG_VERSION};
+use crate::config::v3::MAX_TAG_LENGTH;
+
+/// Errors that can occur while loading, saving, or applying a configuration
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("configuration I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to serialize configuration: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("failed to deserialize configuration: {0}")]
+    Deserialize(#[from] toml::de::Error),
+    #[error("failed to deserialize configuration: {0}")]
+    DeserializeJson(#[from] serde_json::Error),
+    #[error("configuration would not read back the same after saving, so the existing file was kept")]
+    RoundTrip,
+    #[error("the base configuration would override some of these settings, such as removed tags or settings put back to their defaults, so nothing was saved")]
+    LayerOverride,
+    #[error("the configuration comes from the {CONFIG_ENV_VAR} environment variable, so it can't be saved")]
+    ConfiguredByEnvironment,
+    #[error("cannot update configuration until after initial haptic server startup")]
+    ServerNotStarted,
+    #[error("failed to signal web server restart")]
+    WebserverRestart,
+    #[error("configuration task failed: {0}")]
+    Task(#[from] JoinError),
+}
+
+/// A problem found by [`ConfigurationV3::validate`](crate::config::v3::ConfigurationV3::validate)
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("configuration version {version} is not the current version {CONFIG_VERSION}")]
+    OutdatedVersion { version: i32 },
+    #[error("port 0 is not allowed")]
+    Port,
+    #[error("tags may not be empty")]
+    EmptyTag,
+    #[error("tag {tag:?} may not contain `:` or `;`")]
+    TagSeparator { tag: String },
+    #[error("{length} byte tag starting with {start:?} is longer than {MAX_TAG_LENGTH} bytes")]
+    TagTooLong { length: usize, start: String },
+    #[error("tags {first:?} and {second:?} only differ in case, so they are ambiguous with case_insensitive_tags")]
+    AmbiguousTags { first: String, second: String },
+    #[error("{referrer} refers to unknown tag {tag:?}")]
+    UnknownTag { referrer: String, tag: String },
+    #[error("composite tag {tag:?} fans out to itself")]
+    RecursiveCompositeTag { tag: String },
+    #[error("device rename pattern {pattern:?} is not a valid regex: {reason}")]
+    InvalidDeviceRename { pattern: String, reason: String },
+    #[error("response curve of tag {tag:?} can't be used, as {reason}")]
+    InvalidResponseCurve { tag: String, reason: &'static str },
+    #[error("MIDI mapping for channel {channel} controller {controller} can never match, as channels are from 1 to 16 and controllers from 0 to 127")]
+    MidiMapping { channel: u8, controller: u8 },
+}
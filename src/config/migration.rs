@@ -0,0 +1,110 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! Previews what migrating an outdated configuration file would do to its tags, without changing anything
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+
+use crate::config::{read_configuration_file, ConfigError, ConfigurationMinimal, CONFIG_DIR_FILE_PATH, CONFIG_VERSION};
+use crate::config::v2::{ConfigurationV2, MotorConfigurationV2, MotorTypeV2};
+use crate::config::v3::ConfigurationV3;
+
+/// Describe which tags in the configuration file would be dropped or changed by migrating it to the current version,
+/// for the caller to print. This uses the same conversion as loading the configuration, but nothing is written.
+pub fn migration_preview() -> Result<String, ConfigError> {
+    let version = read_configuration_file::<ConfigurationMinimal>()?.version;
+    if !ConfigurationV3::is_version_outdated(version) {
+        return Ok(format!("configuration {:?} is already v{CONFIG_VERSION}, so there is nothing to migrate\n", *CONFIG_DIR_FILE_PATH));
+    }
+    let configuration_v2: ConfigurationV2 = read_configuration_file()?;
+    Ok(describe_migration(configuration_v2, version))
+}
+
+fn describe_migration(configuration_v2: ConfigurationV2, version: i32) -> String {
+    // devices with a contraction motor lose all their tags, not just the contraction motor's
+    let contraction_device_names: HashSet<String> = configuration_v2.tags.values()
+        .filter(|motor| motor.feature_type == MotorTypeV2::Contraction)
+        .map(|motor| motor.device_name.clone())
+        .collect();
+    // why each tag would be dropped, in case the conversion does drop it
+    let tags_v2: BTreeMap<String, (String, &str)> = configuration_v2.tags.iter()
+        .map(|(tag, motor)| {
+            let drop_reason = if motor.feature_type == MotorTypeV2::Contraction {
+                "contraction motors are no longer supported"
+            } else if contraction_device_names.contains(&motor.device_name) {
+                "its device also has a contraction motor"
+            } else {
+                "it could not be converted"
+            };
+            (tag.clone(), (describe_motor_v2(motor), drop_reason))
+        })
+        .collect();
+    let tag_count = tags_v2.len();
+    let configuration_v3: ConfigurationV3 = configuration_v2.into();
+
+    // writing to a String can't fail
+    let mut report = String::new();
+    let _ = writeln!(report, "migrating configuration {:?} from v{version} to v{CONFIG_VERSION} would change its {tag_count} tags like so:", *CONFIG_DIR_FILE_PATH);
+    let mut dropped_count = 0;
+    for (tag, (before, drop_reason)) in tags_v2 {
+        match configuration_v3.tags().get(&tag) {
+            Some(motor) => {
+                let _ = writeln!(report, "{tag:?}: {before} becomes {motor}");
+            }
+            None => {
+                dropped_count += 1;
+                let _ = writeln!(report, "{tag:?}: {before} would be dropped, as {drop_reason}");
+            }
+        }
+    }
+    let _ = writeln!(report, "{dropped_count} of {tag_count} tags would be dropped. Motors are matched by device name only until they are tagged again. Nothing was written.");
+    report
+}
+
+/// a v2 motor, in the same format as a migrated motor is displayed
+fn describe_motor_v2(motor: &MotorConfigurationV2) -> String {
+    let feature_type = match motor.feature_type {
+        MotorTypeV2::Linear => "linear",
+        MotorTypeV2::Rotation => "rotation",
+        MotorTypeV2::Vibration => "vibration",
+        MotorTypeV2::Contraction => "contraction",
+    };
+    format!("{} {feature_type}#{}", motor.device_name, motor.feature_index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_is_described() {
+        let configuration_v2: ConfigurationV2 = toml::from_str(r#"
+            version = 2
+            port = 3031
+            [tags.v]
+            device_name = "toy"
+            feature_type = "Vibration"
+            feature_index = 0
+            [tags.squeeze]
+            device_name = "squeezer"
+            feature_type = "Contraction"
+            feature_index = 0
+            [tags.buzz]
+            device_name = "squeezer"
+            feature_type = "Vibration"
+            feature_index = 1
+        "#).unwrap();
+
+        let report = describe_migration(configuration_v2, 2);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 5, "{report}");
+        assert!(lines[0].ends_with("from v2 to v3 would change its 3 tags like so:"), "{report}");
+        // tags are listed in order
+        assert_eq!(lines[1], r#""buzz": squeezer vibration#1 would be dropped, as its device also has a contraction motor"#);
+        assert_eq!(lines[2], r#""squeeze": squeezer contraction#0 would be dropped, as contraction motors are no longer supported"#);
+        assert!(lines[3].starts_with(r#""v": toy vibration#0 becomes "#), "{report}");
+        assert!(lines[4].starts_with("2 of 3 tags would be dropped."), "{report}");
+    }
+}
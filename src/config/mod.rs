@@ -3,13 +3,19 @@
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
 pub use configuration_minimal::ConfigurationMinimal;
+pub use error::{ConfigError, ValidationError};
 pub use util::*;
 
 mod configuration_minimal;
 mod configuration_v2;
 mod configuration_v3;
+mod error;
 mod util;
 
+pub mod migration;
+pub mod recovery;
+pub mod watcher;
+
 pub mod v2 {
     pub use super::configuration_v2::*;
 }
@@ -0,0 +1,153 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! Crash recovery for configuration changes that have not been saved yet.
+//!
+//! A marker file exists for as long as the GUI is open. If it is still present at startup then the previous session did
+//! not exit cleanly, and any snapshot it left behind is offered to the user.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use tracing::{info, warn};
+
+use crate::config::{ConfigError, CONFIG_DIR_FILE_PATH};
+use crate::config::v3::ConfigurationV3;
+
+lazy_static! {
+    static ref RECOVERY_FILES: RecoveryFiles = RecoveryFiles {
+        snapshot: CONFIG_DIR_FILE_PATH.with_file_name("recovery.toml"),
+        session_marker: CONFIG_DIR_FILE_PATH.with_file_name("session.lock"),
+    };
+}
+
+/// mark a GUI session as started, returning any unsaved configuration left behind by a session that did not exit cleanly
+pub fn begin_session() -> Option<ConfigurationV3> {
+    RECOVERY_FILES.begin_session()
+}
+
+/// mark the GUI session as cleanly exited. Any unsaved changes are intentionally discarded.
+pub fn end_session() {
+    RECOVERY_FILES.end_session();
+}
+
+/// save in-progress changes so they can be recovered after a crash
+pub fn save_snapshot(configuration: &ConfigurationV3) {
+    RECOVERY_FILES.save_snapshot(configuration);
+}
+
+/// discard the recovery snapshot, if any
+pub fn clear_snapshot() {
+    RECOVERY_FILES.clear_snapshot();
+}
+
+/// where the recovery files live
+struct RecoveryFiles {
+    snapshot: PathBuf,
+    session_marker: PathBuf,
+}
+
+impl RecoveryFiles {
+    fn begin_session(&self) -> Option<ConfigurationV3> {
+        let recovered_configuration = if self.session_marker.exists() {
+            self.read_snapshot()
+        } else {
+            None
+        };
+
+        if let Err(e) = fs::write(&self.session_marker, "") {
+            warn!("unable to create session marker, crash recovery will not work: {e}");
+        }
+
+        recovered_configuration
+    }
+
+    fn end_session(&self) {
+        self.clear_snapshot();
+        remove_file(&self.session_marker);
+    }
+
+    fn save_snapshot(&self, configuration: &ConfigurationV3) {
+        if let Err(e) = self.write_snapshot(configuration) {
+            warn!("unable to save recovery snapshot: {e}");
+        }
+    }
+
+    fn clear_snapshot(&self) {
+        remove_file(&self.snapshot);
+    }
+
+    fn write_snapshot(&self, configuration: &ConfigurationV3) -> Result<(), ConfigError> {
+        fs::write(&self.snapshot, toml::to_string(configuration)?)?;
+        Ok(())
+    }
+
+    fn read_snapshot(&self) -> Option<ConfigurationV3> {
+        match fs::read_to_string(&self.snapshot) {
+            Ok(snapshot) => match toml::from_str(&snapshot) {
+                Ok(configuration) => {
+                    info!("found unsaved configuration from a session that did not exit cleanly");
+                    Some(configuration)
+                }
+                Err(e) => {
+                    warn!("ignoring unreadable recovery snapshot: {e}");
+                    None
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!("unable to read recovery snapshot: {e}");
+                None
+            }
+        }
+    }
+}
+
+fn remove_file(path: &Path) {
+    match fs::remove_file(path) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => warn!("unable to remove {path:?}: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// recovery files in a fresh directory of their own
+    fn recovery_files(name: &str) -> RecoveryFiles {
+        let directory = std::env::temp_dir().join(format!("buttplug-lite-recovery-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        RecoveryFiles {
+            snapshot: directory.join("recovery.toml"),
+            session_marker: directory.join("session.lock"),
+        }
+    }
+
+    #[test]
+    fn clean_exit_is_not_recovered() {
+        let files = recovery_files("clean");
+        assert_eq!(files.begin_session(), None);
+        files.save_snapshot(&ConfigurationV3::default());
+        files.end_session();
+        assert!(!files.session_marker.exists());
+        assert!(!files.snapshot.exists());
+        assert_eq!(files.begin_session(), None);
+    }
+
+    #[test]
+    fn crash_is_recovered() {
+        let files = recovery_files("crash");
+        assert_eq!(files.begin_session(), None);
+        let mut configuration = ConfigurationV3::default();
+        configuration.port = 4000;
+        files.save_snapshot(&configuration);
+        // the session never ends, as if the application crashed
+        assert_eq!(files.begin_session(), Some(configuration));
+    }
+}
@@ -2,29 +2,55 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
-use std::{convert, fs};
+use std::env;
+use std::fs;
+use std::io;
 use std::ops::DerefMut as _;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+use chrono::Local;
 use directories::ProjectDirs;
 use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
 use tokio::sync::mpsc;
 use tokio::task;
 use tracing::{info, warn};
 
 use crate::{ApplicationState, ApplicationStateDb, ShutdownMessage};
+use crate::app::buttplug as app_buttplug;
 use crate::config::CONFIG_VERSION;
-use crate::config::ConfigurationMinimal;
+use crate::config::{ConfigError, ConfigurationMinimal};
 use crate::config::v2::ConfigurationV2;
 use crate::config::v3::ConfigurationV3;
+use crate::util::watchdog;
+use crate::util::watchdog::WatchdogTimeoutDb;
 
 static CONFIG_FILE_NAME: &str = "config.toml";
+static BACKUP_CONFIG_FILE_PREFIX: &str = "backup_config_";
+/// how many configuration backups are kept if the configuration doesn't say
+const DEFAULT_CONFIG_BACKUPS: usize = 10;
+
+/// environment variable that may hold an entire configuration, which is used instead of the configuration file
+pub static CONFIG_ENV_VAR: &str = "BUTTPLUG_LITE_CONFIG";
+
+/// A shared base configuration with a machine-specific configuration layered over it
+#[derive(Debug, Clone)]
+pub struct ConfigurationLayers {
+    pub base: PathBuf,
+    /// tags in here replace base tags of the same name, and every other setting in here replaces the base's
+    pub overrides: PathBuf,
+}
+
+/// set at startup if the configuration is layered over a base configuration
+static CONFIGURATION_LAYERS: OnceLock<ConfigurationLayers> = OnceLock::new();
 
 lazy_static! {
     pub static ref CONFIG_DIR_FILE_PATH: PathBuf = create_config_file_path();
 }
 
-fn get_config_dir() -> PathBuf {
+/// directory containing the configuration file and the other files kept next to it
+pub fn get_config_dir() -> PathBuf {
     ProjectDirs::from("io.github", "runtime-shady-backroom", env!("CARGO_PKG_NAME"))
         .expect("unable to locate configuration directory")
         .config_dir()
@@ -37,73 +63,174 @@ fn create_config_file_path() -> PathBuf {
     config_dir_path.join(CONFIG_FILE_NAME)
 }
 
+/// A new path to back up a configuration of this version to. The timestamp keeps earlier backups from being overwritten.
 pub fn get_backup_config_file_path(version: i32) -> PathBuf {
-    get_config_dir().join(format!("backup_config_v{version}.toml"))
+    get_config_dir().join(format!("{BACKUP_CONFIG_FILE_PREFIX}v{version}_{}.toml", Local::now().format("%Y-%m-%d_%H-%M-%S_%3f")))
 }
 
-/// update in-memory configuration
-pub async fn update_configuration(application_state_db: &ApplicationStateDb, configuration: ConfigurationV3, warp_shutdown_tx: &mpsc::UnboundedSender<ShutdownMessage>) -> Result<ConfigurationV3, String> {
+/// Copy the configuration file to a new backup, then delete the oldest backups so only `retained` are left.
+/// Nothing is backed up if `retained` is 0.
+fn back_up_configuration(version: i32, retained: usize) {
+    if retained == 0 {
+        return;
+    }
+    let backup_path = get_backup_config_file_path(version);
+    fs::copy(CONFIG_DIR_FILE_PATH.as_path(), &backup_path).expect("failed to back up config");
+    info!("backed up configuration to {backup_path:?}");
+    if let Err(e) = clean_up_old_backups(retained) {
+        warn!("failed to clean up old configuration backups: {e}");
+    }
+}
+
+/// Delete the oldest configuration backups, retaining up to `retained`. Backups are ordered by when they were written,
+/// as backups made by older versions have no timestamp in their name.
+fn clean_up_old_backups(retained: usize) -> io::Result<()> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(get_config_dir())? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_backup = path.is_file() && path.file_name().and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(BACKUP_CONFIG_FILE_PREFIX) && name.ends_with(".toml"));
+        if is_backup {
+            backups.push((entry.metadata()?.modified()?, path));
+        }
+    }
+    backups.sort_unstable();
+    if let Some(backups_to_delete) = backups.len().checked_sub(retained) {
+        for (_, path) in backups.into_iter().take(backups_to_delete) {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// save configuration to disk and update in-memory configuration
+pub async fn update_configuration(application_state_db: &ApplicationStateDb, watchdog_timeout_db: &WatchdogTimeoutDb, configuration: ConfigurationV3, warp_shutdown_tx: &mpsc::UnboundedSender<ShutdownMessage>) -> Result<ConfigurationV3, ConfigError> {
     save_configuration(&configuration).await?;
+    apply_configuration(application_state_db, watchdog_timeout_db, configuration, warp_shutdown_tx).await
+}
+
+/// update in-memory configuration without touching the configuration file
+pub async fn apply_configuration(application_state_db: &ApplicationStateDb, watchdog_timeout_db: &WatchdogTimeoutDb, configuration: ConfigurationV3, warp_shutdown_tx: &mpsc::UnboundedSender<ShutdownMessage>) -> Result<ConfigurationV3, ConfigError> {
     let mut lock = application_state_db.write().await;
     let previous_state = lock.deref_mut().take();
     match previous_state {
-        Some(ApplicationState { client, configuration: previous_configuration, device_manager }) => {
+        Some(ApplicationState { client, configuration: previous_configuration, device_manager, device_numbering, scanning }) => {
             let new_port = configuration.port;
-            *lock = Some(ApplicationState {
+            let mut application_state = ApplicationState {
                 client,
                 configuration: configuration.clone(),
                 device_manager,
-            });
+                device_numbering,
+                scanning,
+            };
+            // numbering may have just been turned on, or devices may have been renamed
+            app_buttplug::number_devices(&mut application_state);
+            *lock = Some(application_state);
             drop(lock);
 
             // restart warp if necessary
             if new_port != previous_configuration.port {
                 warp_shutdown_tx.send(ShutdownMessage::Restart)
-                    .map_err(|e| format!("{e:?}"))?;
+                    .map_err(|_| ConfigError::WebserverRestart)?;
+            }
+
+            // the watchdog's halt also makes the rest of the application forget what it last commanded
+            if configuration.stop_devices_on_remap && configuration.tags() != previous_configuration.tags() {
+                info!("tags changed, halting devices");
+                watchdog::request_halt(watchdog_timeout_db);
             }
 
             Ok(configuration)
         }
-        None => Err("cannot update configuration until after initial haptic server startup".into())
+        None => Err(ConfigError::ServerNotStarted)
     }
 }
 
-/// save configuration to disk
-pub async fn save_configuration(configuration: &ConfigurationV3) -> Result<(), String> {
-    // config serialization should never fail, so we should be good to panic
-    let serialized_config = toml::to_string(configuration).expect("failed to serialize configuration");
+/// Layer the configuration over a base configuration from now on. This must be done before the configuration is first loaded.
+pub fn use_configuration_layers(configuration_layers: ConfigurationLayers) {
+    if CONFIGURATION_LAYERS.set(configuration_layers).is_err() {
+        warn!("ignoring configuration layers, as they were already set");
+    }
+}
+
+/// if the configuration is layered over a base configuration
+pub fn is_configured_by_layers() -> bool {
+    CONFIGURATION_LAYERS.get().is_some()
+}
+
+/// Save configuration to disk. This is refused if the configuration comes from [`CONFIG_ENV_VAR`], as the saved file would be ignored.
+pub async fn save_configuration(configuration: &ConfigurationV3) -> Result<(), ConfigError> {
+    if is_configured_by_environment() {
+        return Err(ConfigError::ConfiguredByEnvironment);
+    }
+    if let Some(configuration_layers) = CONFIGURATION_LAYERS.get() {
+        return save_layered_configuration(configuration, configuration_layers).await;
+    }
+    let serialized_config = toml::to_string(configuration)?;
+    // refuse to replace a working file with one that would load differently, or not at all
+    if toml::from_str::<ConfigurationV3>(&serialized_config).ok().as_ref() != Some(configuration) {
+        return Err(ConfigError::RoundTrip);
+    }
     task::spawn_blocking(|| {
-        fs::write(CONFIG_DIR_FILE_PATH.as_path(), serialized_config).map_err(|e| format!("{e:?}"))
-    }).await
-        .map_err(|e| format!("{e:?}"))
-        .and_then(convert::identity)
+        fs::write(CONFIG_DIR_FILE_PATH.as_path(), serialized_config)
+    }).await??;
+    Ok(())
+}
+
+/// Save only the settings that differ from the base layer into the overrides layer, so later changes to the base still
+/// show through. Nothing is saved if the base would override some of the settings anyway, such as tags that were removed.
+async fn save_layered_configuration(configuration: &ConfigurationV3, configuration_layers: &ConfigurationLayers) -> Result<(), ConfigError> {
+    let base = read_configuration_layer(&configuration_layers.base);
+    let overrides = configuration_layer_overrides(&base, toml::Table::try_from(configuration)?);
+    let serialized_overrides = toml::to_string(&overrides)?;
+    let merged_configuration = toml::to_string(&merge_configuration_layers(base, overrides))?;
+    if toml::from_str::<ConfigurationV3>(&merged_configuration).ok().as_ref() != Some(configuration) {
+        return Err(ConfigError::LayerOverride);
+    }
+    let path = configuration_layers.overrides.clone();
+    task::spawn_blocking(move || {
+        fs::write(path, serialized_overrides)
+    }).await??;
+    Ok(())
+}
+
+/// if the configuration comes from [`CONFIG_ENV_VAR`] instead of the configuration file
+pub fn is_configured_by_environment() -> bool {
+    configuration_from_environment().is_some()
+}
+
+fn configuration_from_environment() -> Option<String> {
+    env::var(CONFIG_ENV_VAR).ok().filter(|configuration| !configuration.trim().is_empty())
 }
 
 pub async fn load_configuration() -> ConfigurationV3 {
+    if let Some(configuration) = configuration_from_environment() {
+        return load_configuration_from_environment(&configuration);
+    }
+    if let Some(configuration_layers) = CONFIGURATION_LAYERS.get() {
+        return load_layered_configuration(configuration_layers);
+    }
+
     info!("Attempting to load config from {:?}", *CONFIG_DIR_FILE_PATH);
-    let loaded_configuration: Result<ConfigurationMinimal, String> = fs::read_to_string(CONFIG_DIR_FILE_PATH.as_path())
-        .map_err(|e| format!("{e:?}"))
-        .and_then(|string| toml::from_str(&string).map_err(|e| format!("{e:?}")));
-    let configuration: ConfigurationV3 = match loaded_configuration {
+    let loaded_configuration: Result<ConfigurationMinimal, ConfigError> = read_configuration_file();
+    let mut configuration: ConfigurationV3 = match loaded_configuration {
         Ok(configuration) => {
-            let loaded_configuration: Result<ConfigurationV3, String> = if configuration.version < 3 {
-                fs::copy(CONFIG_DIR_FILE_PATH.as_path(), get_backup_config_file_path(configuration.version)).expect("failed to back up config");
+            let config_backups = configuration.config_backups.unwrap_or(DEFAULT_CONFIG_BACKUPS);
+            let loaded_configuration: Result<ConfigurationV3, ConfigError> = if configuration.version < 3 {
+                back_up_configuration(configuration.version, config_backups);
                 info!("converting v{} config to v{}", configuration.version, CONFIG_VERSION);
-                fs::read_to_string(CONFIG_DIR_FILE_PATH.as_path())
-                    .map_err(|e| format!("{e:?}"))
-                    .and_then(|string| toml::from_str::<ConfigurationV2>(&string).map_err(|e| format!("{e:?}")))
+                read_configuration_file::<ConfigurationV2>()
                     .map(|config| config.into())
             } else {
-                fs::read_to_string(CONFIG_DIR_FILE_PATH.as_path())
-                    .map_err(|e| format!("{e:?}"))
-                    .and_then(|string| toml::from_str::<ConfigurationV3>(&string).map_err(|e| format!("{e:?}")))
+                read_configuration_file::<ConfigurationV3>()
             };
 
             match loaded_configuration {
                 Ok(configuration) => configuration,
                 Err(e) => {
                     // attempt to backup old config file when read fails
-                    fs::copy(CONFIG_DIR_FILE_PATH.as_path(), get_backup_config_file_path(configuration.version)).expect("failed to back up config");
+                    back_up_configuration(configuration.version, config_backups);
                     warn!("falling back to default config due to error: {e}");
                     ConfigurationV3::default()
                 }
@@ -115,6 +242,10 @@ pub async fn load_configuration() -> ConfigurationV3 {
         }
     };
     info!("Loaded configuration v{} from disk", configuration.version);
+    configuration.remove_overlong_tags();
+    configuration.remove_invalid_device_renames();
+    configuration.remove_invalid_response_curves();
+    configuration.remove_recursive_composite_tags();
 
     if configuration.is_outdated() {
         let new_configuration = configuration.new_with_current_version();
@@ -132,3 +263,180 @@ pub async fn load_configuration() -> ConfigurationV3 {
         configuration
     }
 }
+
+/// Load a configuration from the contents of [`CONFIG_ENV_VAR`]. Nothing is written to disk, so outdated
+/// configurations are converted in memory only.
+fn load_configuration_from_environment(configuration: &str) -> ConfigurationV3 {
+    info!("Loading config from the {CONFIG_ENV_VAR} environment variable instead of {:?}", *CONFIG_DIR_FILE_PATH);
+    load_configuration_from_string(configuration)
+}
+
+/// Load a configuration with its overrides layered over its base. As with [`CONFIG_ENV_VAR`], nothing is written to disk,
+/// so outdated configurations are converted in memory only. A layer that can't be read is left out.
+fn load_layered_configuration(configuration_layers: &ConfigurationLayers) -> ConfigurationV3 {
+    info!("Loading config from {:?} layered over {:?}", configuration_layers.overrides, configuration_layers.base);
+    let configuration = merge_configuration_layers(read_configuration_layer(&configuration_layers.base), read_configuration_layer(&configuration_layers.overrides));
+    match toml::to_string(&configuration) {
+        Ok(configuration) => load_configuration_from_string(&configuration),
+        Err(e) => {
+            warn!("falling back to default config due to error: {e}");
+            ConfigurationV3::default()
+        }
+    }
+}
+
+/// Read one configuration layer. A layer that can't be read is treated as empty.
+fn read_configuration_layer(path: &Path) -> toml::Table {
+    match fs::read_to_string(path).map_err(ConfigError::from).and_then(|string| Ok(toml::from_str::<toml::Table>(&string)?)) {
+        Ok(layer) => layer,
+        Err(e) => {
+            warn!("ignoring configuration layer {path:?} due to error: {e}");
+            toml::Table::new()
+        }
+    }
+}
+
+/// Layer `overrides` over `base`. Tags are merged by name, and every other setting in `overrides` replaces the base's.
+fn merge_configuration_layers(mut base: toml::Table, overrides: toml::Table) -> toml::Table {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_tags)), toml::Value::Table(tags)) if key == "tags" => base_tags.extend(tags),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+    base
+}
+
+/// The settings in `configuration` that differ from `base`, so that layering them over `base` gives `configuration` back
+/// where possible. Tags are compared by name, as that's how they are merged. The version is always kept.
+fn configuration_layer_overrides(base: &toml::Table, configuration: toml::Table) -> toml::Table {
+    configuration.into_iter()
+        .filter_map(|(key, value)| match (base.get(&key), value) {
+            (Some(toml::Value::Table(base_tags)), toml::Value::Table(tags)) if key == "tags" => {
+                let tags: toml::Table = tags.into_iter()
+                    .filter(|(tag, motor)| base_tags.get(tag) != Some(motor))
+                    .collect();
+                (!tags.is_empty()).then_some((key, toml::Value::Table(tags)))
+            }
+            (Some(base_value), value) if *base_value == value && key != "version" => None,
+            (_, value) => Some((key, value)),
+        })
+        .collect()
+}
+
+/// Load a configuration from a string, converting outdated configurations in memory only
+fn load_configuration_from_string(configuration: &str) -> ConfigurationV3 {
+    let loaded_configuration = deserialize_configuration::<ConfigurationMinimal>(configuration)
+        .and_then(|minimal| {
+            if minimal.version < 3 {
+                info!("converting v{} config to v{}", minimal.version, CONFIG_VERSION);
+                deserialize_configuration::<ConfigurationV2>(configuration).map(|config| config.into())
+            } else {
+                deserialize_configuration::<ConfigurationV3>(configuration)
+            }
+        });
+    let mut configuration = match loaded_configuration {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            warn!("falling back to default config due to error: {e}");
+            ConfigurationV3::default()
+        }
+    };
+    configuration.remove_overlong_tags();
+    configuration.remove_invalid_device_renames();
+    configuration.remove_invalid_response_curves();
+    configuration.remove_recursive_composite_tags();
+    configuration.new_with_current_version()
+}
+
+/// deserialize a configuration written as either TOML or, if it looks like a JSON object, JSON
+pub fn deserialize_configuration<T: DeserializeOwned>(string: &str) -> Result<T, ConfigError> {
+    if string.trim_start().starts_with('{') {
+        Ok(serde_json::from_str(string)?)
+    } else {
+        Ok(toml::from_str(string)?)
+    }
+}
+
+/// Read the settings in [`ConfigurationMinimal`] from wherever the configuration will be loaded from, as they may be needed
+/// before it is. Defaults are used if the configuration can't be read.
+pub fn read_minimal_configuration() -> ConfigurationMinimal {
+    let configuration = if let Some(configuration) = configuration_from_environment() {
+        deserialize_configuration(&configuration)
+    } else if let Some(configuration_layers) = CONFIGURATION_LAYERS.get() {
+        merge_configuration_layers(read_configuration_layer(&configuration_layers.base), read_configuration_layer(&configuration_layers.overrides))
+            .try_into()
+            .map_err(ConfigError::from)
+    } else {
+        read_configuration_file()
+    };
+    configuration.unwrap_or_default()
+}
+
+/// read and deserialize the configuration file into the requested configuration version
+pub(super) fn read_configuration_file<T: DeserializeOwned>() -> Result<T, ConfigError> {
+    let string = fs::read_to_string(CONFIG_DIR_FILE_PATH.as_path())?;
+    Ok(toml::from_str(&string)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn table(string: &str) -> toml::Table {
+        toml::from_str(string).unwrap()
+    }
+
+    fn configuration(table: toml::Table) -> ConfigurationV3 {
+        toml::Value::Table(table).try_into().unwrap()
+    }
+
+    fn tag(tag: &str, feature_index: u32) -> String {
+        format!("[tags.{tag}]\ndevice_name = \"Lovense Hush\"\nfeature_index = {feature_index}\nfeature_type = {{ type = \"Scalar\", actuator_type = \"Vibrate\" }}\n")
+    }
+
+    fn feature_index(configuration: &ConfigurationV3, tag: &str) -> u32 {
+        configuration.tags()[tag].feature_index
+    }
+
+    #[test]
+    fn overriding_keys_replace_base_keys() {
+        let base = table("version = 3\nport = 3031\nwatch_config_file = true\n[tags]");
+        let overrides = table("port = 4000");
+        let merged = configuration(merge_configuration_layers(base, overrides));
+        assert_eq!(merged.port, 4000);
+        assert!(merged.watch_config_file);
+    }
+
+    #[test]
+    fn tags_are_merged_by_name() {
+        let base = table(&format!("version = 3\nport = 3031\n{}{}", tag("a", 0), tag("b", 1)));
+        let overrides = table(&format!("{}{}", tag("b", 2), tag("c", 3)));
+        let merged = configuration(merge_configuration_layers(base, overrides));
+        assert_eq!(merged.tags().len(), 3);
+        assert_eq!(feature_index(&merged, "a"), 0);
+        assert_eq!(feature_index(&merged, "b"), 2);
+        assert_eq!(feature_index(&merged, "c"), 3);
+    }
+
+    #[test]
+    fn other_nested_tables_are_replaced_whole() {
+        let base = table("version = 3\nport = 3031\n[tags]\n[motor_groups]\nleft = [\"a\", \"b\"]\nright = [\"c\"]");
+        let overrides = table("[motor_groups]\nleft = [\"a\"]");
+        let merged = configuration(merge_configuration_layers(base, overrides));
+        assert_eq!(merged.motor_groups, HashMap::from([("left".to_string(), vec!["a".to_string()])]));
+    }
+
+    #[test]
+    fn overrides_only_keep_differences() {
+        let base = table(&format!("version = 3\nport = 3031\n{}{}", tag("a", 0), tag("b", 1)));
+        let layered = table(&format!("version = 3\nport = 4000\n{}{}", tag("a", 0), tag("b", 2)));
+        let overrides = configuration_layer_overrides(&base, layered.clone());
+        assert_eq!(overrides, table(&format!("version = 3\nport = 4000\n{}", tag("b", 2))));
+        assert_eq!(configuration(merge_configuration_layers(base, overrides)), configuration(layered));
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! Applies changes made to the configuration file by anything other than this application
+
+use std::ffi::OsStr;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher as _};
+use tokio::sync::mpsc;
+use tokio::task;
+use tracing::{debug, info, warn};
+
+use crate::{ApplicationStateDb, ShutdownMessage};
+use crate::config::{apply_configuration, ConfigError, CONFIG_DIR_FILE_PATH};
+use crate::config::util::read_configuration_file;
+use crate::config::v3::ConfigurationV3;
+use crate::gui::subscription::ApplicationStatusEvent;
+use crate::util::watchdog::WatchdogTimeoutDb;
+
+// editors frequently save a file in several writes, so wait this long for things to settle before reloading
+const DEBOUNCE_DURATION: Duration = Duration::from_millis(500);
+
+pub fn start(
+    application_state_db: ApplicationStateDb,
+    watchdog_timeout_db: WatchdogTimeoutDb,
+    warp_shutdown_tx: mpsc::UnboundedSender<ShutdownMessage>,
+    application_status_sender: mpsc::UnboundedSender<ApplicationStatusEvent>,
+) {
+    let (file_changed_tx, mut file_changed_rx) = mpsc::unbounded_channel::<()>();
+    let config_file_name = CONFIG_DIR_FILE_PATH.file_name().map(OsStr::to_os_string);
+
+    let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+        Ok(event) => {
+            let config_file_changed = (event.kind.is_create() || event.kind.is_modify())
+                && event.paths.iter().any(|path| path.file_name() == config_file_name.as_deref());
+            if config_file_changed {
+                // this only fails if the reload task is gone, in which case nobody cares about the change anyways
+                let _ = file_changed_tx.send(());
+            }
+        }
+        Err(e) => warn!("error watching configuration file: {e}"),
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("unable to create configuration file watcher: {e}");
+            return;
+        }
+    };
+
+    // watch the whole directory, as some editors save by replacing the file instead of writing to it
+    let config_dir = CONFIG_DIR_FILE_PATH.parent().expect("configuration file has no parent directory");
+    if let Err(e) = watcher.watch(config_dir, RecursiveMode::NonRecursive) {
+        warn!("unable to watch configuration directory {config_dir:?}: {e}");
+        return;
+    }
+    info!("watching {:?} for external changes", *CONFIG_DIR_FILE_PATH);
+
+    task::spawn(async move {
+        // the watch stops as soon as the watcher is dropped, so it has to live as long as this task
+        let _watcher = watcher;
+        while file_changed_rx.recv().await.is_some() {
+            while let Ok(Some(())) = tokio::time::timeout(DEBOUNCE_DURATION, file_changed_rx.recv()).await {}
+            reload_configuration(&application_state_db, &watchdog_timeout_db, &warp_shutdown_tx, &application_status_sender).await;
+        }
+    });
+}
+
+/// read the configuration file without blocking the async runtime
+async fn read_changed_configuration() -> Result<ConfigurationV3, ConfigError> {
+    task::spawn_blocking(read_configuration_file::<ConfigurationV3>).await?
+}
+
+async fn reload_configuration(
+    application_state_db: &ApplicationStateDb,
+    watchdog_timeout_db: &WatchdogTimeoutDb,
+    warp_shutdown_tx: &mpsc::UnboundedSender<ShutdownMessage>,
+    application_status_sender: &mpsc::UnboundedSender<ApplicationStatusEvent>,
+) {
+    let mut configuration = match read_changed_configuration().await {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            warn!("ignoring configuration file change: {e}");
+            return;
+        }
+    };
+
+    if configuration.is_outdated() {
+        warn!("ignoring configuration file change: v{} configurations can only be loaded at startup", configuration.version);
+        return;
+    }
+    configuration.remove_overlong_tags();
+    configuration.remove_invalid_device_renames();
+    configuration.remove_invalid_response_curves();
+    configuration.remove_recursive_composite_tags();
+
+    // this is how we skip our own saves: they always match what's already in memory
+    let unchanged = application_state_db.read().await.as_ref()
+        .is_some_and(|application_state| application_state.configuration == configuration);
+    if unchanged {
+        debug!("configuration file changed, but it matches the current configuration");
+        return;
+    }
+
+    match apply_configuration(application_state_db, watchdog_timeout_db, configuration, warp_shutdown_tx).await {
+        Ok(_) => {
+            info!("applied external configuration file change");
+            if application_status_sender.send(ApplicationStatusEvent::ConfigurationReloaded).is_err() {
+                warn!("unable to notify UI of configuration reload");
+            }
+        }
+        Err(e) => warn!("failed to apply external configuration file change: {e}"),
+    }
+}
@@ -2,6 +2,8 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
+use std::ops::RangeInclusive;
+
 use iced::Pixels;
 
 pub const TEXT_INPUT_PADDING: u16 = 5;
@@ -12,3 +14,10 @@ pub const EOL_INPUT_SPACING: u16 = 5;
 pub const TEXT_SIZE_SMALL: u16 = 12;
 pub const TEXT_SIZE_DEFAULT: Pixels = Pixels(20.0);
 pub const TEXT_SIZE_BIG: u16 = 30;
+pub const DEVICE_SCALE_RANGE: RangeInclusive<f64> = 0.0..=2.0;
+pub const DEVICE_SCALE_STEP: f64 = 0.05;
+pub const DEVICE_SCALE_SLIDER_WIDTH: f32 = 100.0;
+pub const MANUAL_SCALAR_RANGE: RangeInclusive<f64> = 0.0..=1.0;
+pub const MANUAL_ROTATION_RANGE: RangeInclusive<f64> = -1.0..=1.0;
+pub const MANUAL_STEP: f64 = 0.05;
+pub const MANUAL_SLIDER_WIDTH: f32 = 100.0;
@@ -0,0 +1,57 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+use tokio::task;
+
+use crate::app::buttplug;
+use crate::app::webserver::HapticState;
+use crate::config::v3::MotorConfigurationV3;
+use crate::util::watchdog;
+use crate::util::watchdog::WatchdogTimeoutDb;
+
+/// Drives motors from their manual control sliders. Values are sent one at a time by a single task, so a slow device
+/// can't have an older value arrive after a newer one. If a slider moves again before its last value was sent, only
+/// the latest value is sent.
+#[derive(Clone)]
+pub struct ManualControl {
+    /// the latest unsent value of each motor
+    pending: Arc<Mutex<BTreeMap<MotorConfigurationV3, f64>>>,
+    pending_changed: Arc<Notify>,
+}
+
+impl ManualControl {
+    /// Start the task that sends values. Sending counts as activity for the watchdog.
+    pub fn start(haptic_state: HapticState, watchdog_timeout_db: WatchdogTimeoutDb) -> Self {
+        let manual_control = ManualControl {
+            pending: Default::default(),
+            pending_changed: Default::default(),
+        };
+        let task_manual_control = manual_control.clone();
+        task::spawn(async move {
+            loop {
+                task_manual_control.pending_changed.notified().await;
+                let pending = mem::take(&mut *task_manual_control.pending.lock().unwrap_or_else(|e| e.into_inner()));
+                if pending.is_empty() {
+                    continue;
+                }
+                watchdog::feed(&watchdog_timeout_db, &haptic_state.application_state_db).await;
+                for (motor, value) in pending {
+                    buttplug::set_motor(&haptic_state.application_state_db, &haptic_state.device_scale_db, &haptic_state.sent_command_dbs, &motor, value).await;
+                }
+            }
+        });
+        manual_control
+    }
+
+    /// Send a value to a motor once the values before it have been sent, replacing any unsent value for the same motor
+    pub fn set(&self, motor: MotorConfigurationV3, value: f64) {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).insert(motor, value);
+        self.pending_changed.notify_one();
+    }
+}
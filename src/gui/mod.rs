@@ -11,6 +11,7 @@ pub mod subscription;
 mod constants;
 mod element_appearance;
 mod executor;
+mod manual_control;
 mod structs;
 mod tagged_motor;
 mod theme;
@@ -4,6 +4,8 @@
 
 //! various simple structs used by the GUI
 
+use crate::config::v3::MotorConfigurationV3;
+
 #[derive(Clone, Debug)]
 pub enum MotorMessage {
     TagUpdated {
@@ -11,6 +13,11 @@ pub enum MotorMessage {
         valid: bool,
     },
     TagDeleted,
+    EnabledToggled(bool),
+    /// the manual control slider was dragged to a new value
+    ManualValueChanged(f64),
+    /// the manual control slider was let go, which stops the motor
+    ManualValueReleased,
 }
 
 impl MotorMessage {
@@ -18,6 +25,29 @@ impl MotorMessage {
         match self {
             MotorMessage::TagUpdated { tag, .. } => Some(tag),
             MotorMessage::TagDeleted => None,
+            MotorMessage::EnabledToggled(_) => None,
+            MotorMessage::ManualValueChanged(_) => None,
+            MotorMessage::ManualValueReleased => None,
         }
     }
 }
+
+/// the device a group of motors in the motor list belongs to
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DeviceGroup {
+    pub device_name: String,
+    pub device_identifier: Option<String>,
+}
+
+impl DeviceGroup {
+    pub fn of(motor: &MotorConfigurationV3) -> DeviceGroup {
+        DeviceGroup {
+            device_name: motor.device_name.clone(),
+            device_identifier: motor.device_identifier.clone(),
+        }
+    }
+
+    pub fn contains(&self, motor: &MotorConfigurationV3) -> bool {
+        self.device_name == motor.device_name && self.device_identifier == motor.device_identifier
+    }
+}
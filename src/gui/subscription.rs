@@ -18,6 +18,15 @@ struct Marker;
 pub enum ApplicationStatusEvent {
     DeviceAdded,
     DeviceRemoved,
+    ConfigurationReloaded,
+    /// the device server went away, and will be restarted shortly
+    ServerDisconnected,
+    /// the device server is back after being disconnected
+    ServerConnected,
+    /// too many attempts to start the device server failed, so it won't be restarted until a retry is asked for
+    ServerUnavailable,
+    /// the watchdog timeout was changed over HTTP, along with whether that change was also saved
+    WatchdogTimeoutChanged { timeout_ms: u64, persisted: bool },
     Tick,
 }
 
@@ -4,10 +4,10 @@
 
 use std::cmp::Ordering;
 
-use iced::widget::{Button, Row, Text, TextInput};
+use iced::widget::{Button, Checkbox, Row, Slider, Text, TextInput};
 use iced::{Alignment, Element, Length};
 
-use crate::config::v3::MotorConfigurationV3;
+use crate::config::v3::{MotorConfigurationV3, MotorTypeV3};
 use crate::gui::constants::*;
 use crate::gui::element_appearance::ElementAppearance;
 use crate::gui::structs::MotorMessage;
@@ -18,6 +18,10 @@ use crate::gui::util;
 pub struct TaggedMotor {
     pub motor: MotorConfigurationV3,
     pub state: TaggedMotorState,
+    /// value of the manual control slider, which is only nonzero while it is held
+    pub manual_value: f64,
+    /// how many distinct values the device reports this motor supports, if it is connected
+    pub step_count: Option<u32>,
 }
 
 impl PartialEq for TaggedMotor {
@@ -36,7 +40,8 @@ impl PartialOrd for TaggedMotor {
 
 impl Ord for TaggedMotor {
     fn cmp(&self, other: &Self) -> Ordering {
-        (&self.motor, &self.tag()).cmp(&(&other.motor, &other.tag()))
+        // the same order as ConfigurationV3::tags_in_order, with the rest of the motor breaking ties to agree with Eq
+        (self.motor.order_key(), &self.tag(), &self.motor).cmp(&(other.motor.order_key(), &other.tag(), &other.motor))
     }
 }
 
@@ -53,6 +58,8 @@ impl TaggedMotor {
         TaggedMotor {
             motor,
             state,
+            manual_value: 0.0,
+            step_count: None,
         }
     }
 
@@ -75,14 +82,34 @@ impl TaggedMotor {
             MotorMessage::TagDeleted => {
                 self.state = TaggedMotorState::Untagged;
             }
+            MotorMessage::EnabledToggled(enabled) => {
+                self.motor.enabled = enabled;
+            }
+            MotorMessage::ManualValueChanged(value) => {
+                self.manual_value = value;
+            }
+            MotorMessage::ManualValueReleased => {
+                self.manual_value = 0.0;
+            }
         }
     }
 
-    pub fn view(&self) -> Element<MotorMessage> {
+    /// An example command for this motor, such as `tag:0.5`. Untagged and disabled motors have none, as commands sent to them would be ignored.
+    pub fn example_command(&self) -> Option<String> {
+        self.tag().and_then(|tag| self.motor.example_command(tag))
+    }
+
+    /// `show_example` adds this motor's [example command](TaggedMotor::example_command) to the end of the row, if its tag is valid.
+    /// `show_step_count` adds its step count after its name, if it is connected.
+    pub fn view(&self, show_example: bool, show_step_count: bool) -> Element<'_, MotorMessage> {
         let row = Row::new()
             .spacing(EOL_INPUT_SPACING)
             .align_y(Alignment::Center)
-            .push(util::input_label(format!("{}", &self.motor)));
+            .push(util::input_label(format!("{}#{}", self.motor.feature_type, self.motor.feature_index))) // the device is named by the group header
+            .push_maybe(self.step_count.filter(|_| show_step_count).map(|step_count| {
+                let steps = if step_count == 1 { "1 step".to_string() } else { format!("{step_count} steps") };
+                Text::new(steps).size(TEXT_SIZE_SMALL)
+            }));
 
         let row = match &self.state {
             TaggedMotorState::Tagged { tag, valid } => {
@@ -97,10 +124,16 @@ impl TaggedMotor {
                             ElementAppearance::from(&self.state).text_input_custom_style(theme, status)
                         })
                 )
+                    .push(
+                        Checkbox::new("enabled", self.motor.enabled)
+                            .on_toggle(MotorMessage::EnabledToggled)
+                    )
                     .push(
                         Button::new(Text::new("x")) // font doesn't support funny characters like "✕"
                             .on_press(MotorMessage::TagDeleted)
                     )
+                    .push_maybe(self.manual_slider())
+                    .push_maybe(self.example_command().filter(|_| show_example && *valid).map(|example| Text::new(example).size(TEXT_SIZE_SMALL)))
             }
             TaggedMotorState::Untagged => {
                 row.push(
@@ -115,6 +148,21 @@ impl TaggedMotor {
 
         row.into()
     }
+
+    /// a slider that drives the motor while it is held, for enabled scalar and rotation motors
+    fn manual_slider(&self) -> Option<Slider<'_, f64, MotorMessage>> {
+        let range = match self.motor.feature_type {
+            MotorTypeV3::Scalar { .. } => MANUAL_SCALAR_RANGE,
+            MotorTypeV3::Rotation => MANUAL_ROTATION_RANGE,
+            MotorTypeV3::Linear => return None,
+        };
+        self.motor.enabled.then(|| {
+            Slider::new(range, self.manual_value, MotorMessage::ManualValueChanged)
+                .on_release(MotorMessage::ManualValueReleased)
+                .step(MANUAL_STEP)
+                .width(Length::Fixed(MANUAL_SLIDER_WIDTH))
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
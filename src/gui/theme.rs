@@ -3,6 +3,7 @@
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
 use iced::{theme, Color, Theme};
+use tracing::warn;
 
 const DARK_PALETTE: theme::Palette = theme::Palette {
     background: Color::from_rgb(
@@ -28,6 +29,16 @@ const DARK_PALETTE: theme::Palette = theme::Palette {
     ),
 };
 
-pub fn dark_theme() -> Theme {
-    Theme::custom("Dark".to_string(), DARK_PALETTE)
+/// Build the dark theme, optionally replacing its primary color with a custom accent given as a hex string such as `#7289DA`.
+/// An invalid accent color is logged and ignored.
+pub fn dark_theme(accent_color: Option<&str>) -> Theme {
+    let primary = match accent_color {
+        Some(accent_color) => Color::parse(accent_color).unwrap_or_else(|| {
+            warn!("ignoring invalid accent color {accent_color:?}, expected a hex color such as \"#7289DA\"");
+            DARK_PALETTE.primary
+        }),
+        None => DARK_PALETTE.primary,
+    };
+
+    Theme::custom("Dark".to_string(), theme::Palette { primary, ..DARK_PALETTE })
 }
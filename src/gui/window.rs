@@ -2,59 +2,75 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::fs;
+use std::convert;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use iced::widget::{Button, Column, Container, Row, Rule, Scrollable, Text, TextInput};
-use iced::{alignment::Alignment, Element, Event, Length, Settings, Subscription, Task};
+use iced::widget::{Button, Checkbox, Column, Container, Row, Rule, Scrollable, Slider, Text, TextInput};
+use iced::{alignment::Alignment, window, Element, Event, Length, Settings, Subscription, Task, Theme};
+use rfd::AsyncFileDialog;
 use semver::Version;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::task;
 use tracing::{debug, info, warn};
 
 use crate::app::buttplug;
-use crate::app::structs::{ApplicationStatus, DeviceStatus};
-use crate::config::v3::{ConfigurationV3, MotorConfigurationV3, MotorTypeV3};
+use crate::app::structs;
+use crate::app::structs::{ApplicationStatus, BatteryHistoryDb, DeviceScaleDb, DeviceServerRetryDb, DeviceStatus, GroupScaleDb, LatencyReport, SentCommandDbs};
+use crate::config;
+use crate::config::{recovery, ConfigError, ValidationError};
+use crate::config::v3::{build_example_message, ConfigurationV3, MotorConfigurationV3, UpdateCheckV3};
 use crate::gui::constants::*;
-use crate::gui::structs::MotorMessage;
+use crate::gui::structs::{DeviceGroup, MotorMessage};
 use crate::gui::subscription::{ApplicationStatusEvent, SubscriptionProvider};
 use crate::gui::tagged_motor::TaggedMotor;
 use crate::gui::theme::dark_theme;
 use crate::gui::util;
 use crate::gui::TokioExecutor;
+use crate::util::command_hook;
+use crate::util::diagnostics::{self, DiagnosticSnapshot, DiagnosticsError};
+use crate::util::logging;
 use crate::util::slice as slice_util;
 use crate::util::update_checker;
-use crate::{ApplicationStateDb, ShutdownMessage};
+use crate::util::watchdog::WatchdogTimeoutDb;
+use crate::{ApplicationStateDb, HapticState, ShutdownMessage};
 use crate::gui::element_appearance::ElementAppearance;
+use crate::gui::manual_control::ManualControl;
 use crate::gui::util::ConstantTitle;
 
-pub fn run(
-    application_state_db: ApplicationStateDb,
-    warp_shutdown_tx: UnboundedSender<ShutdownMessage>,
-    initial_devices: ApplicationStatus,
-    application_status_subscription: SubscriptionProvider<ApplicationStatusEvent>,
-) {
+/// how long the notice that the device server reconnected stays up
+const RECONNECTED_NOTICE_DURATION: Duration = Duration::from_secs(5);
+/// how long closing the window waits for the shutdown hook to finish
+const SHUTDOWN_HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn run(flags: Flags, rendering_options: RenderingOptions) {
+    let configuration = &flags.initial_application_status.configuration;
+    let antialiasing = configuration.antialiasing && !rendering_options.no_antialiasing;
 
     let settings = Settings {
         id: Some("buttplug-lite".to_string()),
         fonts: vec![],
         default_font: Default::default(),
         default_text_size: TEXT_SIZE_DEFAULT,
-        antialiasing: true,
+        antialiasing,
     };
 
-    let flags = Flags {
-        warp_restart_tx: warp_shutdown_tx.clone(),
-        application_state_db,
-        initial_application_status: initial_devices,
-        application_status_subscription,
-    };
+    let window_level = window_level(configuration.always_on_top);
+    let warp_shutdown_tx = flags.warp_restart_tx.clone();
 
     let application_title = ConstantTitle(format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
     
     iced::application(application_title, Gui::update, Gui::view)
         .settings(settings)
-        .theme(|_| dark_theme())
+        .level(window_level)
+        // without this iced closes the window itself, and the close request never reaches `Gui::update` to stop devices first
+        .exit_on_close_request(false)
+        .theme(Gui::theme)
         .executor::<TokioExecutor>()
         .subscription(Gui::subscription)
         .run_with(|| Gui::new(flags))
@@ -66,31 +82,103 @@ pub fn run(
     };
 }
 
-struct Flags {
-    warp_restart_tx: UnboundedSender<ShutdownMessage>,
-    application_state_db: ApplicationStateDb,
-    initial_application_status: ApplicationStatus,
-    application_status_subscription: SubscriptionProvider<ApplicationStatusEvent>,
+/// Rendering settings given on the command line, which are used on top of the configuration's
+pub struct RenderingOptions {
+    pub no_antialiasing: bool,
+}
+
+/// Everything the GUI needs from the rest of buttplug-lite
+pub struct Flags {
+    pub warp_restart_tx: UnboundedSender<ShutdownMessage>,
+    pub haptic_state: HapticState,
+    pub battery_history_db: BatteryHistoryDb,
+    pub watchdog_timeout_db: WatchdogTimeoutDb,
+    pub device_server_retry_db: DeviceServerRetryDb,
+    pub initial_application_status: ApplicationStatus,
+    pub application_status_subscription: SubscriptionProvider<ApplicationStatusEvent>,
+    /// unsaved configuration left behind by a previous session that crashed
+    pub recovered_configuration: Option<ConfigurationV3>,
 }
 
 #[derive(Debug, Clone)]
 enum Message {
     SaveConfigurationRequest,
+    ApplyConfigurationRequest,
     RefreshDevices,
     RefreshDevicesComplete(Option<ApplicationStatus>),
-    SaveConfigurationComplete(Result<ConfigurationV3, String>),
+    ConfigurationReloaded,
+    ConfigurationReloadComplete(Option<ApplicationStatus>),
+    /// the result of applying a configuration, and whether it was also saved to disk
+    SaveConfigurationComplete(Result<ConfigurationV3, Arc<ConfigError>>, bool),
     PortUpdated(String),
+    AlwaysOnTopToggled(bool),
+    ScanningToggled(bool),
+    /// whether scanning was asked to start, and whether that succeeded
+    ScanningChanged(bool, Result<bool, String>),
+    /// a device identifier and its new live intensity multiplier
+    DeviceScaleChanged(String, f64),
+    DeviceScaleApplied,
+    /// a motor group name and its new live master scale
+    GroupScaleChanged(String, f64),
+    GroupScaleApplied,
     MotorMessageContainer(usize, MotorMessage),
+    DeviceGroupToggled(DeviceGroup),
     NativeEventOccurred(Event),
+    /// devices have been stopped, and the window can close
+    ShutdownReady,
     Tick,
     UpdateButtonPressed,
+    OpenConfigDirectory,
+    OpenLogDirectory,
+    ExportDiagnostics,
+    /// where the user chose to save the diagnostic bundle, or `None` if they cancelled
+    DiagnosticsPathChosen(Option<PathBuf>),
+    /// the identifier of the device to test
+    TestLatency(String),
+    LatencyTestComplete(String, Result<LatencyReport, String>),
+    /// where the diagnostic bundle was written
+    ExportDiagnosticsComplete(Result<PathBuf, Arc<DiagnosticsError>>),
+    RestoreRecoveredConfiguration,
+    DiscardRecoveredConfiguration,
+    ServerDisconnected,
+    ServerConnected,
+    ServerUnavailable,
+    /// the new watchdog timeout, and whether it was saved to disk
+    WatchdogTimeoutChanged(u64, bool),
+    RetryServer,
+    ConnectionNoticeExpired,
+    AboutToggled,
+    OpenRepository,
     StartupActionCompleted(StartupActionResult)
 }
 
 enum Gui {
-    /// intermediate state used for memory-fuckery reasons during transitions
-    Invalid,
-    Loaded(State),
+    Loaded(Box<State>),
+}
+
+#[derive(Debug, Clone)]
+enum LatencyTest {
+    Running,
+    Complete(Result<LatencyReport, String>),
+}
+
+/// a notice about the device server's connection. Only `Unavailable` is shown regardless of the `connection_notices` setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConnectionNotice {
+    Reconnecting,
+    Reconnected,
+    /// too many attempts to start the device server failed
+    Unavailable,
+}
+
+impl Display for ConnectionNotice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionNotice::Reconnecting => write!(f, "Device server reconnecting…"),
+            ConnectionNotice::Reconnected => write!(f, "Device server reconnected"),
+            ConnectionNotice::Unavailable => write!(f, "Device server unavailable"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -102,56 +190,115 @@ enum UpdateCheck {
 
 struct State {
     motors: Vec<TaggedMotor>,
+    /// device groups in the motor list whose motors are hidden
+    collapsed_device_groups: HashSet<DeviceGroup>,
     devices: Vec<DeviceStatus>,
+    /// number of connected devices left out of `devices` because of the configured device limit
+    devices_over_limit: usize,
+    /// live master scales of motor groups, keyed by group name
+    group_scales: HashMap<String, f64>,
+    /// if the device server is scanning for devices
+    scanning: bool,
+    /// the latest latency test of each device, keyed by device identifier
+    latency_tests: HashMap<String, LatencyTest>,
     port: u16,
     port_text: String,
+    always_on_top: bool,
+    theme: Theme,
     warp_restart_tx: UnboundedSender<ShutdownMessage>,
     application_state_db: ApplicationStateDb,
+    device_scale_db: DeviceScaleDb,
+    group_scale_db: GroupScaleDb,
+    battery_history_db: BatteryHistoryDb,
+    sent_command_dbs: SentCommandDbs,
+    manual_control: ManualControl,
+    watchdog_timeout_db: WatchdogTimeoutDb,
+    device_server_retry_db: DeviceServerRetryDb,
     configuration_dirty: bool,
+    /// false if `last_configuration` was applied without being saved to disk
+    configuration_persisted: bool,
     motor_tags_valid: bool,
+    /// why the last save or apply was refused
+    validation_errors: Vec<ValidationError>,
+    /// why the last save or apply failed
+    save_error: Option<Arc<ConfigError>>,
     saving: bool,
     last_configuration: ConfigurationV3,
     application_status_subscription: SubscriptionProvider<ApplicationStatusEvent>,
     update_check: UpdateCheck,
+    /// unsaved configuration left behind by a previous session that crashed
+    recovered_configuration: Option<ConfigurationV3>,
+    connection_notice: Option<ConnectionNotice>,
+    show_about: bool,
 }
 
 impl Gui {
     fn new(flags: Flags) -> (Self, Task<Message>) {
         let config_version = flags.initial_application_status.configuration.version;
         let port = flags.initial_application_status.configuration.port;
-        let ApplicationStatus { motors, devices, configuration } = flags.initial_application_status;
+        let ApplicationStatus { motors, devices, devices_over_limit, group_scales, scanning, configuration } = flags.initial_application_status;
+        let manual_control = ManualControl::start(flags.haptic_state.clone(), flags.watchdog_timeout_db.clone());
+        let update_check_settings = configuration.update_check.clone();
+        // show an update found by a previous launch right away, while the startup check runs
+        let update_check = match update_checker::saved_update_url(&local_version(), &update_check_settings) {
+            Some(update_url) => UpdateCheck::UpdateNeeded(update_url),
+            None => UpdateCheck::Uninitialized,
+        };
 
-        let gui = Gui::Loaded(State {
+        let gui = Gui::Loaded(Box::new(State {
             devices,
+            devices_over_limit,
+            group_scales,
+            scanning,
+            latency_tests: HashMap::new(),
             motors,
+            collapsed_device_groups: HashSet::new(),
             port,
             port_text: port.to_string(),
+            always_on_top: configuration.always_on_top,
+            theme: dark_theme(configuration.accent_color.as_deref()),
             warp_restart_tx: flags.warp_restart_tx,
-            application_state_db: flags.application_state_db,
+            application_state_db: flags.haptic_state.application_state_db,
+            device_scale_db: flags.haptic_state.device_scale_db,
+            group_scale_db: flags.haptic_state.group_scale_db,
+            battery_history_db: flags.battery_history_db,
+            sent_command_dbs: flags.haptic_state.sent_command_dbs,
+            manual_control,
+            watchdog_timeout_db: flags.watchdog_timeout_db,
+            device_server_retry_db: flags.device_server_retry_db,
             configuration_dirty: ConfigurationV3::is_version_outdated(config_version),
+            configuration_persisted: true,
             motor_tags_valid: true,
+            validation_errors: Vec::new(),
+            save_error: None,
             saving: false,
             last_configuration: configuration,
             application_status_subscription: flags.application_status_subscription,
-            update_check: UpdateCheck::Uninitialized,
-        });
+            update_check,
+            recovered_configuration: flags.recovered_configuration,
+            connection_notice: None,
+            show_about: false,
+        }));
 
-        (gui, Task::perform(gui_startup_action(), Message::StartupActionCompleted))
+        (gui, Task::perform(gui_startup_action(update_check_settings), Message::StartupActionCompleted))
     }
 
     fn on_configuration_changed(&mut self) {
-        if let Gui::Loaded(state) = self {
-            // what the new configuration would be if we saved now
-            let new_configuration = ConfigurationV3::new(state.port, tags_from_application_status(&state.motors));
-            state.configuration_dirty = new_configuration != state.last_configuration;
+        let Gui::Loaded(state) = self;
+        // what the new configuration would be if we saved now
+        let new_configuration = state.last_configuration.with_gui_settings(state.port, tags_from_application_status(&state.motors), state.always_on_top);
+        state.configuration_dirty = new_configuration != state.last_configuration;
+
+        // keep a copy of any unsaved changes around in case we crash before they're saved
+        if state.configuration_dirty || !state.configuration_persisted {
+            recovery::save_snapshot(&new_configuration);
+        } else {
+            recovery::clear_snapshot();
         }
     }
 
     fn update(&mut self, message: Message) -> Task<Message> {
         match self {
-            Gui::Invalid => {
-                panic!("GUI was unexpectedly in an invalid state");
-            }
             Gui::Loaded(state) => {
                 match message {
                     Message::StartupActionCompleted(result) => {
@@ -160,74 +307,120 @@ impl Gui {
                     }
                     Message::RefreshDevices => {
                         info!("device refresh triggered");
-                        Task::perform(get_tagged_devices(state.application_state_db.clone()), Message::RefreshDevicesComplete)
+                        Task::perform(get_tagged_devices(state.application_state_db.clone(), state.device_scale_db.clone(), state.group_scale_db.clone(), state.battery_history_db.clone()), Message::RefreshDevicesComplete)
                     }
                     Message::RefreshDevicesComplete(application_status) => {
                         if let Some(application_status) = application_status {
-                            // we conduct the ol' switcharoo to move our old state into the new state without having to clone absolutely everything
-                            if let Gui::Loaded(old_state) = std::mem::replace(self, Gui::Invalid) {
-
-                                //TODO: something in here nukes the status of motor tags that we're currently editing
-                                if old_state.motors != application_status.motors {
-                                    debug!("old motors = {:?}", old_state.motors);
-                                    debug!("new motors = {:?}", application_status.motors);
-                                }
-
-                                *self = Gui::Loaded(State {
-                                    devices: application_status.devices,
-                                    motors: application_status.motors,
-                                    port: old_state.port,
-                                    port_text: old_state.port_text,
-                                    warp_restart_tx: old_state.warp_restart_tx,
-                                    application_state_db: old_state.application_state_db,
-                                    configuration_dirty: old_state.configuration_dirty,
-                                    motor_tags_valid: old_state.motor_tags_valid,
-                                    saving: old_state.saving,
-                                    last_configuration: old_state.last_configuration,
-                                    application_status_subscription: old_state.application_status_subscription,
-                                    update_check: old_state.update_check,
-                                });
-                            } else {
-                                // this should never happen
-                                panic!("GUI was unexpectedly in an invalid state");
+                            //TODO: something in here nukes the status of motor tags that we're currently editing
+                            if state.motors != application_status.motors {
+                                debug!("old motors = {:?}", state.motors);
+                                debug!("new motors = {:?}", application_status.motors);
                             }
+
+                            state.devices = application_status.devices;
+                            state.devices_over_limit = application_status.devices_over_limit;
+                            state.group_scales = application_status.group_scales;
+                            state.scanning = application_status.scanning;
+                            state.motors = application_status.motors;
                         } else {
-                            panic!("Application was unexpectedly not in loaded state");
+                            // this happens if we refresh while the device server is restarting. It'll send another event once it's back.
+                            debug!("device server is not running, skipping refresh");
                         }
 
                         debug!("Finished handling RefreshDevicesComplete event");
                         Task::none()
                     }
+                    Message::ConfigurationReloaded => {
+                        info!("configuration reload triggered");
+                        Task::perform(get_tagged_devices(state.application_state_db.clone(), state.device_scale_db.clone(), state.group_scale_db.clone(), state.battery_history_db.clone()), Message::ConfigurationReloadComplete)
+                    }
+                    Message::ConfigurationReloadComplete(application_status) => {
+                        let mut window_level_task = Task::none();
+                        if let Some(application_status) = &application_status {
+                            // the configuration file takes priority over any unsaved changes in the UI
+                            state.last_configuration = application_status.configuration.clone();
+                            state.port = state.last_configuration.port;
+                            state.port_text = state.port.to_string();
+                            state.configuration_dirty = false;
+                            state.configuration_persisted = true;
+                            state.motor_tags_valid = true;
+                            state.theme = dark_theme(state.last_configuration.accent_color.as_deref());
+                            if state.always_on_top != state.last_configuration.always_on_top {
+                                state.always_on_top = state.last_configuration.always_on_top;
+                                window_level_task = change_window_level(state.always_on_top);
+                            }
+                        }
+                        Task::batch([window_level_task, Task::done(Message::RefreshDevicesComplete(application_status))])
+                    }
                     Message::SaveConfigurationRequest => {
                         if state.saving {
                             debug!("Save requested but we're already saving! I didn't realize this was possible… but I handled it anyways");
                             Task::none()
                         } else {
+                            state.port_text = state.port.to_string();
+
+                            let configuration = state.last_configuration.with_gui_settings(state.port, tags_from_application_status(&state.motors), state.always_on_top);
+                            if let Err(errors) = configuration.validate() {
+                                for e in &errors {
+                                    warn!("save refused, as the configuration is invalid: {e}");
+                                }
+                                state.validation_errors = errors;
+                                return Task::none();
+                            }
+                            state.validation_errors.clear();
+
                             info!("save initiated");
                             state.saving = true;
-
+                            Task::perform(update_configuration(state.application_state_db.clone(), state.watchdog_timeout_db.clone(), configuration, state.warp_restart_tx.clone()), |result| Message::SaveConfigurationComplete(result, true))
+                        }
+                    }
+                    Message::ApplyConfigurationRequest => {
+                        if state.saving {
+                            debug!("Apply requested while saving, ignoring it");
+                            Task::none()
+                        } else {
                             state.port_text = state.port.to_string();
 
-                            let configuration = ConfigurationV3::new(state.port, tags_from_application_status(&state.motors));
-                            Task::perform(update_configuration(state.application_state_db.clone(), configuration, state.warp_restart_tx.clone()), Message::SaveConfigurationComplete)
+                            let configuration = state.last_configuration.with_gui_settings(state.port, tags_from_application_status(&state.motors), state.always_on_top);
+                            if let Err(errors) = configuration.validate() {
+                                for e in &errors {
+                                    warn!("apply refused, as the configuration is invalid: {e}");
+                                }
+                                state.validation_errors = errors;
+                                return Task::none();
+                            }
+                            state.validation_errors.clear();
+
+                            info!("apply initiated");
+                            state.saving = true;
+                            Task::perform(apply_configuration(state.application_state_db.clone(), state.watchdog_timeout_db.clone(), configuration, state.warp_restart_tx.clone()), |result| Message::SaveConfigurationComplete(result, false))
                         }
                     }
-                    Message::SaveConfigurationComplete(result) => {
+                    Message::SaveConfigurationComplete(result, persisted) => {
                         state.saving = false;
                         let application_state = state.application_state_db.clone();
+                        let device_scale_db = state.device_scale_db.clone();
+                        let group_scale_db = state.group_scale_db.clone();
+                        let battery_history_db = state.battery_history_db.clone();
                         match result {
                             Ok(configuration) => {
                                 state.last_configuration = configuration;
+                                state.configuration_persisted = persisted;
+                                state.save_error = None;
                                 self.on_configuration_changed();
                             }
                             Err(e) => {
-                                warn!("save failed: {e:?}");
+                                match e.as_ref() {
+                                    ConfigError::ServerNotStarted => warn!("save failed, as the device server is not running yet. Try again shortly."),
+                                    e => warn!("save failed: {e}"),
+                                }
+                                state.save_error = Some(e);
                             }
                         }
 
                         // trigger a motor refresh
                         // this is needed because when we hit save we may have cleared old tags that no longer match any existing device
-                        Task::perform(get_tagged_devices(application_state), Message::RefreshDevicesComplete)
+                        Task::perform(get_tagged_devices(application_state, device_scale_db, group_scale_db, battery_history_db), Message::RefreshDevicesComplete)
                     }
                     Message::PortUpdated(new_port) => {
                         state.port_text = new_port;
@@ -236,6 +429,42 @@ impl Gui {
                         self.on_configuration_changed();
                         Task::none()
                     }
+                    Message::DeviceScaleChanged(identifier, scale) => {
+                        for device in state.devices.iter_mut().filter(|device| device.identifier.as_ref() == Some(&identifier)) {
+                            device.scale = scale;
+                        }
+                        Task::perform(set_device_scale(state.device_scale_db.clone(), identifier, scale), |_| Message::DeviceScaleApplied)
+                    }
+                    Message::DeviceScaleApplied => Task::none(),
+                    Message::GroupScaleChanged(group, scale) => {
+                        state.group_scales.insert(group.clone(), scale);
+                        Task::perform(set_group_scale(state.group_scale_db.clone(), group, scale), |_| Message::GroupScaleApplied)
+                    }
+                    Message::GroupScaleApplied => Task::none(),
+                    Message::MotorMessageContainer(motor_index, motor_message @ (MotorMessage::ManualValueChanged(_) | MotorMessage::ManualValueReleased)) => {
+                        // manual control doesn't touch the configuration, so there's no need to revalidate tags
+                        state.motors[motor_index].update(motor_message);
+                        let motor = &state.motors[motor_index];
+                        state.manual_control.set(motor.motor.clone(), motor.manual_value);
+                        Task::none()
+                    }
+                    Message::DeviceGroupToggled(device_group) => {
+                        if !state.collapsed_device_groups.remove(&device_group) {
+                            state.collapsed_device_groups.insert(device_group);
+                        }
+                        Task::none()
+                    }
+                    Message::AlwaysOnTopToggled(always_on_top) => {
+                        state.always_on_top = always_on_top;
+                        self.on_configuration_changed();
+                        change_window_level(always_on_top)
+                    }
+                    Message::MotorMessageContainer(motor_index, MotorMessage::EnabledToggled(enabled)) => {
+                        // toggling a motor doesn't touch its tag, so there's no need to revalidate tags
+                        state.motors[motor_index].update(MotorMessage::EnabledToggled(enabled));
+                        self.on_configuration_changed();
+                        Task::none()
+                    }
                     Message::MotorMessageContainer(motor_index, motor_message) => {
                         // this happens BEFORE state.motors is updated with the new information passed via this message
 
@@ -277,7 +506,7 @@ impl Gui {
                             let motor = &mut state.motors[*unique_index];
                             match tag {
                                 Some(tag) => {
-                                    let valid = is_tag_valid(&tag);
+                                    let valid = ConfigurationV3::is_tag_valid(&tag);
                                     tags_valid &= valid; // any falses need to stick
                                     motor.update(MotorMessage::TagUpdated { tag, valid })
                                 }
@@ -301,14 +530,154 @@ impl Gui {
                         // example: https://github.com/iced-rs/iced/blob/master/examples/events/src/main.rs
                         if let Event::Window(iced::window::Event::CloseRequested) = event {
                             info!("received gui shutdown request");
-                            iced::window::get_latest().and_then(iced::window::close)
+                            // iced kills the application as soon as the window closes, so everything else has to shut down first
+                            Task::perform(shut_down(state.application_state_db.clone(), state.sent_command_dbs.clone()), |_| Message::ShutdownReady)
                         } else {
                             Task::none()
                         }
                     }
+                    Message::ShutdownReady => {
+                        recovery::end_session();
+                        iced::window::get_latest().and_then(iced::window::close)
+                    }
                     Message::Tick => {
                         // this should keep battery levels reasonably up to date
-                        Task::perform(get_tagged_devices(state.application_state_db.clone()), Message::RefreshDevicesComplete)
+                        Task::perform(get_tagged_devices(state.application_state_db.clone(), state.device_scale_db.clone(), state.group_scale_db.clone(), state.battery_history_db.clone()), Message::RefreshDevicesComplete)
+                    }
+                    Message::RestoreRecoveredConfiguration => {
+                        match state.recovered_configuration.take() {
+                            Some(configuration) if !state.saving => {
+                                info!("restoring recovered configuration");
+                                state.saving = true;
+                                Task::perform(update_configuration(state.application_state_db.clone(), state.watchdog_timeout_db.clone(), configuration, state.warp_restart_tx.clone()), |result| Message::SaveConfigurationComplete(result, true))
+                            }
+                            recovered_configuration => {
+                                // try again once the current save finishes
+                                state.recovered_configuration = recovered_configuration;
+                                Task::none()
+                            }
+                        }
+                    }
+                    Message::DiscardRecoveredConfiguration => {
+                        info!("discarding recovered configuration");
+                        state.recovered_configuration = None;
+                        if !state.configuration_dirty && state.configuration_persisted {
+                            recovery::clear_snapshot();
+                        }
+                        Task::none()
+                    }
+                    Message::ScanningToggled(scanning) => {
+                        let application_state_db = state.application_state_db.clone();
+                        Task::perform(
+                            async move { buttplug::set_scanning(&application_state_db, scanning).await },
+                            move |result| Message::ScanningChanged(scanning, result),
+                        )
+                    }
+                    Message::ScanningChanged(scanning, result) => {
+                        match result {
+                            Ok(_) => state.scanning = scanning,
+                            Err(e) => warn!("{e}"),
+                        }
+                        Task::none()
+                    }
+                    Message::ServerDisconnected => {
+                        if state.last_configuration.connection_notices {
+                            state.connection_notice = Some(ConnectionNotice::Reconnecting);
+                        }
+                        Task::none()
+                    }
+                    Message::ServerConnected => {
+                        if state.last_configuration.connection_notices {
+                            state.connection_notice = Some(ConnectionNotice::Reconnected);
+                            Task::perform(tokio::time::sleep(RECONNECTED_NOTICE_DURATION), |_| Message::ConnectionNoticeExpired)
+                        } else {
+                            Task::none()
+                        }
+                    }
+                    Message::ServerUnavailable => {
+                        state.connection_notice = Some(ConnectionNotice::Unavailable);
+                        Task::none()
+                    }
+                    Message::WatchdogTimeoutChanged(timeout_ms, persisted) => {
+                        // only this setting changed, so unsaved edits in the GUI are kept, and its next save keeps the new timeout
+                        state.last_configuration.watchdog_timeout_ms = timeout_ms;
+                        if !persisted {
+                            state.configuration_persisted = false;
+                        }
+                        self.on_configuration_changed();
+                        Task::none()
+                    }
+                    Message::RetryServer => {
+                        state.device_server_retry_db.retry();
+                        state.connection_notice = state.last_configuration.connection_notices.then_some(ConnectionNotice::Reconnecting);
+                        Task::none()
+                    }
+                    Message::ConnectionNoticeExpired => {
+                        // a disconnect since the reconnect shouldn't have its notice cleared
+                        if state.connection_notice == Some(ConnectionNotice::Reconnected) {
+                            state.connection_notice = None;
+                        }
+                        Task::none()
+                    }
+                    Message::OpenConfigDirectory => {
+                        open_directory(&config::get_config_dir());
+                        Task::none()
+                    }
+                    Message::OpenLogDirectory => {
+                        open_directory(&logging::get_log_dir());
+                        Task::none()
+                    }
+                    Message::AboutToggled => {
+                        state.show_about = !state.show_about;
+                        Task::none()
+                    }
+                    Message::OpenRepository => {
+                        if let Err(e) = open::that(env!("CARGO_PKG_REPOSITORY")) {
+                            warn!("Failed to open repository URL: {e}");
+                        }
+                        Task::none()
+                    }
+                    Message::TestLatency(identifier) => {
+                        info!("latency test started for {identifier}");
+                        state.latency_tests.insert(identifier.clone(), LatencyTest::Running);
+                        let result_identifier = identifier.clone();
+                        Task::perform(
+                            measure_latency(state.application_state_db.clone(), state.sent_command_dbs.clone(), identifier),
+                            move |result| Message::LatencyTestComplete(result_identifier.clone(), result),
+                        )
+                    }
+                    Message::LatencyTestComplete(identifier, result) => {
+                        match &result {
+                            Ok(report) => info!("latency test of {identifier} finished: {report}"),
+                            Err(e) => warn!("latency test of {identifier} failed: {e}"),
+                        }
+                        state.latency_tests.insert(identifier, LatencyTest::Complete(result));
+                        Task::none()
+                    }
+                    Message::ExportDiagnostics => {
+                        info!("diagnostic bundle export initiated");
+                        Task::perform(choose_bundle_path(), Message::DiagnosticsPathChosen)
+                    }
+                    Message::DiagnosticsPathChosen(path) => {
+                        match path {
+                            Some(path) => Task::perform(export_diagnostics(state.application_state_db.clone(), path), Message::ExportDiagnosticsComplete),
+                            None => {
+                                info!("diagnostic bundle export cancelled");
+                                Task::none()
+                            }
+                        }
+                    }
+                    Message::ExportDiagnosticsComplete(result) => {
+                        match result {
+                            Ok(path) => {
+                                info!("wrote diagnostic bundle to {path:?}");
+                                if let Some(directory) = path.parent() {
+                                    open_directory(directory);
+                                }
+                            }
+                            Err(e) => warn!("{e}"),
+                        }
+                        Task::none()
                     }
                     Message::UpdateButtonPressed => {
                         if let UpdateCheck::UpdateNeeded(update_url) = &state.update_check {
@@ -324,13 +693,10 @@ impl Gui {
         }
     }
 
-    fn view(&self) -> Element<Message> {
+    fn view(&self) -> Element<'_, Message> {
         match self {
-            Gui::Invalid => {
-                panic!("GUI was unexpectedly in an invalid state");
-            }
             Gui::Loaded(state) => {
-                let example_message = format!("example message: {}", build_example_message(&state.motors));
+                let example_message = format!("example message: {}", build_example_message(state.motors.iter().filter_map(|motor| Some((motor.tag()?, &motor.motor)))));
 
                 let save_button_text = if state.saving {
                     "saving…"
@@ -341,16 +707,40 @@ impl Gui {
                 if save_allowed(state) {
                     save_button = save_button.on_press(Message::SaveConfigurationRequest);
                 }
+                let mut apply_button = Button::new(Text::new("apply without saving"));
+                if apply_allowed(state) {
+                    apply_button = apply_button.on_press(Message::ApplyConfigurationRequest);
+                }
 
                 let content = Scrollable::new(
                     Column::new()
                         .spacing(TABLE_SPACING)
                         .padding(TABLE_SPACING)
                         .width(Length::Fill)
+                        .push_maybe(state.connection_notice.map(|notice| {
+                            Row::new()
+                                .spacing(TABLE_SPACING)
+                                .align_y(Alignment::Center)
+                                .push(Text::new(notice.to_string()))
+                                .push_maybe((notice == ConnectionNotice::Unavailable).then(|| Button::new(Text::new("retry")).on_press(Message::RetryServer)))
+                        }))
+                        .push_maybe(state.recovered_configuration.as_ref().map(|_| {
+                            Row::new()
+                                .spacing(TABLE_SPACING)
+                                .align_y(Alignment::Center)
+                                .push(util::input_label("Unsaved changes from a previous session that did not exit cleanly were found."))
+                                .push(Button::new(Text::new("restore")).on_press(Message::RestoreRecoveredConfiguration))
+                                .push(Button::new(Text::new("discard")).on_press(Message::DiscardRecoveredConfiguration))
+                        }))
                         .push({
                             let row = Row::new()
                                 .spacing(TABLE_SPACING)
-                                .push(save_button);
+                                .push(save_button)
+                                .push(apply_button)
+                                .push(Button::new(Text::new("open config folder")).on_press(Message::OpenConfigDirectory))
+                                .push(Button::new(Text::new("open log folder")).on_press(Message::OpenLogDirectory))
+                                .push(Button::new(Text::new("export diagnostics")).on_press(Message::ExportDiagnostics))
+                                .push(Button::new(Text::new("about")).on_press(Message::AboutToggled));
                             if let UpdateCheck::UpdateNeeded(_) = state.update_check {
                                 row.push(
                                     Button::new(Text::new("Update Available!"))
@@ -361,6 +751,8 @@ impl Gui {
                                 row
                             }
                         })
+                        .extend(state.validation_errors.iter().map(|e| Text::new(format!("can't save: {e}")).size(TEXT_SIZE_SMALL).into()))
+                        .extend(state.save_error.iter().map(|e| Text::new(format!("save failed: {e}")).size(TEXT_SIZE_SMALL).into()))
                         .push(Row::new()
                             .spacing(EOL_INPUT_SPACING)
                             .align_y(Alignment::Center)
@@ -375,6 +767,14 @@ impl Gui {
                                         ElementAppearance::from_port_text(state.port_text.as_str()).text_input_custom_style(theme, status)
                                     })
                             )
+                            .push(
+                                Checkbox::new("always on top", state.always_on_top)
+                                    .on_toggle(Message::AlwaysOnTopToggled)
+                            )
+                            .push(
+                                Checkbox::new("scan for devices", state.scanning)
+                                    .on_toggle(Message::ScanningToggled)
+                            )
                         )
                         .push(
                             Rule::horizontal(TABLE_SPACING)
@@ -382,16 +782,20 @@ impl Gui {
                         .push(Row::new()
                             .spacing(TABLE_SPACING)
                             .push(
-                                render_motor_list(&state.motors)
+                                render_motor_list(&state.motors, &state.collapsed_device_groups, state.last_configuration.show_motor_examples, state.last_configuration.show_step_counts)
                             )
                             .push(
-                                render_device_list(&state.devices)
+                                render_device_list(&state.devices, state.devices_over_limit, state.last_configuration.show_device_details, &state.latency_tests)
                             )
                         )
+                        .push_maybe((!state.last_configuration.motor_groups.is_empty()).then(|| {
+                            render_motor_groups(&state.last_configuration.motor_groups, &state.group_scales)
+                        }))
                         .push(
                             Rule::horizontal(TABLE_SPACING)
                         )
                         .push(Text::new(example_message).size(TEXT_SIZE_SMALL))
+                        .push_maybe(state.show_about.then(render_about))
                 );
 
                 Container::new(content)
@@ -402,6 +806,12 @@ impl Gui {
         }
     }
 
+    fn theme(&self) -> Theme {
+        match self {
+            Gui::Loaded(state) => state.theme.clone(),
+        }
+    }
+
     // this is called many times in strange and mysterious ways
     fn subscription(&self) -> Subscription<Message> {
         // example: https://github.com/iced-rs/iced/blob/d993b53e095d9cee71c30b315d8fe84d207ddb6d/examples/events/src/main.rs#L57
@@ -414,24 +824,55 @@ impl Gui {
                     .map(|event| match event {
                         ApplicationStatusEvent::DeviceAdded => Message::RefreshDevices,
                         ApplicationStatusEvent::DeviceRemoved => Message::RefreshDevices,
+                        ApplicationStatusEvent::ConfigurationReloaded => Message::ConfigurationReloaded,
+                        ApplicationStatusEvent::ServerDisconnected => Message::ServerDisconnected,
+                        ApplicationStatusEvent::ServerConnected => Message::ServerConnected,
+                        ApplicationStatusEvent::ServerUnavailable => Message::ServerUnavailable,
+                        ApplicationStatusEvent::WatchdogTimeoutChanged { timeout_ms, persisted } => Message::WatchdogTimeoutChanged(timeout_ms, persisted),
                         ApplicationStatusEvent::Tick => Message::Tick
                     });
                 Subscription::batch(vec![application_events, native_events])
             }
-            Gui::Invalid => panic!("GUI was unexpectedly in an invalid state"),
         }
     }
 }
 
+/// version, commit, license and where to find the source, for bug reports and the AGPL's sake
+fn render_about<'a>() -> Element<'a, Message> {
+    Column::new()
+        .spacing(EOL_INPUT_SPACING)
+        .push(Rule::horizontal(TABLE_SPACING))
+        .push(Text::new(format!("{} v{} (commit {})", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), env!("GIT_COMMIT_HASH"))))
+        .push(Text::new(format!("Licensed under the {} license. The source code is available at {}", env!("CARGO_PKG_LICENSE"), env!("CARGO_PKG_REPOSITORY"))).size(TEXT_SIZE_SMALL))
+        .push(Text::new("Bugs can be reported as GitHub issues. Please include the version and commit above.").size(TEXT_SIZE_SMALL))
+        .push(Button::new(Text::new("open repository")).on_press(Message::OpenRepository))
+        .into()
+}
+
+fn window_level(always_on_top: bool) -> window::Level {
+    if always_on_top {
+        window::Level::AlwaysOnTop
+    } else {
+        window::Level::Normal
+    }
+}
+
+/// apply a window level change immediately. This does nothing on platforms that don't support it, in which case the change applies on next launch.
+fn change_window_level(always_on_top: bool) -> Task<Message> {
+    window::get_latest().and_then(move |id| window::change_level(id, window_level(always_on_top)))
+}
+
 #[derive(Debug, Clone)]
 struct StartupActionResult {
     update_check: UpdateCheck,
 }
 
-async fn gui_startup_action() -> StartupActionResult {
-    // grab our local version
-    let local_version = Version::parse(env!("CARGO_PKG_VERSION")).unwrap_or_else(|e| panic!("Local version \"{}\" didn't follow semver! {}", env!("CARGO_PKG_VERSION"), e));
-    let update_url = update_checker::check_for_update(local_version).await;
+fn local_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).unwrap_or_else(|e| panic!("Local version \"{}\" didn't follow semver! {}", env!("CARGO_PKG_VERSION"), e))
+}
+
+async fn gui_startup_action(update_check_settings: UpdateCheckV3) -> StartupActionResult {
+    let update_url = update_checker::check_for_update(&local_version(), &update_check_settings).await;
     let update_check = match update_url {
         Some(update_url) => UpdateCheck::UpdateNeeded(update_url),
         None => UpdateCheck::NoUpdateNeeded,
@@ -446,23 +887,58 @@ impl Display for TaggedMotor {
     }
 }
 
-fn render_motor_list(motors: &[TaggedMotor]) -> Element<Message> {
-    let col = Column::new()
+fn render_motor_list<'a>(motors: &'a [TaggedMotor], collapsed_device_groups: &HashSet<DeviceGroup>, show_examples: bool, show_step_counts: bool) -> Element<'a, Message> {
+    let mut col = Column::new()
         .spacing(TABLE_SPACING)
         .push(Text::new("Motor Configuration").size(TEXT_SIZE_BIG));
-    let col = if motors.is_empty() {
-        col.push(Text::new("No motors"))
+    if motors.is_empty() {
+        col = col.push(Text::new("No motors"));
+    } else {
+        // motors are sorted by device, so each device's motors are next to each other
+        let mut first_index = 0;
+        for group in motors.chunk_by(|a, b| DeviceGroup::of(&a.motor).contains(&b.motor)) {
+            let device_group = DeviceGroup::of(&group[0].motor);
+            col = col.push(render_motor_group(group, first_index, collapsed_device_groups.contains(&device_group), device_group, show_examples, show_step_counts));
+            first_index += group.len();
+        }
+    }
+    col.into()
+}
+
+/// render one device's motors under a header that hides or shows them. `first_index` is the index of the group's first motor in the full list.
+fn render_motor_group(motors: &[TaggedMotor], first_index: usize, collapsed: bool, device_group: DeviceGroup, show_examples: bool, show_step_counts: bool) -> Element<'_, Message> {
+    let label = match &device_group.device_identifier {
+        Some(_) => device_group.device_name.clone(),
+        None => format!("{} [LEGACY]", device_group.device_name),
+    };
+    let motor_count = if motors.len() == 1 { "1 motor".to_string() } else { format!("{} motors", motors.len()) };
+    let header = Row::new()
+        .spacing(EOL_INPUT_SPACING)
+        .align_y(Alignment::Center)
+        .push(
+            Button::new(Text::new(if collapsed { "+" } else { "-" })) // font doesn't support funny characters like "▸"
+                .on_press(Message::DeviceGroupToggled(device_group))
+        )
+        .push(Text::new(label))
+        .push(Text::new(motor_count).size(TEXT_SIZE_SMALL));
+
+    let col = Column::new()
+        .spacing(TABLE_SPACING)
+        .push(header);
+    if collapsed {
+        col.into()
     } else {
         motors.iter()
             .enumerate()
             .fold(col, |column, (i, motor)| {
-                column.push(motor.view().map(move |message| Message::MotorMessageContainer(i, message)))
+                let i = first_index + i;
+                column.push(motor.view(show_examples, show_step_counts).map(move |message| Message::MotorMessageContainer(i, message)))
             })
-    };
-    col.into()
+            .into()
+    }
 }
 
-fn render_device_list(devices: &[DeviceStatus]) -> Element<Message> {
+fn render_device_list<'a>(devices: &'a [DeviceStatus], devices_over_limit: usize, show_details: bool, latency_tests: &HashMap<String, LatencyTest>) -> Element<'a, Message> {
     let col = Column::new()
         .spacing(TABLE_SPACING)
         .push(Text::new("Connected Devices").size(TEXT_SIZE_BIG));
@@ -471,18 +947,146 @@ fn render_device_list(devices: &[DeviceStatus]) -> Element<Message> {
     } else {
         devices.iter()
             .fold(col, |column, device| {
-                column.push(util::input_label(format!("{device}")))
+                let row = Row::new()
+                    .spacing(EOL_INPUT_SPACING)
+                    .align_y(Alignment::Center)
+                    .push(util::input_label(format!("{device}")));
+                let row = match &device.identifier {
+                    Some(identifier) => {
+                        let running = matches!(latency_tests.get(identifier), Some(LatencyTest::Running));
+                        let latency_test_message = (device.motor_count > 0 && !running).then(|| Message::TestLatency(identifier.clone()));
+                        let identifier = identifier.clone();
+                        row.push(
+                            Slider::new(DEVICE_SCALE_RANGE, device.scale, move |scale| Message::DeviceScaleChanged(identifier.clone(), scale))
+                                .step(DEVICE_SCALE_STEP)
+                                .width(Length::Fixed(DEVICE_SCALE_SLIDER_WIDTH))
+                        )
+                        .push(Text::new(format!("{:.0}%", device.scale * 100.0)).size(TEXT_SIZE_SMALL))
+                        .push_maybe((device.motor_count > 0).then(|| {
+                            Button::new(Text::new("test latency").size(TEXT_SIZE_SMALL)).on_press_maybe(latency_test_message)
+                        }))
+                    }
+                    None => row,
+                };
+                let latency_test = device.identifier.as_ref()
+                    .and_then(|identifier| latency_tests.get(identifier))
+                    .map(|latency_test| match latency_test {
+                        LatencyTest::Running => "testing latency…".to_string(),
+                        LatencyTest::Complete(Ok(report)) => report.to_string(),
+                        LatencyTest::Complete(Err(e)) => format!("latency test failed: {e}"),
+                    });
+                column.push(row)
+                    .push_maybe(latency_test.map(|latency_test| Text::new(latency_test).size(TEXT_SIZE_SMALL)))
+                    .push_maybe((device.motor_count == 0).then(|| Text::new("This device has no motors buttplug-lite can control").size(TEXT_SIZE_SMALL)))
+                    .push_maybe(show_details.then(|| Text::new(device.details()).size(TEXT_SIZE_SMALL)))
             })
     };
-    col.into()
+    col.push_maybe((devices_over_limit > 0).then(|| {
+        Text::new(format!("{devices_over_limit} more connected, but not used because of the max_devices setting")).size(TEXT_SIZE_SMALL)
+    }))
+        .into()
+}
+
+/// a master scale slider for each motor group, in name order
+fn render_motor_groups<'a>(motor_groups: &'a HashMap<String, Vec<String>>, group_scales: &HashMap<String, f64>) -> Element<'a, Message> {
+    let mut groups: Vec<(&String, &Vec<String>)> = motor_groups.iter().collect();
+    groups.sort_unstable_by_key(|(group, _)| *group);
+    groups.into_iter()
+        .fold(Column::new().spacing(TABLE_SPACING).push(Text::new("Motor Groups").size(TEXT_SIZE_BIG)), |column, (group, tags)| {
+            let scale = group_scales.get(group).copied().unwrap_or(1.0);
+            let group_name = group.clone();
+            column.push(
+                Row::new()
+                    .spacing(EOL_INPUT_SPACING)
+                    .align_y(Alignment::Center)
+                    .push(util::input_label(group.as_str()))
+                    .push(
+                        Slider::new(DEVICE_SCALE_RANGE, scale, move |scale| Message::GroupScaleChanged(group_name.clone(), scale))
+                            .step(DEVICE_SCALE_STEP)
+                            .width(Length::Fixed(DEVICE_SCALE_SLIDER_WIDTH))
+                    )
+                    .push(Text::new(format!("{:.0}%", scale * 100.0)).size(TEXT_SIZE_SMALL))
+                    .push(Text::new(tags.join(", ")).size(TEXT_SIZE_SMALL))
+            )
+        })
+        .into()
+}
+
+async fn get_tagged_devices(application_state_db: ApplicationStateDb, device_scale_db: DeviceScaleDb, group_scale_db: GroupScaleDb, battery_history_db: BatteryHistoryDb) -> Option<ApplicationStatus> {
+    buttplug::get_tagged_devices(&application_state_db, &device_scale_db, &group_scale_db, &battery_history_db).await
+}
+
+/// Ask the user where to save a diagnostic bundle, starting from the default location. Returns `None` if they cancel.
+async fn choose_bundle_path() -> Option<PathBuf> {
+    let default_path = diagnostics::default_bundle_path();
+    let mut dialog = AsyncFileDialog::new()
+        .set_title("Save diagnostic bundle")
+        .add_filter("zip archive", &["zip"]);
+    if let Some(directory) = default_path.parent() {
+        dialog = dialog.set_directory(directory);
+    }
+    if let Some(file_name) = default_path.file_name() {
+        dialog = dialog.set_file_name(file_name.to_string_lossy());
+    }
+    dialog.save_file().await.map(|file| file.path().to_path_buf())
 }
 
-async fn get_tagged_devices(application_state_db: ApplicationStateDb) -> Option<ApplicationStatus> {
-    buttplug::get_tagged_devices(&application_state_db).await
+/// Write a diagnostic bundle to `path`, returning where it was written
+async fn export_diagnostics(application_state_db: ApplicationStateDb, path: PathBuf) -> Result<PathBuf, Arc<DiagnosticsError>> {
+    let snapshot = application_state_db.read().await.as_ref()
+        .map(DiagnosticSnapshot::from_application_state)
+        .unwrap_or_default();
+    let bundle_path = path.clone();
+    task::spawn_blocking(move || diagnostics::write_bundle(&bundle_path, snapshot)).await
+        .map_err(DiagnosticsError::from)
+        .and_then(convert::identity)
+        .map_err(Arc::new)?;
+    Ok(path)
 }
 
-async fn update_configuration(application_state_db: ApplicationStateDb, configuration: ConfigurationV3, warp_shutdown_tx: UnboundedSender<ShutdownMessage>) -> Result<ConfigurationV3, String> {
-    crate::config::update_configuration(&application_state_db, configuration, &warp_shutdown_tx).await
+/// Open a directory in the system file browser, creating it first if needed
+fn open_directory(path: &Path) {
+    if let Err(e) = fs::create_dir_all(path) {
+        warn!("Failed to create directory {path:?}: {e}");
+    } else if let Err(e) = open::that(path) {
+        warn!("Failed to open directory {path:?}: {e}");
+    }
+}
+
+async fn stop_all_devices(application_state_db: ApplicationStateDb, sent_command_dbs: SentCommandDbs) {
+    buttplug::stop_all_devices(&application_state_db, &sent_command_dbs).await
+}
+
+/// Stop all devices, then run the shutdown hook and wait for it
+async fn shut_down(application_state_db: ApplicationStateDb, sent_command_dbs: SentCommandDbs) {
+    stop_all_devices(application_state_db.clone(), sent_command_dbs).await;
+    let shutdown_command = application_state_db.read().await.as_ref()
+        .and_then(|application_state| application_state.configuration.shutdown_command.clone());
+    if let Some(command) = shutdown_command {
+        command_hook::run("shutdown", &command, SHUTDOWN_HOOK_TIMEOUT).await;
+    }
+}
+
+async fn measure_latency(application_state_db: ApplicationStateDb, sent_command_dbs: SentCommandDbs, identifier: String) -> Result<LatencyReport, String> {
+    buttplug::measure_latency(&application_state_db, &sent_command_dbs, &identifier).await
+}
+
+async fn set_device_scale(device_scale_db: DeviceScaleDb, identifier: String, scale: f64) {
+    structs::set_scale(&device_scale_db, identifier, scale).await
+}
+
+async fn set_group_scale(group_scale_db: GroupScaleDb, group: String, scale: f64) {
+    structs::set_scale(&group_scale_db, group, scale).await
+}
+
+async fn apply_configuration(application_state_db: ApplicationStateDb, watchdog_timeout_db: WatchdogTimeoutDb, configuration: ConfigurationV3, warp_shutdown_tx: UnboundedSender<ShutdownMessage>) -> Result<ConfigurationV3, Arc<ConfigError>> {
+    crate::config::apply_configuration(&application_state_db, &watchdog_timeout_db, configuration, &warp_shutdown_tx).await
+        .map_err(Arc::new)
+}
+
+async fn update_configuration(application_state_db: ApplicationStateDb, watchdog_timeout_db: WatchdogTimeoutDb, configuration: ConfigurationV3, warp_shutdown_tx: UnboundedSender<ShutdownMessage>) -> Result<ConfigurationV3, Arc<ConfigError>> {
+    crate::config::update_configuration(&application_state_db, &watchdog_timeout_db, configuration, &warp_shutdown_tx).await
+        .map_err(Arc::new) // iced messages must be Clone, which io::Error is not
 }
 
 fn tags_from_application_status(motors: &[TaggedMotor]) -> HashMap<String, MotorConfigurationV3> {
@@ -492,19 +1096,6 @@ fn tags_from_application_status(motors: &[TaggedMotor]) -> HashMap<String, Motor
         .collect()
 }
 
-fn build_example_message(motors: &[TaggedMotor]) -> String {
-    motors.iter()
-        .flat_map(|motor| {
-            motor.tag().map(|tag| match motor.motor.feature_type {
-                MotorTypeV3::Linear => format!("{tag}:20:0.5"),
-                MotorTypeV3::Rotation => format!("{tag}:-0.5"),
-                MotorTypeV3::Scalar { .. } => format!("{tag}:0.5"),
-            })
-        })
-        .collect::<Vec<_>>()
-        .join(";")
-}
-
 #[inline(always)]
 fn override_tag_at_index<'a>(slice: &'a [TaggedMotor], read_index: usize, override_index: usize, override_value: Option<&'a str>) -> Option<&'a str> {
     if read_index == override_index {
@@ -516,10 +1107,11 @@ fn override_tag_at_index<'a>(slice: &'a [TaggedMotor], read_index: usize, overri
 
 #[inline(always)]
 fn save_allowed(state: &State) -> bool {
-    state.configuration_dirty && state.motor_tags_valid && !state.saving
+    (state.configuration_dirty || !state.configuration_persisted) && state.motor_tags_valid && !state.saving
 }
 
 #[inline(always)]
-fn is_tag_valid(tag: &str) -> bool {
-    !tag.contains(':') && !tag.contains(';')
+fn apply_allowed(state: &State) -> bool {
+    state.configuration_dirty && state.motor_tags_valid && !state.saving
 }
+
@@ -5,21 +5,24 @@
 // necessary to remove the weird console window that appears alongside the real GUI on Windows
 #![windows_subsystem = "windows"]
 
+use std::collections::HashMap;
+use std::env;
 use std::ops::DerefMut as _;
 use std::sync::Arc;
-use std::sync::atomic::AtomicI64;
 use std::time::Duration;
 
 use clap::Parser as _;
-use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::sync::{mpsc, oneshot, watch, RwLock};
 use tokio::task;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 use crate::app::buttplug;
-use crate::app::structs::{ApplicationState, ApplicationStateDb, CliArgs};
-use crate::app::webserver::ShutdownMessage;
+use crate::app::structs::{ApplicationState, ApplicationStateDb, BatteryHistoryDb, CliArgs, DeviceNumberingDb, DeviceScaleDb, DeviceServerRetryDb, GroupScaleDb, MotorHoldDb, MotorRampDb, SentCommandDbs, TagValueDb};
+use crate::app::webserver::{HapticState, ShutdownMessage, WebserverChannels, WebserverError};
+use crate::config::ConfigurationLayers;
+use crate::config::v3::ConfigurationV3;
 use crate::gui::subscription::{ApplicationStatusEvent, SubscriptionProvider};
-use crate::util::{logging, watchdog};
+use crate::util::{command_hook, diagnostics, keepalive, log_shipping, logging, watchdog};
 use crate::util::watchdog::WatchdogTimeoutDb;
 
 mod app;
@@ -28,11 +31,27 @@ mod gui;
 mod util;
 
 fn main() {
-    util::GLOBAL_TOKIO_RUNTIME.block_on(tokio_main())
+    let args: CliArgs = CliArgs::parse();
+
+    if let Some(base) = args.base_config.clone() {
+        config::use_configuration_layers(ConfigurationLayers {
+            base,
+            overrides: args.override_config.clone().unwrap_or_else(|| config::CONFIG_DIR_FILE_PATH.clone()),
+        });
+    }
+
+    // iced has no setting for this, but wgpu reads its backends from the environment. Changing the environment is only
+    // sound before any other threads exist, so this is done before the runtime starts.
+    let prefer_opengl = (args.prefer_opengl || config::read_minimal_configuration().prefer_opengl) && env::var_os("WGPU_BACKEND").is_none();
+    if prefer_opengl {
+        env::set_var("WGPU_BACKEND", "gl");
+    }
+
+    util::GLOBAL_TOKIO_RUNTIME.block_on(tokio_main(args, prefer_opengl))
 }
 
-async fn tokio_main() {
-    let args: CliArgs = CliArgs::parse();
+/// `prefer_opengl` is `true` if OpenGL was chosen for rendering the GUI
+async fn tokio_main(args: CliArgs, prefer_opengl: bool) {
 
     // after logging init we can use tracing to log. Any tracing logs before this point go nowhere.
     let _log_guard = logging::init(
@@ -44,11 +63,70 @@ async fn tokio_main() {
     );
 
     info!("initializing {} {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), env!("GIT_COMMIT_HASH"));
+    if prefer_opengl {
+        info!("preferring OpenGL for rendering");
+    }
+
+    if let Some(path) = &args.diagnostics {
+        match diagnostics::write_bundle(path, Default::default()) {
+            Ok(()) => info!("wrote diagnostic bundle to {path:?}"),
+            Err(e) => {
+                error!("{e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.migrate_dry_run {
+        match config::migration::migration_preview() {
+            Ok(report) => print!("{report}"),
+            Err(e) => {
+                error!("failed to read configuration: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.list_midi_ports {
+        match app::webserver::midi_input_port_names() {
+            Ok(names) => names.iter().for_each(|name| println!("{name}")),
+            Err(e) => {
+                error!("failed to list MIDI input ports: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    let watchdog_timeout_db: WatchdogTimeoutDb = Arc::new(AtomicI64::new(i64::MAX));
+    if args.test_pipeline {
+        match app::webserver::run_pipeline_test() {
+            Ok(()) => info!("pipeline test passed"),
+            Err(e) => {
+                error!("pipeline test failed: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let watchdog_timeout_db: WatchdogTimeoutDb = Default::default();
     let application_state_db: ApplicationStateDb = Arc::new(RwLock::new(None));
+    let tag_value_db: TagValueDb = Arc::new(RwLock::new(HashMap::new()));
+    let sent_command_dbs = SentCommandDbs::default();
+    let device_scale_db: DeviceScaleDb = Arc::new(RwLock::new(HashMap::new()));
+    let group_scale_db: GroupScaleDb = Arc::new(RwLock::new(HashMap::new()));
+    let battery_history_db: BatteryHistoryDb = Arc::new(RwLock::new(Default::default()));
+    let motor_hold_db: MotorHoldDb = Arc::new(RwLock::new(Default::default()));
+    let motor_ramp_db: MotorRampDb = Arc::new(RwLock::new(Default::default()));
+    let device_server_retry_db: DeviceServerRetryDb = Default::default();
+    let device_numbering_db: DeviceNumberingDb = Default::default();
 
-    watchdog::start(watchdog_timeout_db.clone(), application_state_db.clone());
+    let haptic_state = HapticState::new(application_state_db.clone(), tag_value_db, sent_command_dbs.clone(), device_scale_db.clone(), group_scale_db.clone(), motor_hold_db, motor_ramp_db);
+
+    watchdog::start(watchdog_timeout_db.clone(), haptic_state.clone());
+    keepalive::start(application_state_db.clone(), sent_command_dbs.clone());
 
     // used to send initial port over from the configuration load
     let (initial_config_loaded_tx, initial_config_loaded_rx) = oneshot::channel::<()>();
@@ -66,54 +144,96 @@ async fn tokio_main() {
         });
     }
 
-    buttplug::start_server(application_state_db.clone(), initial_config_loaded_tx, application_status_sender).await;
+    // lets the web server wait for the first device, if configured to
+    let (device_connected_tx, device_connected_rx) = watch::channel(false);
+
+    buttplug::start_server(application_state_db.clone(), initial_config_loaded_tx, application_status_sender.clone(), device_connected_tx, sent_command_dbs.clone(), device_server_retry_db.clone(), device_numbering_db).await;
 
     // use to shut down or restart the webserver
     let (warp_shutdown_initiate_tx, warp_shutdown_initiate_rx) = mpsc::unbounded_channel::<ShutdownMessage>();
 
     // called once warp is done dying
-    let (warp_shutdown_complete_tx, warp_shutdown_complete_rx) = oneshot::channel::<()>();
+    let (warp_shutdown_complete_tx, warp_shutdown_complete_rx) = oneshot::channel::<Result<(), WebserverError>>();
 
     // triggers the GUI to start, only called after warp spins up
     let (gui_start_tx, gui_start_rx) = oneshot::channel::<()>();
 
     // start up the webserver
     app::webserver::start_webserver(
-        application_state_db.clone(),
-        watchdog_timeout_db,
-        initial_config_loaded_rx,
-        gui_start_tx,
-        warp_shutdown_initiate_rx,
-        warp_shutdown_complete_tx,
+        haptic_state.clone(),
+        watchdog_timeout_db.clone(),
+        battery_history_db.clone(),
+        device_server_retry_db.clone(),
+        WebserverChannels {
+            application_status_sender: application_status_sender.clone(),
+            initial_config_loaded_rx,
+            device_connected_rx,
+            gui_start_tx,
+            warp_shutdown_initiate_rx,
+            warp_shutdown_complete_tx,
+        },
     );
 
     if let Ok(()) = gui_start_rx.await {
+        let log_shipping_url = application_state_db.read().await.as_ref()
+            .and_then(|application_state| application_state.configuration.log_shipping_url.clone());
+        if let Some(url) = log_shipping_url {
+            log_shipping::start(url);
+        }
+        if let Some(command) = configured_command(&application_state_db, |configuration| &configuration.startup_command).await {
+            command_hook::spawn("startup", &command);
+        }
+
+        let watch_config_file = application_state_db.read().await.as_ref()
+            .is_some_and(|application_state| application_state.configuration.watch_config_file);
+        if watch_config_file && config::is_configured_by_environment() {
+            warn!("not watching the configuration file, as the configuration comes from the {} environment variable", config::CONFIG_ENV_VAR);
+        } else if watch_config_file && config::is_configured_by_layers() {
+            warn!("not watching the configuration file, as the configuration is layered over --base-config");
+        } else if watch_config_file {
+            config::watcher::start(application_state_db.clone(), watchdog_timeout_db.clone(), warp_shutdown_initiate_tx.clone(), application_status_sender);
+        }
+
         //TODO: wait for buttplug to notice devices
-        let initial_devices = buttplug::get_tagged_devices(&application_state_db).await.expect("Application failed to initialize");
+        let initial_devices = buttplug::get_tagged_devices(&application_state_db, &device_scale_db, &group_scale_db, &battery_history_db).await.expect("Application failed to initialize");
 
         let subscription = SubscriptionProvider::new(application_status_receiver);
-        gui::run(application_state_db.clone(), warp_shutdown_initiate_tx, initial_devices, subscription); // blocking call
+        let recovered_configuration = config::recovery::begin_session();
+        let rendering_options = gui::RenderingOptions { no_antialiasing: args.no_antialiasing };
+        let flags = gui::Flags {
+            warp_restart_tx: warp_shutdown_initiate_tx,
+            haptic_state,
+            battery_history_db,
+            watchdog_timeout_db,
+            device_server_retry_db,
+            initial_application_status: initial_devices,
+            application_status_subscription: subscription,
+            recovered_configuration,
+        };
+        gui::run(flags, rendering_options); // blocking call
 
         // NOTE: iced hard kills the application when the windows is closed!
         // That means this code is unreachable.
         // As far as I'm aware it is currently impossible to register any sort of shutdown
         // hook/return/signal from iced once you sacrifice your main thread.
-        // For now this is fine, as we don't have any super sensitive shutdown code to run,
-        // especially with the buttplug server being (apparently?) unstoppable.
+        // That's why the GUI stops all devices and runs the shutdown hook itself before it closes its window.
     }
 
     // at this point we begin cleaning up resources for shutdown
     info!("shutting down…");
 
     // but first, wait for warp to close
-    if let Err(e) = warp_shutdown_complete_rx.await {
-        info!("error shutting down warp webserver: {e:?}")
-    } else {
-        info!("initiated warp webserver graceful shutdown");
+    let webserver_result = warp_shutdown_complete_rx.await;
+    match &webserver_result {
+        Ok(Ok(())) => info!("initiated warp webserver graceful shutdown"),
+        Ok(Err(e)) => info!("warp webserver stopped due to error: {e}"),
+        Err(e) => info!("error shutting down warp webserver: {e:?}"),
     }
 
     // it's be nice if I could shut down buttplug with `server.shutdown()`, but I'm forced to give server ownership to the connector
     // it'd be nice if I could shut down buttplug with `connector.server_ref().shutdown();`, but I'm forced to give connector ownership to the client
+    // the client can still stop every device before it disconnects, so nothing is left running
+    buttplug::stop_all_devices(&application_state_db, &sent_command_dbs).await;
     let mut application_state_mutex = application_state_db.write().await;
     if let Some(application_state) = application_state_mutex.deref_mut() {
         if let Err(e) = application_state.client.disconnect().await {
@@ -122,4 +242,15 @@ async fn tokio_main() {
     }
 
     info!("shutdown complete");
+
+    // if the web server failed to start we never got as far as the GUI, so let any calling script know what happened
+    if let Ok(Err(e)) = webserver_result {
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// read a command hook out of the current configuration
+async fn configured_command(application_state_db: &ApplicationStateDb, hook: impl FnOnce(&ConfigurationV3) -> &Option<Vec<String>>) -> Option<Vec<String>> {
+    application_state_db.read().await.as_ref()
+        .and_then(|application_state| hook(&application_state.configuration).clone())
 }
@@ -0,0 +1,75 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! User-configured commands run at various points in the application lifecycle
+
+use std::process::Command;
+use std::time::Duration;
+
+use tokio::process;
+use tokio::time;
+use tracing::{info, warn};
+
+/// Spawn a user-configured command. `command` is the program followed by its arguments.
+/// Failures are logged, but are otherwise ignored: a broken hook should never take down the application.
+pub fn spawn(hook_name: &str, command: &[String]) {
+    match command.split_first() {
+        Some((program, args)) => {
+            match Command::new(program).args(args).spawn() {
+                Ok(child) => info!("{hook_name} hook: started {program:?} with pid {}", child.id()),
+                Err(e) => warn!("{hook_name} hook: failed to start {program:?}: {e}"),
+            }
+        }
+        None => warn!("{hook_name} hook: ignoring empty command"),
+    }
+}
+
+/// Run a user-configured command like [`spawn`], then wait up to `timeout` for it to finish.
+/// A command that takes longer is left running.
+pub async fn run(hook_name: &str, command: &[String], timeout: Duration) {
+    let Some((program, args)) = command.split_first() else {
+        warn!("{hook_name} hook: ignoring empty command");
+        return;
+    };
+    let mut child = match process::Command::new(program).args(args).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("{hook_name} hook: failed to start {program:?}: {e}");
+            return;
+        }
+    };
+    info!("{hook_name} hook: started {program:?} with pid {:?}", child.id());
+    match time::timeout(timeout, child.wait()).await {
+        Ok(Ok(status)) => info!("{hook_name} hook: {program:?} exited with {status}"),
+        Ok(Err(e)) => warn!("{hook_name} hook: failed to wait for {program:?}: {e}"),
+        Err(_) => warn!("{hook_name} hook: {program:?} is still running after {timeout:?}, no longer waiting for it"),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn shell(script: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), script.to_string()]
+    }
+
+    #[tokio::test]
+    async fn run_waits_for_command() {
+        let path = std::env::temp_dir().join(format!("buttplug-lite-hook-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        run("test", &shell(&format!("sleep 0.2 && touch '{}'", path.display())), Duration::from_secs(10)).await;
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_stops_waiting_after_timeout() {
+        let start = Instant::now();
+        run("test", &shell("sleep 10"), Duration::from_millis(100)).await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! Diagnostic bundles: a single zip file with everything needed to look into a bug report
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use thiserror::Error;
+use tokio::task;
+use toml::{Table, Value};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::app::buttplug::{display_name_from_device, id_from_device};
+use crate::app::structs::ApplicationState;
+use crate::config::CONFIG_DIR_FILE_PATH;
+use crate::util::logging;
+
+/// configuration keys that may hold secrets, such as tokens passed to a command or included in a URL
+static REDACTED_KEYS: [&str; 3] = ["startup_command", "shutdown_command", "log_shipping_url"];
+static REDACTED_KEY_FRAGMENTS: [&str; 4] = ["token", "secret", "password", "auth"];
+static REDACTED_VALUE: &str = "<redacted>";
+
+#[derive(Debug, Error)]
+pub enum DiagnosticsError {
+    #[error("could not write diagnostic bundle: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not write diagnostic bundle: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("diagnostic bundle task failed: {0}")]
+    Join(#[from] task::JoinError),
+}
+
+/// What goes into a diagnostic bundle besides the files that are always included
+#[derive(Debug, Default)]
+pub struct DiagnosticSnapshot {
+    /// the configuration in use, serialized as TOML. If absent, the configuration file is read from disk instead.
+    pub configuration: Option<String>,
+    /// a description of each connected device and its capabilities. If absent, the bundle notes that devices were not available.
+    pub devices: Option<String>,
+}
+
+impl DiagnosticSnapshot {
+    /// Capture the in-memory configuration and connected devices of a running device server
+    pub fn from_application_state(application_state: &ApplicationState) -> DiagnosticSnapshot {
+        let mut devices = String::new();
+        for device in application_state.devices() {
+            let name = display_name_from_device(&device, application_state);
+            let identifier = id_from_device(&device, &application_state.device_manager).unwrap_or_else(|| "<unknown>".to_string());
+            // writing to a String can't fail
+            let _ = writeln!(devices, "{name} ({}) @ {identifier}\n  {:?}", device.name(), device.message_attributes());
+        }
+        if devices.is_empty() {
+            devices.push_str("no devices connected\n");
+        }
+
+        DiagnosticSnapshot {
+            configuration: toml::to_string(&application_state.configuration).ok(),
+            devices: Some(devices),
+        }
+    }
+}
+
+/// Where the GUI suggests saving a bundle: a timestamped file next to the log files
+pub fn default_bundle_path() -> PathBuf {
+    logging::get_log_dir().join(Local::now().format("diagnostics_%Y-%m-%d_%H-%M-%S.zip").to_string())
+}
+
+/// Write a diagnostic bundle to `path`, containing build and OS information, the redacted configuration, the most recent
+/// log file, and the devices from `snapshot`
+pub fn write_bundle(path: &Path, snapshot: DiagnosticSnapshot) -> Result<(), DiagnosticsError> {
+    let configuration = match snapshot.configuration {
+        Some(configuration) => Ok(configuration),
+        None => fs::read_to_string(CONFIG_DIR_FILE_PATH.as_path()),
+    };
+    let configuration = match configuration {
+        Ok(configuration) => redact_configuration(&configuration),
+        Err(e) => format!("# could not read configuration: {e}\n"),
+    };
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default();
+
+    zip.start_file("build.txt", options)?;
+    writeln!(zip, "{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))?;
+    writeln!(zip, "commit {}", env!("GIT_COMMIT_HASH"))?;
+    writeln!(zip, "os {} ({}) {}", std::env::consts::OS, std::env::consts::FAMILY, std::env::consts::ARCH)?;
+
+    zip.start_file("config.toml", options)?;
+    zip.write_all(configuration.as_bytes())?;
+
+    zip.start_file("devices.txt", options)?;
+    zip.write_all(snapshot.devices.as_deref().unwrap_or("device server was not running\n").as_bytes())?;
+
+    if let Some(log_path) = latest_log_file() {
+        let log_name = log_path.file_name().map_or("latest.log".into(), |name| name.to_string_lossy());
+        zip.start_file(format!("logs/{log_name}"), options)?;
+        io::copy(&mut File::open(&log_path)?, &mut zip)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Blank out every value in a TOML document that might be a secret. Documents that can't be parsed are left out entirely.
+fn redact_configuration(configuration: &str) -> String {
+    match configuration.parse::<Table>() {
+        Ok(mut table) => {
+            redact_table(&mut table);
+            table.to_string()
+        }
+        // the full error quotes the line that failed to parse, which may hold a secret
+        Err(e) => format!("# configuration could not be parsed, so it was left out: {}\n", e.message()),
+    }
+}
+
+fn redact_table(table: &mut Table) {
+    for (key, value) in table.iter_mut() {
+        // tables are never secrets themselves, and their keys may be user-chosen names such as tags
+        if let Value::Table(inner) = value {
+            redact_table(inner);
+            continue;
+        }
+        let lowercase_key = key.to_lowercase();
+        if REDACTED_KEYS.contains(&lowercase_key.as_str()) || REDACTED_KEY_FRAGMENTS.iter().any(|fragment| lowercase_key.contains(fragment)) {
+            *value = Value::String(REDACTED_VALUE.to_string());
+        } else if let Value::Array(values) = value {
+            redact_array(values);
+        }
+    }
+}
+
+/// Redact the tables in an array, such as `[[device_renames]]`, including those in nested arrays
+fn redact_array(values: &mut [Value]) {
+    for value in values {
+        match value {
+            Value::Table(table) => redact_table(table),
+            Value::Array(values) => redact_array(values),
+            _ => (),
+        }
+    }
+}
+
+/// the most recently created log file, if there are any
+fn latest_log_file() -> Option<PathBuf> {
+    fs::read_dir(logging::get_log_dir()).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "log"))
+        .max() // log file names are timestamps, so they sort by age
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let redacted = redact_configuration(r#"
+            port = 3031
+            startup_command = "notify --token abc"
+            api_token = "abc"
+            [tags.secret]
+            device_name = "toy"
+            password = "abc"
+        "#);
+        let table = redacted.parse::<Table>().unwrap();
+        assert_eq!(table["port"].as_integer(), Some(3031));
+        assert_eq!(table["startup_command"].as_str(), Some(REDACTED_VALUE));
+        assert_eq!(table["api_token"].as_str(), Some(REDACTED_VALUE));
+        // a tag is kept even if its name looks like a secret, but secrets inside it are not
+        assert_eq!(table["tags"]["secret"]["device_name"].as_str(), Some("toy"));
+        assert_eq!(table["tags"]["secret"]["password"].as_str(), Some(REDACTED_VALUE));
+        assert!(!redacted.contains("abc"), "{redacted}");
+    }
+
+    #[test]
+    fn secrets_in_arrays_are_redacted() {
+        let redacted = redact_configuration(r#"
+            debug_tags = ["v", "auth"]
+            secrets = ["abc", "def"]
+            [[hooks]]
+            name = "first"
+            auth_header = "abc"
+            [[hooks]]
+            name = "second"
+            nested = [[{ token = "def", name = "inner" }]]
+        "#);
+        let table = redacted.parse::<Table>().unwrap();
+        // plain values in an array are only redacted along with the whole array
+        assert_eq!(table["debug_tags"].as_array().unwrap().len(), 2);
+        assert_eq!(table["secrets"].as_str(), Some(REDACTED_VALUE));
+        let hooks = table["hooks"].as_array().unwrap();
+        assert_eq!(hooks[0]["name"].as_str(), Some("first"));
+        assert_eq!(hooks[0]["auth_header"].as_str(), Some(REDACTED_VALUE));
+        assert_eq!(hooks[1]["nested"][0][0]["name"].as_str(), Some("inner"));
+        assert_eq!(hooks[1]["nested"][0][0]["token"].as_str(), Some(REDACTED_VALUE));
+        assert!(!redacted.contains("abc") && !redacted.contains("def"), "{redacted}");
+    }
+
+    #[test]
+    fn unparseable_configuration_is_left_out() {
+        let redacted = redact_configuration("startup_command = \"notify --token abc");
+        assert!(redacted.starts_with("# configuration could not be parsed"), "{redacted}");
+        assert!(!redacted.contains("abc"), "{redacted}");
+    }
+}
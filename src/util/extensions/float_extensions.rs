@@ -5,6 +5,10 @@
 /// extension functions for floating-point numbers
 pub trait FloatExtensions {
     fn filter_nan(self) -> Self;
+
+    /// Snap a value in [0,1] to the device step buttplug would pick for it, expressed again as a value in [0,1].
+    /// A `step_count` of zero leaves the value untouched.
+    fn quantize(self, step_count: u32) -> Self;
 }
 
 impl FloatExtensions for f32 {
@@ -15,6 +19,10 @@ impl FloatExtensions for f32 {
             self
         }
     }
+
+    fn quantize(self, step_count: u32) -> f32 {
+        (self as f64).quantize(step_count) as f32
+    }
 }
 
 impl FloatExtensions for f64 {
@@ -25,4 +33,61 @@ impl FloatExtensions for f64 {
             self
         }
     }
+
+    fn quantize(self, step_count: u32) -> f64 {
+        if step_count == 0 {
+            return self;
+        }
+        let step_count = step_count as f64;
+
+        // buttplug rounds up to the next step, treating anything this close to zero as off
+        let steps = self * step_count;
+        let step = if steps < 0.0001 {
+            0.0
+        } else {
+            steps.ceil()
+        };
+
+        // step / step_count can come out a hair too large, which buttplug would then round up to the following step
+        let quantized = step / step_count;
+        if quantized * step_count > step {
+            quantized.next_down()
+        } else {
+            quantized
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the step buttplug sends to a device for a given intensity
+    fn buttplug_step(value: f64, step_count: u32) -> f64 {
+        let steps = value * step_count as f64;
+        if steps < 0.0001 {
+            0.0
+        } else {
+            steps.ceil()
+        }
+    }
+
+    #[test]
+    fn quantize_matches_buttplug_step() {
+        for step_count in 1..=100u32 {
+            for i in 0..=1000 {
+                let value = i as f64 / 1000.0;
+                let quantized = value.quantize(step_count);
+                assert_eq!(buttplug_step(quantized, step_count), buttplug_step(value, step_count), "{value} with {step_count} steps");
+                assert_eq!(quantized.quantize(step_count), quantized, "{value} with {step_count} steps");
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_rounds_up_to_next_step() {
+        assert_eq!(0.26.quantize(4), 0.5);
+        assert_eq!(0.00001.quantize(4), 0.0);
+        assert_eq!(0.26.quantize(0), 0.26);
+    }
 }
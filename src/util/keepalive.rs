@@ -0,0 +1,108 @@
+// Copyright 2026 runtime-shady-backroom
+// This file is part of buttplug-lite.
+// buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
+
+//! Keeps running motors awake on devices that fall asleep when they go without commands for a while
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use buttplug::client::{ButtplugClientDevice, RotateCommand, ScalarCommand};
+use buttplug::core::message::ActuatorType;
+use tokio::task;
+use tracing::{debug, warn};
+
+use crate::ApplicationStateDb;
+use crate::app::structs::SentCommandDbs;
+use crate::util::extensions::FloatExtensions as _;
+
+/// how often to check if keepalives have been turned on
+const DISABLED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Start refreshing every running motor at the configured keepalive interval.
+///
+/// The device server ignores commands that would not change a motor's step, so simply re-sending the current values
+/// would never reach the device. Instead, each motor is nudged one step and immediately set back.
+/// Motors that are stopped are left alone, as are linear motors.
+pub fn start(application_state_db: ApplicationStateDb, sent_command_dbs: SentCommandDbs) {
+    task::spawn(async move {
+        loop {
+            let keepalive_interval = application_state_db.read().await.as_ref()
+                .and_then(|application_state| application_state.configuration.keepalive_interval());
+            let Some(keepalive_interval) = keepalive_interval else {
+                tokio::time::sleep(DISABLED_POLL_INTERVAL).await;
+                continue;
+            };
+            tokio::time::sleep(keepalive_interval).await;
+
+            let application_state_mutex = application_state_db.read().await;
+            let Some(application_state) = application_state_mutex.as_ref() else { continue };
+            for device in application_state.devices() {
+                // other sends to this device wait until its motors have been set back, so a newer command can't be overwritten
+                let _send_guard = sent_command_dbs.lock_device(device.index()).await;
+                let scalars = sent_command_dbs.running_motors.read().await.scalars(device.index());
+                keep_scalars_alive(&device, &scalars).await;
+                let rotations = sent_command_dbs.running_motors.read().await.rotations(device.index());
+                keep_rotations_alive(&device, &rotations).await;
+            }
+        }
+    });
+}
+
+async fn keep_scalars_alive(device: &ButtplugClientDevice, scalars: &HashMap<u32, (f64, ActuatorType)>) {
+    let step_count = |feature_index: u32| device.message_attributes().scalar_cmd().as_ref()
+        .and_then(|attributes| attributes.get(feature_index as usize))
+        .map_or(0, |attributes| *attributes.step_count());
+    let running: HashMap<u32, (f64, ActuatorType)> = scalars.iter()
+        .filter(|(_, (intensity, _))| *intensity > 0.0)
+        .map(|(feature_index, scalar)| (*feature_index, *scalar))
+        .collect();
+    let nudged: HashMap<u32, (f64, ActuatorType)> = running.iter()
+        .filter_map(|(feature_index, (intensity, actuator_type))| Some((*feature_index, (nudged(*intensity, step_count(*feature_index))?, *actuator_type))))
+        .collect();
+    if nudged.is_empty() {
+        return;
+    }
+    debug!("keeping {} scalar motors of {} awake", nudged.len(), device.name());
+    if let Err(e) = device.scalar(&ScalarCommand::ScalarMap(nudged)).await {
+        warn!("keepalive: error sending command to {}: {e:?}", device.name());
+    }
+    if let Err(e) = device.scalar(&ScalarCommand::ScalarMap(running)).await {
+        warn!("keepalive: error sending command to {}: {e:?}", device.name());
+    }
+}
+
+async fn keep_rotations_alive(device: &ButtplugClientDevice, rotations: &HashMap<u32, (f64, bool)>) {
+    let step_count = |feature_index: u32| device.message_attributes().rotate_cmd().as_ref()
+        .and_then(|attributes| attributes.get(feature_index as usize))
+        .map_or(0, |attributes| *attributes.step_count());
+    let running: HashMap<u32, (f64, bool)> = rotations.iter()
+        .filter(|(_, (speed, _))| *speed > 0.0)
+        .map(|(feature_index, rotation)| (*feature_index, *rotation))
+        .collect();
+    let nudged: HashMap<u32, (f64, bool)> = running.iter()
+        .filter_map(|(feature_index, (speed, clockwise))| Some((*feature_index, (nudged(*speed, step_count(*feature_index))?, *clockwise))))
+        .collect();
+    if nudged.is_empty() {
+        return;
+    }
+    debug!("keeping {} rotation motors of {} awake", nudged.len(), device.name());
+    if let Err(e) = device.rotate(&RotateCommand::RotateMap(nudged)).await {
+        warn!("keepalive: error sending command to {}: {e:?}", device.name());
+    }
+    if let Err(e) = device.rotate(&RotateCommand::RotateMap(running)).await {
+        warn!("keepalive: error sending command to {}: {e:?}", device.name());
+    }
+}
+
+/// A value one step away from `value` on a motor with `step_count` steps. Values on the lowest step are nudged up
+/// instead of down, as the step below would stop the motor. Motors with fewer than two steps can't be nudged.
+fn nudged(value: f64, step_count: u32) -> Option<f64> {
+    if step_count < 2 {
+        return None;
+    }
+    // buttplug rounds up to the next step
+    let step = (value.quantize(step_count) * step_count as f64).round() as u32;
+    let nudged_step = if step > 1 { step - 1 } else { step + 1 };
+    Some((nudged_step as f64 / step_count as f64).quantize(step_count))
+}
@@ -12,9 +12,11 @@ use directories::ProjectDirs;
 use tracing::{debug, info, warn};
 use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
 use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt as _;
 
 use crate::util;
+use crate::util::log_shipping::LogShippingLayer;
 
 const MAXIMUM_LOG_FILES: usize = 50;
 static LOG_DIR_NAME: &str = "logs";
@@ -72,6 +74,7 @@ fn init_console_logging(log_filter: EnvFilter) {
     tracing_subscriber::fmt()
         .with_env_filter(log_filter)
         .finish()
+        .with(LogShippingLayer)
         .init();
 }
 
@@ -82,6 +85,7 @@ fn init_file_logging(log_filter: EnvFilter, non_blocking: NonBlocking) {
         .with_writer(non_blocking)
         .with_env_filter(log_filter)
         .finish()
+        .with(LogShippingLayer)
         .init();
 }
 
@@ -119,16 +123,23 @@ fn get_log_filter(verbosity_level: u8, log_filter: Option<String>) -> EnvFilter
     }
 }
 
+/// A unique name for this launch's log file. The milliseconds keep names sorting by age, and the process ID keeps two
+/// launches in the same millisecond from writing to the same file.
 fn get_log_file_name() -> String {
-    //TODO: this will cause problems if you launch the program twice in the same second...
-    Local::now().format("%Y-%m-%d_%H-%M-%S.log").to_string()
+    format!("{}_{}.log", Local::now().format("%Y-%m-%d_%H-%M-%S_%3f"), std::process::id())
 }
 
-fn get_log_dir() -> PathBuf {
+/// directory application data, such as logs, is stored in
+pub fn get_data_dir() -> PathBuf {
     ProjectDirs::from("io.github", "runtime-shady-backroom", env!("CARGO_PKG_NAME"))
         .expect("unable to locate configuration directory")
         .data_dir()
-        .join(LOG_DIR_NAME)
+        .to_path_buf()
+}
+
+/// directory log files are written to when not logging to stdout
+pub fn get_log_dir() -> PathBuf {
+    get_data_dir().join(LOG_DIR_NAME)
 }
 
 fn create_log_dir_path() -> io::Result<PathBuf> {
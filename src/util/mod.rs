@@ -6,7 +6,11 @@
 
 pub use crate::util::tokio::GLOBAL_TOKIO_RUNTIME;
 
+pub mod command_hook;
+pub mod diagnostics;
 pub mod extensions;
+pub mod keepalive;
+pub mod log_shipping;
 pub mod logging;
 pub mod panic;
 pub mod slice;
@@ -3,7 +3,7 @@
 // Copyright 2018 human-panic Individual contributors
 // Copyright 2023 runtime-shady-backroom
 
-/// Handles custom panic hook and logging
+//! Handles custom panic hook and logging
 
 use std::{mem, panic, thread};
 use std::fmt::Write as _;
@@ -84,7 +84,7 @@ pub fn set_hook() {
         // A typical one-liner panic looks like this:
         // thread 'util::panic::tests::normal_panic' panicked at 'normal_panic', src\util\panic.rs:31:9
         // we'll emulate that format for our first line, but also add a backtrace
-        let thread_name = thread::current().name().map_or_else(|| "<unknown>", |s| s).to_string();
+        let thread_name = thread::current().name().unwrap_or("<unknown>").to_string();
         error!("{} v{} has crashed.\nTo help me diagnose this problem you can attach this log file to a new GitHub issue at https://github.com/runtime-shady-backroom/buttplug-lite/issues\nthread '{thread_name}' panicked at '{cause}', {location}{backtrace}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
     }));
 }
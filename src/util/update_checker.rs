@@ -5,67 +5,113 @@
 //! GitHub Releases-based update checking
 
 use std::cmp::Ordering;
-use std::time::Duration;
-use reqwest::header;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use lazy_static::lazy_static;
+use reqwest::header;
 use semver::Version;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
+use crate::config::v3::UpdateCheckV3;
+use crate::util::logging;
+
 const USER_AGENT: &str = "runtime-shady-backroom/buttplug-lite";
 const UPDATE_CHECK_URI: &str = "https://api.github.com/repos/runtime-shady-backroom/buttplug-lite/releases/latest";
 
+lazy_static! {
+    static ref UPDATE_CHECK_FILE_PATH: PathBuf = logging::get_data_dir().join("update_check.toml");
+}
+
 /// Compare the local version to the latest GitHub release. If there's a newer version available, return its URL.
-pub async fn check_for_update(local_version: Version) -> Option<String> {
+/// With a cooldown set, a result saved less than the cooldown ago is used instead of asking GitHub again.
+pub async fn check_for_update(local_version: &Version, settings: &UpdateCheckV3) -> Option<String> {
+    if let Some(cooldown) = settings.cooldown() {
+        if let Some(saved_result) = read_saved_result() {
+            if saved_result.age().is_some_and(|age| age < cooldown) {
+                info!("Using saved update check result from less than {cooldown:?} ago.");
+                return saved_result.update_url(local_version);
+            }
+        }
+    }
 
-    match get_latest_release().await {
+    match get_latest_release(settings).await {
         Ok(response) => {
             info!("Update Url: {:?}", response.html_url);
             info!("Update Version: {:?}", response.tag_name);
-            match Version::parse(&response.tag_name) {
-                Ok(remote_version) => {
-                    match remote_version.cmp(&local_version) {
-                        Ordering::Greater => {
-                            // we are behind
-                            info!("Local version is outdated.");
-                            Some(response.html_url)
-                        }
-                        Ordering::Less => {
-                            // we are NEWER than remote
-                            warn!("Local version is NEWER than remote version! If you're not beta testing a pre-release then something has gone terribly wrong.");
-                            None
-                        }
-                        Ordering::Equal => {
-                            // we are up to date
-                            info!("We are up to date.");
-                            None
-                        }
-                    }
-                }
-                Err(e) => {
-                    warn!("Error parsing remote version: {e:?}");
-                    None
-                }
+            let result = UpdateCheckResult::new(response);
+            if settings.cooldown_hours.is_some() {
+                save_result(&result);
             }
+            result.update_url(local_version)
         }
         Err(e) => {
             warn!("Failed to get latest version info: {e:?}");
+            // an update we already know about is still worth showing
+            saved_update_url(local_version, settings)
+        }
+    }
+}
+
+/// The URL of a newer version found by a previous update check, if one was saved. This never makes a request.
+pub fn saved_update_url(local_version: &Version, settings: &UpdateCheckV3) -> Option<String> {
+    settings.cooldown_hours?;
+    read_saved_result()?.update_url(local_version)
+}
+
+fn read_saved_result() -> Option<UpdateCheckResult> {
+    let text = fs::read_to_string(UPDATE_CHECK_FILE_PATH.as_path()).ok()?;
+    match toml::from_str(&text) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            warn!("Ignoring unreadable saved update check result: {e}");
             None
         }
     }
 }
 
-/// Get latest release from GitHub
-async fn get_latest_release() -> Result<GithubRelease, String> {
-    let client = reqwest::Client::builder()
+fn save_result(result: &UpdateCheckResult) {
+    let write_result = toml::to_string(result)
+        .map_err(|e| e.to_string())
+        .and_then(|text| {
+            if let Some(parent) = UPDATE_CHECK_FILE_PATH.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            fs::write(UPDATE_CHECK_FILE_PATH.as_path(), text).map_err(|e| e.to_string())
+        });
+    if let Err(e) = write_result {
+        warn!("Unable to save update check result: {e}");
+    }
+}
+
+/// Get latest release from GitHub. If starting with HTTP/2 fails, for example because a proxy only speaks HTTP/1.1, try once more with negotiation.
+async fn get_latest_release(settings: &UpdateCheckV3) -> Result<GithubRelease, String> {
+    if !settings.http2_prior_knowledge {
+        return request_latest_release(settings, false).await;
+    }
+    match request_latest_release(settings, true).await {
+        Ok(release) => Ok(release),
+        Err(e) => {
+            info!("{e}. Retrying without HTTP/2 prior knowledge.");
+            request_latest_release(settings, false).await
+        }
+    }
+}
+
+async fn request_latest_release(settings: &UpdateCheckV3, http2_prior_knowledge: bool) -> Result<GithubRelease, String> {
+    let mut client = reqwest::Client::builder()
         .gzip(true)
-        .http2_prior_knowledge()
-        .https_only(true)
+        .https_only(settings.https_only)
         .user_agent(USER_AGENT)
-        .connect_timeout(Duration::from_secs(10))
-        .timeout(Duration::from_secs(3))
-        .connection_verbose(true)
-        .build()
+        .connect_timeout(settings.connect_timeout())
+        .timeout(settings.timeout())
+        .connection_verbose(true);
+    if http2_prior_knowledge {
+        client = client.http2_prior_knowledge();
+    }
+    let client = client.build()
         .map_err(|e| format!("Failed to build HTTP client: {e}"))?;
 
     let request = client.get(UPDATE_CHECK_URI)
@@ -90,6 +136,62 @@ async fn get_latest_release() -> Result<GithubRelease, String> {
         .map_err(|e| format!("error parsing github release {} response: {}", status.as_str(), e))
 }
 
+/// The latest release seen by an update check, as saved between launches
+#[derive(Deserialize, Serialize)]
+struct UpdateCheckResult {
+    /// when the check was made, in seconds since the Unix epoch
+    checked_at: u64,
+    latest_version: String,
+    url: String,
+}
+
+impl UpdateCheckResult {
+    fn new(release: GithubRelease) -> Self {
+        let checked_at = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        UpdateCheckResult {
+            checked_at,
+            latest_version: release.tag_name,
+            url: release.html_url,
+        }
+    }
+
+    /// how long ago the check was made, or `None` if it seems to have been made in the future
+    fn age(&self) -> Option<Duration> {
+        SystemTime::now().duration_since(UNIX_EPOCH + Duration::from_secs(self.checked_at)).ok()
+    }
+
+    /// the release URL, if the release is newer than the local version
+    fn update_url(&self, local_version: &Version) -> Option<String> {
+        match Version::parse(&self.latest_version) {
+            Ok(remote_version) => {
+                match remote_version.cmp(local_version) {
+                    Ordering::Greater => {
+                        // we are behind
+                        info!("Local version is outdated.");
+                        Some(self.url.clone())
+                    }
+                    Ordering::Less => {
+                        // we are NEWER than remote
+                        warn!("Local version is NEWER than remote version! If you're not beta testing a pre-release then something has gone terribly wrong.");
+                        None
+                    }
+                    Ordering::Equal => {
+                        // we are up to date
+                        info!("We are up to date.");
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Error parsing remote version: {e:?}");
+                None
+            }
+        }
+    }
+}
+
 /// GitHub API response object
 #[derive(Deserialize)]
 struct GithubRelease {
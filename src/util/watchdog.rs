@@ -2,51 +2,166 @@
 // This file is part of buttplug-lite.
 // buttplug-lite is licensed under the AGPL-3.0 license (see LICENSE file for details).
 
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom as _;
 use std::ops::Add as _;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicI64, Ordering};
 use std::time::{Duration, UNIX_EPOCH};
 
+use buttplug::client::{RotateCommand, ScalarCommand};
+use tokio::sync::Notify;
 use tokio::task;
-use tracing::warn;
+use tracing::{error, info, warn};
 
 use crate::ApplicationStateDb;
+use crate::app::buttplug as app_buttplug;
+use crate::app::structs::ApplicationState;
+use crate::app::webserver::HapticState;
+use crate::config::v3::{ConfigurationV3, MotorConfigurationV3, MotorTypeV3};
 
-pub type WatchdogTimeoutDb = Arc<AtomicI64>;
+pub type WatchdogTimeoutDb = Arc<WatchdogTimeout>;
+
+/// When the watchdog halts devices if no command arrives first, and requests to halt them right away
+#[derive(Debug)]
+pub struct WatchdogTimeout {
+    /// the deadline as a unix time in milliseconds, or `i64::MAX` if no command has been received since devices were last halted
+    deadline: AtomicI64,
+    /// wakes the watchdog to halt devices right away. Unlike the deadline, this can't be undone by feeding the watchdog.
+    halt_requested: Notify,
+}
+
+impl Default for WatchdogTimeout {
+    fn default() -> Self {
+        WatchdogTimeout {
+            deadline: AtomicI64::new(i64::MAX),
+            halt_requested: Notify::new(),
+        }
+    }
+}
 
 // how often the watchdog runs its check
 const WATCHDOG_POLL_INTERVAL_MILLIS: u64 = 1000;
 
-// halt devices after this much time with no command received
-const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(10);
-
-pub fn start(watchdog_timeout_db: WatchdogTimeoutDb, buttplug_connector_db: ApplicationStateDb) {
+pub fn start(watchdog_timeout_db: WatchdogTimeoutDb, haptic_state: HapticState) {
+    let HapticState { application_state_db: buttplug_connector_db, tag_value_db, sent_command_dbs, motor_hold_db, motor_ramp_db, .. } = haptic_state;
     // spawn the watchdog task
     // if too much time passes with no input from the client, this halts all haptic devices
     task::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_millis(WATCHDOG_POLL_INTERVAL_MILLIS));
         loop {
-            interval.tick().await;
-            let watchdog_violation = unix_time() > watchdog_timeout_db.load(Ordering::Relaxed);
-            if watchdog_violation {
-                warn!("Watchdog violation! Halting all devices. To avoid this send an update at least every {}ms.", WATCHDOG_TIMEOUT.as_millis());
-                watchdog_timeout_db.store(i64::MAX, Ordering::Relaxed); // this prevents the message from spamming
+            let halt_requested = tokio::select! {
+                _ = interval.tick() => false,
+                _ = watchdog_timeout_db.halt_requested.notified() => true,
+            };
+            let watchdog_violation = unix_time() > watchdog_timeout_db.deadline.load(Ordering::Relaxed);
+            if halt_requested || watchdog_violation {
+                watchdog_timeout_db.deadline.store(i64::MAX, Ordering::Relaxed); // this prevents the message from spamming
                 let buttplug_connector_mutex = buttplug_connector_db.read().await;
-                if let Some(buttplug_connector) = buttplug_connector_mutex.as_ref() {
-                    match buttplug_connector.client.stop_all_devices().await {
-                        Ok(()) => (),
-                        Err(e) => warn!("watchdog: error halting devices: {e:?}")
+                if halt_requested {
+                    info!("Halting all devices, as requested");
+                } else {
+                    let watchdog_timeout = configured_timeout(buttplug_connector_mutex.as_ref());
+                    warn!("Watchdog violation! Halting all devices. To avoid this send an update at least every {}ms.", watchdog_timeout.as_millis());
+                }
+                let exempt_tags: HashSet<String> = match buttplug_connector_mutex.as_ref() {
+                    Some(buttplug_connector) => {
+                        let exempt_tags: HashSet<String> = buttplug_connector.configuration.tags().iter()
+                            .filter(|(_, motor)| motor.watchdog_exempt)
+                            .map(|(tag, _)| tag.to_owned())
+                            .collect();
+                        if exempt_tags.is_empty() {
+                            match buttplug_connector.client.stop_all_devices().await {
+                                Ok(()) => (),
+                                Err(e) => error!("watchdog: error halting devices: {e:?}")
+                            }
+                        } else {
+                            halt_non_exempt_motors(buttplug_connector).await;
+                        }
+                        exempt_tags
                     }
-                } // else, do nothing because there is no server connected
+                    None => HashSet::new() // do nothing because there is no server connected
+                };
+                drop(buttplug_connector_mutex);
+                tag_value_db.write().await.retain(|tag, _| exempt_tags.contains(tag)); // relative commands should now start from a halted state
+                sent_command_dbs.clear().await; // every scalar and rotate command now changes something
+                motor_hold_db.write().await.retain(|tag| exempt_tags.contains(tag)); // halted motors are no longer being held on
+                motor_ramp_db.write().await.retain(|tag| exempt_tags.contains(tag)); // halted motors ramp up again when restarted
+                sent_command_dbs.last_commands.write().await.clear(); // halted devices must not start back up when they reconnect
             }
         }
     });
 }
 
-/// feed the watchdog, preventing it from kicking in for WATCHDOG_TIMEOUT more time
-pub async fn feed(watchdog_timeout_db: &WatchdogTimeoutDb) {
-    watchdog_timeout_db.store(calculate_timeout(), Ordering::Relaxed);
+/// Halt every motor that has not been configured as exempt from the watchdog.
+/// Devices with no exempt motors are stopped outright. Otherwise, only their non-exempt scalar and rotation motors are
+/// stopped, as linear motors cannot be stopped individually.
+async fn halt_non_exempt_motors(application_state: &ApplicationState) {
+    for device in application_state.devices() {
+        let device_identifier = app_buttplug::id_from_device(&device, &application_state.device_manager);
+        let device_name = app_buttplug::display_name_from_device(&device, application_state);
+        let motors: Vec<&MotorConfigurationV3> = application_state.configuration.tags().values()
+            .filter(|motor| motor.device_name == device_name)
+            .filter(|motor| motor.device_identifier.is_none() || motor.device_identifier == device_identifier) // untagged identifiers come from old configs
+            .collect();
+
+        if !motors.iter().any(|motor| motor.watchdog_exempt) {
+            if let Err(e) = device.stop().await {
+                error!("watchdog: error halting {}: {e:?}", device.name());
+            }
+            continue;
+        }
+
+        let mut scalar_map = HashMap::new();
+        let mut rotate_map = HashMap::new();
+        for motor in motors.into_iter().filter(|motor| !motor.watchdog_exempt) {
+            match &motor.feature_type {
+                MotorTypeV3::Scalar { actuator_type } => {
+                    scalar_map.insert(motor.feature_index, (0.0, app_buttplug::reported_actuator_type(&device, motor.feature_index, actuator_type.to_buttplug())));
+                }
+                MotorTypeV3::Rotation => {
+                    rotate_map.insert(motor.feature_index, (0.0, true));
+                }
+                MotorTypeV3::Linear => (),
+            }
+        }
+        if !scalar_map.is_empty() {
+            if let Err(e) = device.scalar(&ScalarCommand::ScalarMap(scalar_map)).await {
+                error!("watchdog: error halting {}: {e:?}", device.name());
+            }
+        }
+        if !rotate_map.is_empty() {
+            if let Err(e) = device.rotate(&RotateCommand::RotateMap(rotate_map)).await {
+                error!("watchdog: error halting {}: {e:?}", device.name());
+            }
+        }
+    }
+}
+
+/// feed the watchdog, preventing it from kicking in until the configured timeout passes again
+pub async fn feed(watchdog_timeout_db: &WatchdogTimeoutDb, application_state_db: &ApplicationStateDb) {
+    let watchdog_timeout = configured_timeout(application_state_db.read().await.as_ref());
+    watchdog_timeout_db.deadline.store(calculate_timeout(watchdog_timeout), Ordering::Relaxed);
+}
+
+/// Make the watchdog halt devices right away, as if the timeout had just passed. Commands received in the meantime don't prevent this.
+pub fn request_halt(watchdog_timeout_db: &WatchdogTimeoutDb) {
+    watchdog_timeout_db.halt_requested.notify_one();
+}
+
+/// Restart a running watchdog's countdown with a new timeout, so a changed timeout takes effect right away.
+/// A watchdog that isn't running, because no command has been received since devices were last halted, stays that way.
+pub fn restart(watchdog_timeout_db: &WatchdogTimeoutDb, watchdog_timeout: Duration) {
+    let _ = watchdog_timeout_db.deadline.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |deadline| {
+        (deadline != i64::MAX).then(|| calculate_timeout(watchdog_timeout))
+    });
+}
+
+/// the timeout from the in-memory configuration, which may have been changed at runtime without being saved
+fn configured_timeout(application_state: Option<&ApplicationState>) -> Duration {
+    application_state
+        .map(|application_state| application_state.configuration.watchdog_timeout())
+        .unwrap_or_else(|| ConfigurationV3::default().watchdog_timeout())
 }
 
 fn unix_time_plus(plus: Duration) -> i64 {
@@ -59,8 +174,8 @@ fn unix_time_plus(plus: Duration) -> i64 {
     i64::try_from(unix_time).expect("System time out of range")
 }
 
-fn calculate_timeout() -> i64 {
-    unix_time_plus(WATCHDOG_TIMEOUT)
+fn calculate_timeout(watchdog_timeout: Duration) -> i64 {
+    unix_time_plus(watchdog_timeout)
 }
 
 fn unix_time() -> i64 {